@@ -0,0 +1,26 @@
+-- Add migration script here
+create table login_attempts
+(
+    id           INTEGER
+        primary key autoincrement,
+    username     TEXT                               not null,
+    success      BOOLEAN                            not null,
+    reason       TEXT,
+    attempted_at DATETIME default CURRENT_TIMESTAMP not null
+);
+
+create index idx_login_attempts_username
+    on login_attempts (username, attempted_at desc);
+
+create index idx_login_attempts_attempted_at
+    on login_attempts (attempted_at);
+
+create table login_lockouts
+(
+    username        TEXT
+        primary key,
+    failed_attempts INTEGER  default 0                 not null,
+    lockout_count   INTEGER  default 0                 not null,
+    locked_until    DATETIME,
+    updated_at      DATETIME default CURRENT_TIMESTAMP not null
+);
@@ -0,0 +1,17 @@
+-- Add migration script here
+alter table users
+    add must_change_password BOOLEAN default FALSE not null;
+
+create table password_history
+(
+    id            INTEGER
+        primary key autoincrement,
+    user_id       INTEGER                            not null
+        references users
+            on delete cascade,
+    password_hash TEXT                               not null,
+    created_at    DATETIME default CURRENT_TIMESTAMP not null
+);
+
+create index idx_password_history_user_id
+    on password_history (user_id, created_at desc);
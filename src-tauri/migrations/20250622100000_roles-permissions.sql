@@ -0,0 +1,68 @@
+-- Add migration script here
+create table roles
+(
+    id          INTEGER
+        primary key autoincrement,
+    name        TEXT                               not null
+        unique,
+    description TEXT,
+    is_system   BOOLEAN  default FALSE             not null,
+    created_at  DATETIME default CURRENT_TIMESTAMP not null,
+    updated_at  DATETIME default CURRENT_TIMESTAMP not null
+);
+
+create table permissions
+(
+    code        TEXT
+        primary key,
+    description TEXT not null
+);
+
+create table role_permissions
+(
+    role_id    INTEGER not null
+        references roles
+            on delete cascade,
+    permission TEXT    not null
+        references permissions (code)
+            on delete cascade,
+    primary key (role_id, permission)
+);
+
+insert into permissions (code, description)
+values ('members.view', 'View members'),
+       ('members.manage', 'Create and edit members'),
+       ('members.delete', 'Delete members'),
+       ('memberships.view', 'View memberships'),
+       ('memberships.manage', 'Sell and edit memberships'),
+       ('memberships.delete', 'Delete memberships'),
+       ('membership_types.view', 'View membership types'),
+       ('membership_types.manage', 'Create, edit and delete membership types'),
+       ('entry_logs.scan', 'Scan cards and see recent entries'),
+       ('entry_logs.view', 'Browse the full entry log'),
+       ('entry_logs.delete', 'Delete entry logs'),
+       ('analytics.view', 'View analytics'),
+       ('backup.manage', 'Run backups and list remote backups'),
+       ('backup.restore', 'Restore the database from a backup'),
+       ('settings.manage', 'Change application settings'),
+       ('users.manage', 'Manage users, roles and login lockouts');
+
+-- Default roles reproduce the previous hard-coded 'admin' / 'user' behaviour
+insert into roles (name, description, is_system)
+values ('admin', 'Full access', TRUE),
+       ('user', 'Front desk staff', TRUE);
+
+insert into role_permissions (role_id, permission)
+select r.id, p.code
+from roles r,
+     permissions p
+where r.name = 'admin';
+
+insert into role_permissions (role_id, permission)
+select r.id, p.code
+from roles r,
+     permissions p
+where r.name = 'user'
+  and p.code in ('members.view', 'members.manage', 'members.delete',
+                 'memberships.view', 'memberships.manage', 'memberships.delete',
+                 'membership_types.view', 'entry_logs.scan');
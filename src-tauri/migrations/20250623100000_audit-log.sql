@@ -0,0 +1,32 @@
+-- Add migration script here
+-- Actor columns are denormalised on purpose: audit rows must outlive the users they mention.
+create table audit_log
+(
+    id             INTEGER
+        primary key autoincrement,
+    actor_user_id  INTEGER,
+    actor_username TEXT                               not null,
+    action         TEXT                               not null,
+    entity_type    TEXT                               not null,
+    entity_id      INTEGER,
+    before_data    TEXT,
+    after_data     TEXT,
+    created_at     DATETIME default CURRENT_TIMESTAMP not null
+);
+
+create index idx_audit_log_created_at
+    on audit_log (created_at desc);
+
+create index idx_audit_log_entity
+    on audit_log (entity_type, entity_id);
+
+create index idx_audit_log_actor
+    on audit_log (actor_username);
+
+insert into permissions (code, description)
+values ('audit.view', 'View the staff audit trail');
+
+insert into role_permissions (role_id, permission)
+select id, 'audit.view'
+from roles
+where name = 'admin';
@@ -0,0 +1,25 @@
+-- Add migration script here
+alter table users
+    add totp_secret TEXT;
+
+alter table users
+    add totp_enabled BOOLEAN default FALSE not null;
+
+-- Last accepted time step, so a code can't be replayed within its window
+alter table users
+    add totp_last_used_step INTEGER;
+
+create table user_recovery_codes
+(
+    id         INTEGER
+        primary key autoincrement,
+    user_id    INTEGER                            not null
+        references users
+            on delete cascade,
+    code_hash  TEXT                               not null,
+    used_at    DATETIME,
+    created_at DATETIME default CURRENT_TIMESTAMP not null
+);
+
+create index idx_user_recovery_codes_user_id
+    on user_recovery_codes (user_id);
@@ -0,0 +1,17 @@
+-- Add migration script here
+alter table users
+    add pin_hash TEXT;
+
+-- Staff member who was at the front desk when the row was created
+alter table entry_logs
+    add operator_user_id INTEGER
+        references users
+            on delete set null;
+
+alter table memberships
+    add operator_user_id INTEGER
+        references users
+            on delete set null;
+
+create index idx_entry_logs_operator_user_id
+    on entry_logs (operator_user_id);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Application settings, one JSON encoded value per top-level field, so they
+-- are part of every database backup.
+create table settings
+(
+    key        TEXT                               not null
+        primary key,
+    value      TEXT                               not null,
+    updated_at DATETIME default CURRENT_TIMESTAMP not null
+);
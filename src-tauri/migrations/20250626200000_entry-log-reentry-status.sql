@@ -0,0 +1,75 @@
+-- Add migration script here
+-- Re-entries within the check-in policy's window are logged as
+-- 'allowed_reentry', which the entry_logs status check has to allow.
+create table entry_logs_new
+(
+    id               INTEGER
+        primary key autoincrement,
+    member_id        INTEGER
+        references members
+            on delete cascade,
+    membership_id    INTEGER
+        references memberships
+            on delete set null,
+    card_id          TEXT,
+    member_name      TEXT,
+    entry_time       DATETIME default CURRENT_TIMESTAMP not null,
+    status           TEXT                               not null,
+    notes            TEXT,
+    created_at       DATETIME default CURRENT_TIMESTAMP not null,
+    local_date       DATE,
+    operator_user_id INTEGER
+        references users
+            on delete set null,
+    check (status in (
+        'allowed',
+        'allowed_single',
+        'allowed_reentry',
+        'denied_member_not_found',
+        'denied_no_membership',
+        'denied_no_visits_left',
+        'denied_membership_expired',
+        'denied_membership_not_active_yet',
+        'denied_membership_inactive',
+        'denied_membership_suspended',
+        'denied_membership_invalid_status',
+        'denied_already_checked_in',
+        'denied_after_hours',
+        'error_updating_membership',
+        'error'
+    ))
+);
+
+insert into entry_logs_new (id, member_id, membership_id, card_id, member_name, entry_time, status,
+                            notes, created_at, local_date, operator_user_id)
+select id,
+       member_id,
+       membership_id,
+       card_id,
+       member_name,
+       entry_time,
+       status,
+       notes,
+       created_at,
+       local_date,
+       operator_user_id
+from entry_logs;
+
+drop table entry_logs;
+
+alter table entry_logs_new rename to entry_logs;
+
+create index idx_entry_log_card_id
+    on entry_logs (card_id);
+
+create index idx_entry_log_entry_time
+    on entry_logs (entry_time);
+
+create index idx_entry_log_search
+    on entry_logs (status asc, entry_time desc);
+
+create index idx_entry_log_status
+    on entry_logs (status);
+
+create index idx_entry_logs_operator_user_id
+    on entry_logs (operator_user_id);
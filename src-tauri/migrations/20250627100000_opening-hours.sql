@@ -0,0 +1,104 @@
+-- Add migration script here
+-- Regular opening hours, one row per weekday (0 = Monday). Weekdays without a
+-- row are treated as open all day.
+create table opening_hours
+(
+    weekday    INTEGER                            not null
+        primary key
+        check (weekday between 0 and 6),
+    is_closed  BOOLEAN  default FALSE             not null,
+    opens_at   TEXT,
+    closes_at  TEXT,
+    updated_at DATETIME default CURRENT_TIMESTAMP not null
+);
+
+-- Holidays and special days. Without opening times the gym is closed all day,
+-- otherwise the times replace the regular hours for that date.
+create table closures
+(
+    id         INTEGER
+        primary key autoincrement,
+    date       DATE                               not null
+        unique,
+    opens_at   TEXT,
+    closes_at  TEXT,
+    reason     TEXT,
+    created_at DATETIME default CURRENT_TIMESTAMP not null,
+    updated_at DATETIME default CURRENT_TIMESTAMP not null
+);
+
+-- Entries turned away while the gym is closed are logged as
+-- 'denied_gym_closed', which the entry_logs status check has to allow.
+create table entry_logs_new
+(
+    id               INTEGER
+        primary key autoincrement,
+    member_id        INTEGER
+        references members
+            on delete cascade,
+    membership_id    INTEGER
+        references memberships
+            on delete set null,
+    card_id          TEXT,
+    member_name      TEXT,
+    entry_time       DATETIME default CURRENT_TIMESTAMP not null,
+    status           TEXT                               not null,
+    notes            TEXT,
+    created_at       DATETIME default CURRENT_TIMESTAMP not null,
+    local_date       DATE,
+    operator_user_id INTEGER
+        references users
+            on delete set null,
+    check (status in (
+        'allowed',
+        'allowed_single',
+        'allowed_reentry',
+        'denied_member_not_found',
+        'denied_no_membership',
+        'denied_no_visits_left',
+        'denied_membership_expired',
+        'denied_membership_not_active_yet',
+        'denied_membership_inactive',
+        'denied_membership_suspended',
+        'denied_membership_invalid_status',
+        'denied_already_checked_in',
+        'denied_after_hours',
+        'denied_gym_closed',
+        'error_updating_membership',
+        'error'
+    ))
+);
+
+insert into entry_logs_new (id, member_id, membership_id, card_id, member_name, entry_time, status,
+                            notes, created_at, local_date, operator_user_id)
+select id,
+       member_id,
+       membership_id,
+       card_id,
+       member_name,
+       entry_time,
+       status,
+       notes,
+       created_at,
+       local_date,
+       operator_user_id
+from entry_logs;
+
+drop table entry_logs;
+
+alter table entry_logs_new rename to entry_logs;
+
+create index idx_entry_log_card_id
+    on entry_logs (card_id);
+
+create index idx_entry_log_entry_time
+    on entry_logs (entry_time);
+
+create index idx_entry_log_search
+    on entry_logs (status asc, entry_time desc);
+
+create index idx_entry_log_status
+    on entry_logs (status);
+
+create index idx_entry_logs_operator_user_id
+    on entry_logs (operator_user_id);
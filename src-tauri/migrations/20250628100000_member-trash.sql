@@ -0,0 +1,11 @@
+-- Add migration script here
+-- When a member was moved to the trash; purged after the retention period
+alter table members
+    add deleted_at DATETIME;
+
+update members
+set deleted_at = updated_at
+where is_deleted = TRUE;
+
+create index idx_members_deleted_at
+    on members (is_deleted, deleted_at);
@@ -0,0 +1,24 @@
+-- Add migration script here
+create table maintenance_runs
+(
+    id           INTEGER
+        primary key autoincrement,
+    started_at   DATETIME                           not null,
+    finished_at  DATETIME                           not null,
+    trigger_type TEXT                               not null,
+    -- Username, or 'system' for scheduled runs
+    triggered_by TEXT                               not null,
+    tasks        TEXT                               not null, -- JSON array of task names
+    status       TEXT                               not null,
+    report       TEXT                               not null, -- JSON
+    error_message TEXT,
+    created_at   DATETIME default CURRENT_TIMESTAMP not null,
+    check (trigger_type IN ('manual', 'scheduled')),
+    check (status IN ('healthy', 'issues_found', 'failed'))
+);
+
+create index idx_maintenance_runs_started_at
+    on maintenance_runs (started_at desc);
+
+create index idx_maintenance_runs_status
+    on maintenance_runs (status, started_at desc);
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Prices are kept as integers in the currency's minor unit (cents) so that
+-- sums don't pick up floating point rounding errors
+alter table membership_types
+    add price_minor INTEGER default 0 not null;
+
+update membership_types
+set price_minor = cast(round(price * 100) as INTEGER);
+
+alter table membership_types
+    drop column price;
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Reports and filters go by the gym-local date of an entry. Entries logged
+-- before local_date was recorded get it at startup, in the gym's timezone
+-- from the settings, which SQL can't know
+
+create index idx_entry_log_local_date
+    on entry_logs (local_date);
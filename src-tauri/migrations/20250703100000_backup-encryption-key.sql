@@ -0,0 +1,15 @@
+-- Add migration script here
+-- The key backups are encrypted with. data_key is the key itself, envelope
+-- the same key wrapped with the admin's backup passphrase, as it is written
+-- into every backup. There is at most one row.
+create table backup_encryption_key
+(
+    id         INTEGER
+        primary key
+        check (id = 1),
+    key_id     TEXT                               not null,
+    data_key   BLOB                               not null,
+    envelope   BLOB                               not null,
+    created_at DATETIME default CURRENT_TIMESTAMP not null,
+    updated_at DATETIME
+);
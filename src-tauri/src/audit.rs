@@ -0,0 +1,216 @@
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Executor, Sqlite};
+
+use crate::{
+    auth::Session,
+    db,
+    error::{AppError, Result as AppResult},
+};
+
+/// Fields that change on every write and would only add noise to a diff.
+const IGNORED_FIELDS: &[&str] = &["updated_at"];
+
+/// Fields whose values must never end up in the audit trail. A change is
+/// still recorded, but the value is masked.
+const REDACTED_FIELDS: &[&str] = &["password", "password_hash", "backup_store"];
+const REDACTED_VALUE: &str = "***";
+
+/// Who performed an audited action. Background tasks run as `system`.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub user_id: Option<i64>,
+    pub username: String,
+}
+
+impl Actor {
+    pub fn system() -> Self {
+        Self {
+            user_id: None,
+            username: "system".to_string(),
+        }
+    }
+}
+
+impl From<&Session> for Actor {
+    fn from(session: &Session) -> Self {
+        Self {
+            user_id: Some(session.user_id),
+            username: session.username.clone(),
+        }
+    }
+}
+
+/// Serializes an entity for the audit trail.
+pub fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+    match serde_json::to_value(value) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!("Failed to serialize audit snapshot: {}", e);
+            None
+        }
+    }
+}
+
+fn redact(mut value: Value) -> Value {
+    if let Value::Object(map) = &mut value {
+        for field in REDACTED_FIELDS {
+            if let Some(v) = map.get_mut(*field) {
+                if !v.is_null() {
+                    *v = Value::String(REDACTED_VALUE.to_string());
+                }
+            }
+        }
+    }
+    value
+}
+
+/// Reduces two object snapshots to the fields that actually changed. Anything
+/// that isn't a pair of objects is kept as-is.
+fn diff(before: Option<Value>, after: Option<Value>) -> (Option<Value>, Option<Value>) {
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let mut changed_before = Map::new();
+            let mut changed_after = Map::new();
+            for (key, old) in &before {
+                if IGNORED_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let new = after.get(key).unwrap_or(&Value::Null);
+                if old != new {
+                    changed_before.insert(key.clone(), old.clone());
+                    changed_after.insert(key.clone(), new.clone());
+                }
+            }
+            for (key, new) in &after {
+                if !before.contains_key(key) && !IGNORED_FIELDS.contains(&key.as_str()) {
+                    changed_before.insert(key.clone(), Value::Null);
+                    changed_after.insert(key.clone(), new.clone());
+                }
+            }
+            (
+                Some(Value::Object(changed_before)),
+                Some(Value::Object(changed_after)),
+            )
+        }
+        other => other,
+    }
+}
+
+/// Appends a row to the audit trail. Pass the same transaction as the change
+/// being audited so both are committed or rolled back together.
+pub async fn record<'e, E>(
+    executor: E,
+    actor: &Actor,
+    action: &str,
+    entity_type: &str,
+    entity_id: Option<i64>,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> AppResult<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let (before, after) = diff(before.map(redact), after.map(redact));
+    let now = chrono::Utc::now().naive_utc();
+
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (actor_user_id, actor_username, action, entity_type, entity_id, before_data, after_data, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(actor.user_id)
+    .bind(&actor.username)
+    .bind(action)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(before.map(sqlx::types::Json))
+    .bind(after.map(sqlx::types::Json))
+    .bind(now)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Copies audit rows from the database that is about to be replaced into the
+/// freshly restored one, so restoring an older backup doesn't erase the trail
+/// of what happened since, then records the restore itself. The restored file
+/// is migrated first because it may predate the `audit_log` table.
+pub async fn carry_over(
+    restored_db: &Path,
+    previous_db: &Path,
+    actor: &Actor,
+    restore_details: Value,
+) -> AppResult<u64> {
+    let previous_db_str = previous_db.to_str().ok_or_else(|| {
+        AppError::Config(format!(
+            "Failed to convert database path to string: {:?}",
+            previous_db
+        ))
+    })?;
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(restored_db)
+        .create_if_missing(false)
+        .connect()
+        .await?;
+
+    db::MIGRATOR.run(&mut conn).await?;
+
+    sqlx::query("ATTACH DATABASE ? AS previous")
+        .bind(previous_db_str)
+        .execute(&mut conn)
+        .await?;
+
+    let has_audit_log: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM previous.sqlite_master WHERE type = 'table' AND name = 'audit_log')",
+    )
+    .fetch_one(&mut conn)
+    .await?;
+
+    let copied = if has_audit_log {
+        sqlx::query(
+            r#"
+            INSERT INTO main.audit_log (actor_user_id, actor_username, action, entity_type, entity_id, before_data, after_data, created_at)
+            SELECT p.actor_user_id, p.actor_username, p.action, p.entity_type, p.entity_id, p.before_data, p.after_data, p.created_at
+            FROM previous.audit_log p
+            WHERE NOT EXISTS (
+                SELECT 1 FROM main.audit_log a
+                WHERE a.created_at = p.created_at
+                  AND a.action = p.action
+                  AND a.entity_type = p.entity_type
+                  AND a.entity_id IS p.entity_id
+                  AND a.actor_username = p.actor_username
+            )
+            ORDER BY p.id
+            "#,
+        )
+        .execute(&mut conn)
+        .await?
+        .rows_affected()
+    } else {
+        0
+    };
+
+    sqlx::query("DETACH DATABASE previous")
+        .execute(&mut conn)
+        .await?;
+
+    record(
+        &mut conn,
+        actor,
+        "restore",
+        "database",
+        None,
+        None,
+        Some(restore_details),
+    )
+    .await?;
+
+    Ok(copied)
+}
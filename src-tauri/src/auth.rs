@@ -0,0 +1,763 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::{
+    config::LoginLockoutSettings,
+    error::{AppError, ErrorCodes, Result as AppResult, TranslatableError},
+    state::AppState,
+    totp, utils,
+};
+
+const SESSION_IDLE_TIMEOUT_MINUTES: u64 = 30;
+/// Time allowed between a correct password and the second factor.
+const TWO_FACTOR_CHALLENGE_MINUTES: u64 = 5;
+const OPERATOR_PIN_MIN_LENGTH: usize = 4;
+const OPERATOR_PIN_MAX_LENGTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Permission {
+    #[serde(rename = "members.view")]
+    MembersView,
+    #[serde(rename = "members.manage")]
+    MembersManage,
+    #[serde(rename = "members.delete")]
+    MembersDelete,
+    #[serde(rename = "memberships.view")]
+    MembershipsView,
+    #[serde(rename = "memberships.manage")]
+    MembershipsManage,
+    #[serde(rename = "memberships.delete")]
+    MembershipsDelete,
+    #[serde(rename = "membership_types.view")]
+    MembershipTypesView,
+    #[serde(rename = "membership_types.manage")]
+    MembershipTypesManage,
+    #[serde(rename = "entry_logs.scan")]
+    EntryLogsScan,
+    #[serde(rename = "entry_logs.view")]
+    EntryLogsView,
+    #[serde(rename = "entry_logs.delete")]
+    EntryLogsDelete,
+    #[serde(rename = "analytics.view")]
+    AnalyticsView,
+    #[serde(rename = "backup.manage")]
+    BackupManage,
+    #[serde(rename = "backup.restore")]
+    BackupRestore,
+    #[serde(rename = "settings.manage")]
+    SettingsManage,
+    #[serde(rename = "users.manage")]
+    UsersManage,
+    #[serde(rename = "audit.view")]
+    AuditView,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 17] = [
+        Permission::MembersView,
+        Permission::MembersManage,
+        Permission::MembersDelete,
+        Permission::MembershipsView,
+        Permission::MembershipsManage,
+        Permission::MembershipsDelete,
+        Permission::MembershipTypesView,
+        Permission::MembershipTypesManage,
+        Permission::EntryLogsScan,
+        Permission::EntryLogsView,
+        Permission::EntryLogsDelete,
+        Permission::AnalyticsView,
+        Permission::BackupManage,
+        Permission::BackupRestore,
+        Permission::SettingsManage,
+        Permission::UsersManage,
+        Permission::AuditView,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::MembersView => "members.view",
+            Permission::MembersManage => "members.manage",
+            Permission::MembersDelete => "members.delete",
+            Permission::MembershipsView => "memberships.view",
+            Permission::MembershipsManage => "memberships.manage",
+            Permission::MembershipsDelete => "memberships.delete",
+            Permission::MembershipTypesView => "membership_types.view",
+            Permission::MembershipTypesManage => "membership_types.manage",
+            Permission::EntryLogsScan => "entry_logs.scan",
+            Permission::EntryLogsView => "entry_logs.view",
+            Permission::EntryLogsDelete => "entry_logs.delete",
+            Permission::AnalyticsView => "analytics.view",
+            Permission::BackupManage => "backup.manage",
+            Permission::BackupRestore => "backup.restore",
+            Permission::SettingsManage => "settings.manage",
+            Permission::UsersManage => "users.manage",
+            Permission::AuditView => "audit.view",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        Permission::ALL.into_iter().find(|p| p.as_str() == code)
+    }
+
+    /// Permissions that come along with this one. Being able to change or
+    /// delete something implies being able to look at it.
+    fn implies(&self) -> &'static [Permission] {
+        match self {
+            Permission::MembersManage | Permission::MembersDelete => &[Permission::MembersView],
+            Permission::MembershipsManage | Permission::MembershipsDelete => {
+                &[Permission::MembershipsView]
+            }
+            Permission::MembershipTypesManage => &[Permission::MembershipTypesView],
+            Permission::EntryLogsDelete => &[Permission::EntryLogsView],
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub role: String,
+    pub permissions: HashSet<Permission>,
+    pub must_change_password: bool,
+    pub started_at: chrono::NaiveDateTime,
+    last_activity: Instant,
+}
+
+impl Session {
+    fn is_idle_expired(&self) -> bool {
+        self.last_activity.elapsed() > Duration::from_secs(SESSION_IDLE_TIMEOUT_MINUTES * 60)
+    }
+
+    pub fn has(&self, required: Permission) -> bool {
+        self.permissions
+            .iter()
+            .any(|p| *p == required || p.implies().contains(&required))
+    }
+}
+
+/// Loads the permissions granted to `role`. Unknown permission codes are
+/// skipped so a downgrade can't lock everyone out.
+pub async fn load_role_permissions(
+    pool: &SqlitePool,
+    role: &str,
+) -> AppResult<Option<HashSet<Permission>>> {
+    let role_id: Option<i64> = sqlx::query_scalar("SELECT id FROM roles WHERE name = ?")
+        .bind(role)
+        .fetch_optional(pool)
+        .await?;
+    let role_id = match role_id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    let codes: Vec<String> =
+        sqlx::query_scalar("SELECT permission FROM role_permissions WHERE role_id = ?")
+            .bind(role_id)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(Some(
+        codes
+            .iter()
+            .filter_map(|code| {
+                let permission = Permission::parse(code);
+                if permission.is_none() {
+                    tracing::warn!("Ignoring unknown permission '{}' on role '{}'", code, role);
+                }
+                permission
+            })
+            .collect(),
+    ))
+}
+
+/// Replaces any existing session with a fresh one for the given user.
+pub async fn start_session(
+    state: &AppState,
+    user_id: i64,
+    username: &str,
+    role: &str,
+    permissions: HashSet<Permission>,
+    must_change_password: bool,
+) -> Session {
+    let session = Session {
+        user_id,
+        username: username.to_string(),
+        role: role.to_string(),
+        permissions,
+        must_change_password,
+        started_at: chrono::Utc::now().naive_utc(),
+        last_activity: Instant::now(),
+    };
+    *state.session.write().await = Some(session.clone());
+    set_active_operator(state, user_id, username).await;
+    tracing::info!("Session started for user '{}' ({})", username, role);
+    session
+}
+
+pub async fn end_session(state: &AppState) {
+    *state.active_operator.write().await = None;
+    if let Some(session) = state.session.write().await.take() {
+        tracing::info!("Session ended for user '{}'", session.username);
+    }
+}
+
+/// Applies edited role permissions to the live session, so an admin does not
+/// have to log out to see the change.
+pub async fn refresh_session_permissions(
+    state: &AppState,
+    role: &str,
+    permissions: &HashSet<Permission>,
+) {
+    if let Some(session) = state.session.write().await.as_mut() {
+        if session.role == role {
+            session.permissions = permissions.clone();
+        }
+    }
+}
+
+/// Checks that there is a live session that holds `required`.
+/// Refreshes the idle timer on success.
+pub async fn require_permission(state: &AppState, required: Permission) -> AppResult<Session> {
+    check_session(state, Some(required), false).await
+}
+
+/// Checks that there is a live session, whatever its permissions. Used for
+/// actions users take on their own account.
+pub async fn require_session(state: &AppState) -> AppResult<Session> {
+    check_session(state, None, false).await
+}
+
+/// Accepts any live session, including one that still has to rotate its
+/// password, so the password change itself can go through.
+pub async fn require_session_for_password_change(state: &AppState) -> AppResult<Session> {
+    check_session(state, None, true).await
+}
+
+/// Clears the pending password change on the current session once `user_id`
+/// has picked a new password.
+pub async fn mark_password_changed(state: &AppState, user_id: i64) {
+    if let Some(session) = state.session.write().await.as_mut() {
+        if session.user_id == user_id {
+            session.must_change_password = false;
+        }
+    }
+}
+
+async fn check_session(
+    state: &AppState,
+    required: Option<Permission>,
+    allow_pending_password_change: bool,
+) -> AppResult<Session> {
+    let mut session_guard = state.session.write().await;
+
+    let session = match session_guard.as_mut() {
+        Some(session) => session,
+        None => {
+            return Err(AppError::Translatable(TranslatableError::new(
+                ErrorCodes::NOT_AUTHENTICATED,
+                "You must be logged in to perform this action.",
+            )));
+        }
+    };
+
+    if session.is_idle_expired() {
+        tracing::warn!(
+            "Session for user '{}' expired after {} minutes of inactivity.",
+            session.username,
+            SESSION_IDLE_TIMEOUT_MINUTES
+        );
+        *session_guard = None;
+        return Err(AppError::Translatable(TranslatableError::new(
+            ErrorCodes::SESSION_EXPIRED,
+            "Your session has expired. Please log in again.",
+        )));
+    }
+
+    if let Some(required) = required {
+        if !session.has(required) {
+            tracing::warn!(
+                "User '{}' ({}) attempted an action requiring '{}'.",
+                session.username,
+                session.role,
+                required.as_str()
+            );
+            return Err(AppError::Translatable(TranslatableError::with_params(
+                ErrorCodes::PERMISSION_DENIED,
+                serde_json::json!({ "permission": required.as_str() }),
+                "You do not have permission to perform this action.",
+            )));
+        }
+    }
+
+    if session.must_change_password && !allow_pending_password_change {
+        return Err(AppError::Translatable(TranslatableError::new(
+            ErrorCodes::PASSWORD_CHANGE_REQUIRED,
+            "You must change your password before continuing.",
+        )));
+    }
+
+    session.last_activity = Instant::now();
+    Ok(session.clone())
+}
+
+/// Staff member currently working the front desk. Starts out as the logged-in
+/// user and changes when someone switches in with their PIN; the session and
+/// its permissions stay the same.
+#[derive(Debug, Clone, Serialize)]
+pub struct Operator {
+    pub user_id: i64,
+    pub username: String,
+}
+
+pub async fn set_active_operator(state: &AppState, user_id: i64, username: &str) {
+    *state.active_operator.write().await = Some(Operator {
+        user_id,
+        username: username.to_string(),
+    });
+}
+
+pub async fn get_active_operator(state: &AppState) -> Option<Operator> {
+    state.active_operator.read().await.clone()
+}
+
+/// User id to attribute new entries and sales to.
+pub async fn active_operator_id(state: &AppState) -> Option<i64> {
+    state
+        .active_operator
+        .read()
+        .await
+        .as_ref()
+        .map(|operator| operator.user_id)
+}
+
+/// Operator PINs are short digit-only codes, typed on the front desk keypad.
+pub fn validate_operator_pin(pin: &str) -> AppResult<()> {
+    let valid_length = (OPERATOR_PIN_MIN_LENGTH..=OPERATOR_PIN_MAX_LENGTH).contains(&pin.len());
+    if !valid_length || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::INVALID_OPERATOR_PIN_FORMAT,
+            serde_json::json!({
+                "min_length": OPERATOR_PIN_MIN_LENGTH,
+                "max_length": OPERATOR_PIN_MAX_LENGTH,
+            }),
+            "The PIN must be a number between 4 and 8 digits long.",
+        )));
+    }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct OperatorCredentials {
+    id: i64,
+    username: String,
+    pin_hash: Option<String>,
+}
+
+fn invalid_operator_pin() -> AppError {
+    AppError::Translatable(TranslatableError::new(
+        ErrorCodes::INVALID_OPERATOR_PIN,
+        "Invalid operator or PIN",
+    ))
+}
+
+/// Checks the PIN `username` typed to switch in at the front desk. Wrong PINs
+/// count towards the same lockout as failed logins.
+pub async fn verify_operator_pin(
+    pool: &SqlitePool,
+    username: &str,
+    pin: &str,
+    lockout_policy: &LoginLockoutSettings,
+) -> AppResult<Operator> {
+    if let Some(locked_until) = get_active_lockout(pool, username).await? {
+        record_login_attempt(pool, username, false, "locked_out").await?;
+        return Err(AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::ACCOUNT_LOCKED,
+            serde_json::json!({ "locked_until": locked_until }),
+            "Too many failed login attempts. Account is temporarily locked.",
+        )));
+    }
+
+    let credentials = sqlx::query_as::<_, OperatorCredentials>(
+        "SELECT id, username, pin_hash FROM users WHERE username = ?",
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+
+    let operator = match credentials {
+        Some(OperatorCredentials {
+            id,
+            username,
+            pin_hash: Some(pin_hash),
+        }) if utils::verify_password(pin.trim(), &pin_hash)? => Operator {
+            user_id: id,
+            username,
+        },
+        _ => {
+            tracing::warn!("Operator switch to '{}' rejected.", username);
+            register_failed_login(pool, username, lockout_policy).await?;
+            record_login_attempt(pool, username, false, "invalid_pin").await?;
+            return Err(invalid_operator_pin());
+        }
+    };
+
+    clear_failed_logins(pool, &operator.username).await?;
+    record_login_attempt(pool, &operator.username, true, "operator_switch").await?;
+    Ok(operator)
+}
+
+/// A login that passed the password check and now waits for a TOTP or
+/// recovery code.
+#[derive(Debug, Clone)]
+pub struct PendingTwoFactor {
+    pub user_id: i64,
+    pub username: String,
+    started: Instant,
+}
+
+pub async fn begin_two_factor(state: &AppState, user_id: i64, username: &str) {
+    *state.pending_two_factor.write().await = Some(PendingTwoFactor {
+        user_id,
+        username: username.to_string(),
+        started: Instant::now(),
+    });
+}
+
+/// Returns the pending two-factor login, dropping it once it has expired.
+pub async fn get_pending_two_factor(state: &AppState) -> AppResult<PendingTwoFactor> {
+    let mut pending_guard = state.pending_two_factor.write().await;
+    let pending = match pending_guard.as_ref() {
+        Some(pending)
+            if pending.started.elapsed()
+                <= Duration::from_secs(TWO_FACTOR_CHALLENGE_MINUTES * 60) =>
+        {
+            pending.clone()
+        }
+        _ => {
+            *pending_guard = None;
+            return Err(AppError::Translatable(TranslatableError::new(
+                ErrorCodes::TWO_FACTOR_NOT_PENDING,
+                "No login is waiting for a two-factor code. Please log in again.",
+            )));
+        }
+    };
+    Ok(pending)
+}
+
+pub async fn clear_pending_two_factor(state: &AppState) {
+    *state.pending_two_factor.write().await = None;
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct TwoFactorState {
+    totp_secret: Option<String>,
+    totp_enabled: bool,
+    totp_last_used_step: Option<i64>,
+}
+
+/// How a second factor was satisfied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondFactor {
+    Totp,
+    RecoveryCode,
+}
+
+impl SecondFactor {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecondFactor::Totp => "totp",
+            SecondFactor::RecoveryCode => "recovery_code",
+        }
+    }
+}
+
+fn invalid_two_factor_code() -> AppError {
+    AppError::Translatable(TranslatableError::new(
+        ErrorCodes::INVALID_TWO_FACTOR_CODE,
+        "Invalid two-factor code",
+    ))
+}
+
+/// Verifies a TOTP code, or failing that a recovery code, for a user with
+/// two-factor enabled. Accepted TOTP steps and recovery codes are burned so
+/// neither can be used twice. Returns `None` when the code is wrong.
+pub async fn verify_second_factor(
+    pool: &SqlitePool,
+    user_id: i64,
+    code: &str,
+    unix_time: u64,
+) -> AppResult<Option<SecondFactor>> {
+    let two_factor: Option<TwoFactorState> = sqlx::query_as(
+        "SELECT totp_secret, totp_enabled, totp_last_used_step FROM users WHERE id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    let (secret, last_used_step) = match two_factor {
+        Some(TwoFactorState {
+            totp_secret: Some(secret),
+            totp_enabled: true,
+            totp_last_used_step,
+        }) => (secret, totp_last_used_step),
+        _ => {
+            return Err(AppError::Translatable(TranslatableError::new(
+                ErrorCodes::TWO_FACTOR_NOT_ENABLED,
+                "Two-factor authentication is not enabled for this account",
+            )))
+        }
+    };
+
+    if let Some(step) = totp::verify(&secret, code, unix_time)? {
+        if last_used_step.is_some_and(|last| step as i64 <= last) {
+            tracing::warn!("Rejected replayed TOTP code for user {}.", user_id);
+            return Ok(None);
+        }
+        sqlx::query("UPDATE users SET totp_last_used_step = ? WHERE id = ?")
+            .bind(step as i64)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        return Ok(Some(SecondFactor::Totp));
+    }
+
+    let recovery_code = totp::normalize_recovery_code(code);
+    if recovery_code.len() <= totp::DIGITS as usize {
+        return Ok(None);
+    }
+    let unused: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, code_hash FROM user_recovery_codes WHERE user_id = ? AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    for (id, hash) in unused {
+        if utils::verify_password(&recovery_code, &hash)? {
+            sqlx::query("UPDATE user_recovery_codes SET used_at = ? WHERE id = ?")
+                .bind(Utc::now().naive_utc())
+                .bind(id)
+                .execute(pool)
+                .await?;
+            tracing::warn!("User {} logged in with a recovery code.", user_id);
+            return Ok(Some(SecondFactor::RecoveryCode));
+        }
+    }
+    Ok(None)
+}
+
+/// Like [`verify_second_factor`], but a wrong code is an error.
+pub async fn require_second_factor(
+    pool: &SqlitePool,
+    user_id: i64,
+    code: &str,
+) -> AppResult<SecondFactor> {
+    verify_second_factor(pool, user_id, code, Utc::now().timestamp() as u64)
+        .await?
+        .ok_or_else(invalid_two_factor_code)
+}
+
+/// Replaces all recovery codes of `user_id` and returns the new plain-text
+/// codes. They are only stored hashed, so this is the one chance to show them.
+pub async fn replace_recovery_codes(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: i64,
+) -> AppResult<Vec<String>> {
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let codes = totp::generate_recovery_codes();
+    let now = Utc::now().naive_utc();
+    for code in &codes {
+        let hash = utils::hash_password(&totp::normalize_recovery_code(code))?;
+        sqlx::query(
+            "INSERT INTO user_recovery_codes (user_id, code_hash, created_at) VALUES (?, ?, ?)",
+        )
+        .bind(user_id)
+        .bind(hash)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(codes)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct LoginLockout {
+    failed_attempts: i64,
+    lockout_count: i64,
+}
+
+/// Length of the `lockout_count`-th consecutive lockout: the base duration
+/// doubled for every previous lockout, capped at the configured maximum.
+fn lockout_duration(policy: &LoginLockoutSettings, lockout_count: i64) -> chrono::Duration {
+    let exponent = (lockout_count.max(1) - 1).min(32) as u32;
+    let minutes = policy
+        .base_lockout_minutes
+        .saturating_mul(1u64 << exponent)
+        .min(policy.max_lockout_minutes);
+    chrono::Duration::minutes(minutes as i64)
+}
+
+/// Returns the time until which `username` is locked out, if a lockout is active.
+pub async fn get_active_lockout(
+    pool: &SqlitePool,
+    username: &str,
+) -> AppResult<Option<NaiveDateTime>> {
+    let now = Utc::now().naive_utc();
+    let locked_until: Option<Option<NaiveDateTime>> =
+        sqlx::query_scalar("SELECT locked_until FROM login_lockouts WHERE username = ?")
+            .bind(username)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(locked_until.flatten().filter(|until| *until > now))
+}
+
+/// Counts a failed attempt for `username` and locks the account once the
+/// configured threshold is reached. Returns the new lockout end, if any.
+pub async fn register_failed_login(
+    pool: &SqlitePool,
+    username: &str,
+    policy: &LoginLockoutSettings,
+) -> AppResult<Option<NaiveDateTime>> {
+    let now = Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+
+    let lockout: Option<LoginLockout> = sqlx::query_as(
+        "SELECT failed_attempts, lockout_count FROM login_lockouts WHERE username = ?",
+    )
+    .bind(username)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (mut failed_attempts, mut lockout_count) = lockout
+        .map(|l| (l.failed_attempts, l.lockout_count))
+        .unwrap_or((0, 0));
+    failed_attempts += 1;
+
+    let mut locked_until = None;
+    if failed_attempts >= policy.max_failed_attempts.max(1) as i64 {
+        lockout_count += 1;
+        failed_attempts = 0;
+        locked_until = Some(now + lockout_duration(policy, lockout_count));
+        tracing::warn!(
+            "Account '{}' locked until {:?} after too many failed login attempts.",
+            username,
+            locked_until
+        );
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO login_lockouts (username, failed_attempts, lockout_count, locked_until, updated_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(username) DO UPDATE SET
+            failed_attempts = excluded.failed_attempts,
+            lockout_count = excluded.lockout_count,
+            locked_until = excluded.locked_until,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(username)
+    .bind(failed_attempts)
+    .bind(lockout_count)
+    .bind(locked_until)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(locked_until)
+}
+
+/// Resets the failed attempt counter and any lockout for `username`.
+pub async fn clear_failed_logins(pool: &SqlitePool, username: &str) -> AppResult<bool> {
+    let result = sqlx::query("DELETE FROM login_lockouts WHERE username = ?")
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn record_login_attempt(
+    pool: &SqlitePool,
+    username: &str,
+    success: bool,
+    reason: &str,
+) -> AppResult<()> {
+    sqlx::query(
+        "INSERT INTO login_attempts (username, success, reason, attempted_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(username)
+    .bind(success)
+    .bind(reason)
+    .bind(Utc::now().naive_utc())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Rejects `new_password` if it matches the user's current password or any of
+/// the last `history_size` passwords.
+pub async fn ensure_password_not_reused(
+    pool: &SqlitePool,
+    user_id: i64,
+    new_password: &str,
+    history_size: u32,
+) -> AppResult<()> {
+    if history_size == 0 {
+        return Ok(());
+    }
+
+    // The current hash is checked too, for accounts created before history was kept.
+    let mut previous_hashes: Vec<String> =
+        sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+    let history: Vec<String> = sqlx::query_scalar(
+        "SELECT password_hash FROM password_history WHERE user_id = ? ORDER BY created_at DESC, id DESC LIMIT ?",
+    )
+    .bind(user_id)
+    .bind(history_size as i64)
+    .fetch_all(pool)
+    .await?;
+    previous_hashes.extend(history);
+
+    for hash in &previous_hashes {
+        if utils::verify_password(new_password, hash)? {
+            return Err(AppError::Translatable(TranslatableError::with_params(
+                ErrorCodes::PASSWORD_REUSED,
+                serde_json::json!({ "history_size": history_size }),
+                "New password must differ from recently used passwords",
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub async fn record_password_history<'e, E>(
+    executor: E,
+    user_id: i64,
+    password_hash: &str,
+) -> AppResult<()>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO password_history (user_id, password_hash, created_at) VALUES (?, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(password_hash)
+    .bind(Utc::now().naive_utc())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
@@ -1,132 +1,106 @@
-use crate::config::parse_backup_url;
-use crate::db::get_database_path;
+use crate::audit::{self, Actor};
+use crate::auth::{self, Permission};
+use crate::config;
+use crate::db::sibling_path;
+use crate::dto::AppSettingsView;
 use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
 use crate::models::CronCheck;
+use crate::services::backup;
+use crate::services::backup_encryption::{self, BackupSecret};
+use crate::services::backup_store::{self, DirectoryStore, ProgressReporter};
+use crate::utils;
 use crate::AppState;
-use base64::engine::general_purpose;
-use base64::Engine;
-use chrono::Local;
-use reqwest::header::CONTENT_TYPE;
-use sqlx::pool::PoolConnection;
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::{ConnectOptions, Sqlite};
-use std::path::PathBuf;
 use std::time::Duration;
 use tauri::{Emitter, Manager};
 use tokio::time::interval;
 
 const BACKUP_CHECK_INTERVAL_MINUTES: u64 = 30;
 
-async fn execute_vacuum(
-    mut conn: PoolConnection<Sqlite>,
-    backup_path: &str,
-) -> Result<(), AppError> {
-    let query_string = format!("VACUUM INTO '{}'", backup_path);
-
-    sqlx::query(&query_string)
-        .execute(&mut *conn)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to execute VACUUM command: {}", e);
-            AppError::Sqlx(e)
-        })?;
-
-    Ok(())
+/// Emits the progress of backup uploads and downloads to the UI as
+/// `backup_progress` events.
+fn progress_events(app_handle: &tauri::AppHandle) -> ProgressReporter {
+    let app_handle = app_handle.clone();
+    ProgressReporter::new(move |progress| {
+        if let Err(e) = app_handle.emit("backup_progress", progress) {
+            tracing::warn!("Failed to emit backup progress: {}", e);
+        }
+    })
 }
 
+/// Backs the database up to the configured backup store and the local
+/// folder, whichever are configured. One snapshot serves both.
 async fn perform_backup(app_handle: &tauri::AppHandle) -> AppResult<()> {
     tracing::info!("Starting database backup process...");
 
     let app_state = app_handle.state::<AppState>();
-    let backup_url = app_state.settings.read().await.backup_url.clone();
-    let gym_code = app_state.settings.read().await.gym_code.clone();
-
-    if backup_url.is_none() {
-        tracing::warn!("Backup URL is not configured, skipping backup.");
-        return Err(AppError::BackupFailed(
-            "Backup URL not configured".to_string(),
-        ));
-    }
-    let url_data = parse_backup_url(backup_url.unwrap().as_str());
-    if url_data.is_err() {
-        tracing::error!("Invalid backup URL format: {}", url_data.unwrap_err());
-        return Err(AppError::BackupFailed(
-            "Invalid backup URL format".to_string(),
-        ));
+    let settings = app_state.settings.read().await.clone();
+    let store = backup_store::from_settings(&settings)?;
+    let local = DirectoryStore::local_backup(&settings);
+    if store.is_none() && local.is_none() {
+        return Err(AppError::Translatable(TranslatableError::new(
+            ErrorCodes::BACKUP_NOT_CONFIGURED,
+            "Neither a backup store nor a local backup folder is configured.",
+        )));
     }
-    let (backup_url, token) = url_data.unwrap();
 
-    let db_path = get_database_path(app_handle)?;
-    let backup_path = db_path.join("backup.tmp.sqlite");
-    let backup_path_str = backup_path.to_str().ok_or_else(|| {
-        AppError::Config(format!(
-            "Failed to convert backup path to string: {:?}",
-            backup_path
-        ))
-    })?;
+    // Backups leave the machine only encrypted. Copies in the local folder
+    // are encrypted too once a passphrase is set.
+    let key = backup_encryption::load_key(&app_state.db_pool()).await?;
+    if store.is_some() && key.is_none() {
+        return Err(AppError::Translatable(TranslatableError::new(
+            ErrorCodes::BACKUP_ENCRYPTION_NOT_SET,
+            "Set a backup passphrase before backing up to a backup store.",
+        )));
+    }
 
+    // Next to the open database, which may be a profile's or have been moved
+    // away from the app data folder.
+    let db_path = app_state.database_path.read().await.clone();
+    let snapshot_path = sibling_path(&db_path, ".backup.tmp.sqlite");
+    let backup_path = sibling_path(&db_path, ".backup.tmp.pack");
     tracing::info!("Creating temporary backup file at: {:?}", backup_path);
-
-    let conn = app_state.db_pool.acquire().await.map_err(|e| {
-        tracing::error!("Failed to acquire database connection: {}", e);
-        AppError::Sqlx(e)
-    })?;
-
-    tracing::info!("Executing VACUUM INTO temporary file: {}", backup_path_str);
-    execute_vacuum(conn, backup_path_str).await?;
-
-    let db_file_bytes = tokio::fs::read(&backup_path).await.map_err(|e| {
-        tracing::error!("Failed to read database file: {}", e);
-        AppError::Io(e)
-    })?;
-
-    tokio::fs::remove_file(&backup_path).await.map_err(|e| {
+    let manifest = backup::prepare_backup(
+        &app_state.db_pool(),
+        &snapshot_path,
+        &backup_path,
+        key.as_ref(),
+        &app_state.gym_clock().await?,
+    )
+    .await?;
+    let taken_at = manifest.created_at.naive_local();
+    let progress = progress_events(app_handle);
+
+    // A failing target doesn't keep the other one from getting its copy.
+    let mut result = Ok(());
+    if let Some(local) = &local {
+        if let Err(e) = backup::upload_verified(local, &backup_path, taken_at, &progress).await {
+            tracing::error!("Local backup failed: {:?}", e);
+            result = Err(e);
+        }
+    }
+    if let Some(store) = &store {
+        if let Err(e) =
+            backup::upload_verified(store.as_ref(), &backup_path, taken_at, &progress).await
+        {
+            tracing::error!("Backup to {:?} failed: {:?}", store, e);
+            result = result.and(Err(e));
+        }
+    }
+    if let Err(e) = tokio::fs::remove_file(&backup_path).await {
         tracing::error!("Failed to remove temporary backup file: {}", e);
-        AppError::Io(e)
-    })?;
-
-    let client = reqwest::Client::new();
-    let upload_endpoint = format!("{}/backup", backup_url);
-
-    let res = client
-        .post(&upload_endpoint)
-        .header(CONTENT_TYPE, "application/octet-stream")
-        .header("X-Api-Key", token)
-        .header("X-Gym-Code", gym_code)
-        .body(db_file_bytes)
-        .send()
-        .await;
+    }
 
-    match res {
-        Ok(response) => {
-            if response.status().is_success() {
-                let now = Local::now().naive_local();
-                save_last_backup_date(&app_handle.state(), now, "success").await?;
-                tracing::info!("Backup completed successfully at {}", now);
-            } else {
-                let status = response.status();
-                let error_text = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "No error message".to_string());
-                tracing::error!("Backup failed with status {}: {}", status, error_text);
-                save_last_backup_date(&app_handle.state(), Local::now().naive_local(), "fail")
-                    .await?;
-                return Err(AppError::BackupFailed(format!(
-                    "Backup failed with status {}: {}",
-                    status, error_text
-                )));
-            }
+    match result {
+        Ok(()) => {
+            save_last_backup_date(&app_state, taken_at, "success").await?;
+            tracing::info!("Backup completed successfully at {}", taken_at);
+            Ok(())
         }
         Err(e) => {
-            tracing::error!("Backup request failed: {:?}", e);
-            return Err(AppError::BackupFailed(
-                "Backup failed. Gateway error response!".to_string(),
-            ));
+            save_last_backup_date(&app_state, taken_at, "fail").await?;
+            Err(e)
         }
     }
-
-    Ok(())
 }
 
 async fn load_last_backup_date(
@@ -137,7 +111,7 @@ async fn load_last_backup_date(
           CronCheck,
           "SELECT id as `id!`, last_check_time, check_type, status, created_at, updated_at FROM cron_checks WHERE check_type = 'backup' AND status='success' ORDER BY last_check_time DESC LIMIT 1",
       )
-      .fetch_optional(&app_state.db_pool)
+      .fetch_optional(&app_state.db_pool())
       .await?;
 
     match check {
@@ -158,7 +132,7 @@ async fn save_last_backup_date(
         date,
         status
     )
-    .execute(&app_state.db_pool)
+    .execute(&app_state.db_pool())
     .await?;
 
     // Try to get the lock without waiting
@@ -176,13 +150,16 @@ pub async fn is_backup_needed(app_state: &tauri::State<'_, AppState>) -> AppResu
         tracing::info!("Backup is disabled, skipping backup check.");
         return Ok(false);
     }
-    let backup_url = app_state.settings.read().await.backup_url.clone();
-    if backup_url.is_none() {
-        tracing::warn!("Backup URL is not configured, skipping backup check.");
+    let has_target = {
+        let settings = app_state.settings.read().await;
+        settings.backup_store.is_configured() || settings.local_backup.directory.is_some()
+    };
+    if !has_target {
+        tracing::warn!("No backup store or local backup folder configured, skipping backup check.");
         return Ok(false);
     }
 
-    let today = Local::now().naive_local();
+    let today = app_state.gym_clock().await?.now_local();
     let last_backup_in_memory = *app_state.last_backup.read().await;
 
     let last_backup: chrono::NaiveDateTime;
@@ -244,6 +221,18 @@ pub fn spawn_backup_check_task(app_handle: tauri::AppHandle) {
                         if let Err(e) = perform_backup(&app_handle).await {
                             tracing::error!("Backup failed: {:?}", e);
                             message = "backup_failed";
+                        } else if let Err(e) = audit::record(
+                            &app_handle.state::<AppState>().db_pool(),
+                            &Actor::system(),
+                            "backup",
+                            "database",
+                            None,
+                            None,
+                            None,
+                        )
+                        .await
+                        {
+                            tracing::warn!("Failed to audit scheduled backup: {:?}", e);
                         }
                         let _ = &app_handle
                             .emit("status", message.to_string())
@@ -265,217 +254,81 @@ pub fn spawn_backup_check_task(app_handle: tauri::AppHandle) {
 pub async fn manual_trigger_backup(app_handle: tauri::AppHandle) -> AppResult<()> {
     return perform_backup(&app_handle).await;
 }
-async fn restore_local_backup(backup_path: &std::path::PathBuf, db_path: &std::path::PathBuf) {
-    if backup_path.exists() {
-        tracing::info!(
-            "Restoring local backup from {:?} to {:?}",
-            backup_path,
-            db_path
-        );
-        if let Err(e) = tokio::fs::rename(backup_path, db_path).await {
-            tracing::error!("CRITICAL FAILURE: Could not restore local backup. The database file might be missing. Error: {}", e);
-        }
-    }
-}
-
-pub async fn check_db_integrity(db_path: &PathBuf) -> AppResult<()> {
-    tracing::info!("Performing integrity check on: {:?}", db_path);
-
-    if !db_path.exists() {
-        tracing::info!("Database file does not exist, creating it...");
-        return Err(AppError::Config(format!(
-            "Database file does not exist at: {:?}",
-            db_path
-        )));
-    }
-
-    let options = SqliteConnectOptions::new()
-        .filename(db_path)
-        .create_if_missing(false)
-        .read_only(true);
-
-    let mut conn = options
-        .connect()
-        .await
-        .map_err(|e| AppError::Config(format!("Failed to connect to database: {}", e)))?;
-
-    // The PRAGMA returns a single row with the text 'ok' if the DB is not corrupt.
-    let result: (String,) = sqlx::query_as("PRAGMA integrity_check;")
-        .fetch_one(&mut conn)
-        .await
-        .map_err(|e| AppError::Sqlx(e))?;
-
-    if result.0.to_lowercase() == "ok" {
-        tracing::info!("Integrity check passed for: {:?}", db_path);
-        Ok(())
-    } else {
-        tracing::error!(
-            "Integrity check failed for {:?}. Result: {}",
-            db_path,
-            result.0
-        );
-        Err(AppError::RestoreFailed(result.0))
-    }
-}
-
-async fn download_and_verify_backup(
-    temp_path: &PathBuf,
-    download_url: String,
-    token: String,
-    gym_code: String,
-) -> AppResult<()> {
-    tracing::info!("Downloading backup file to: {:?}", temp_path);
-
-    // Download the file
-    let client = reqwest::Client::new();
-    let res = client
-        .get(&download_url)
-        .header("X-Api-Key", token)
-        .header("X-Gym-Code", gym_code)
-        .send()
-        .await;
-
-    let response = match res {
-        Ok(response) => {
-            if response.status().is_success() {
-                tracing::info!("Backup file downloaded successfully.");
-                response
-            } else {
-                let status = response.status();
-                let error_text = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "No error message".to_string());
-                tracing::error!(
-                    "Backup download failed with status {}: {}",
-                    status,
-                    error_text
-                );
-                return Err(AppError::RestoreFailed(format!(
-                    "Backup download failed with status {}: {}",
-                    status, error_text
-                )));
-            }
-        }
-        Err(e) => {
-            tracing::error!("Failed to download backup file: {:?}", e);
-            return Err(AppError::Reqwest(e));
-        }
-    };
-
-    // The body is base64 encoded by our Lambda
-    let encoded_body = response.text().await;
-    let body = match encoded_body {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("Failed to read response body: {:?}", e);
-            return Err(AppError::Reqwest(e));
-        }
-    };
-
-    let db_bytes = general_purpose::STANDARD.decode(body).map_err(|e| {
-        tracing::error!("Failed to decode base64 response body: {:?}", e);
-        AppError::Base64Decode(e)
-    })?;
-
-    tokio::fs::write(temp_path, db_bytes).await.map_err(|e| {
-        tracing::error!("Failed to write backup file: {:?}", e);
-        AppError::Io(e)
-    })?;
-    tracing::info!("Backup file downloaded successfully.");
-    check_db_integrity(temp_path).await?;
-    Ok(())
-}
-
+/// Restores a backup from the backup store. Encrypted backups need the
+/// backup passphrase or the recovery key.
+///
+/// The restored database is opened right away and a `database_restored`
+/// event tells the UI to reload. Users come from the backup, so the session
+/// ends as it does on a profile switch.
 #[tauri::command]
 pub async fn restore_from_backup(
     app_handle: tauri::AppHandle,
     version_id: Option<String>,
+    passphrase: Option<String>,
+    recovery_key: Option<String>,
 ) -> AppResult<String> {
     tracing::info!("Starting database restore process...");
 
     let app_state = app_handle.state::<AppState>();
-    let backup_url = app_state.settings.read().await.backup_url.clone();
-    let gym_code = app_state.settings.read().await.gym_code.clone();
-
-    if backup_url.is_none() {
-        tracing::warn!("Backup URL is not configured, skipping backup.");
-        return Err(AppError::Translatable(TranslatableError::new(
-            ErrorCodes::BACKUP_URL_NOT_SET,
-            "Backup URL is not set. Please configure it in the settings.",
-        )));
-    }
-    let url_data = parse_backup_url(backup_url.unwrap().as_str());
-    if url_data.is_err() {
-        tracing::error!("Invalid backup URL format: {}", url_data.unwrap_err());
-        return Err(AppError::Translatable(TranslatableError::new(
-            ErrorCodes::INVALID_BACKUP_URL,
-            "Invalid backup URL format",
-        )));
-    }
-    let (backup_url, token) = url_data.unwrap();
-    let mut download_url = format!("{}/backup", backup_url);
-    if let Some(vid) = version_id {
-        if !vid.is_empty() && vid != "null" {
-            download_url = format!("{}?versionId={}", download_url, vid);
+    let session = auth::require_permission(&app_state, Permission::BackupRestore).await?;
+    let current_settings = app_state.settings.read().await.clone();
+    let store = backup_store::configured(&current_settings)?;
+    let current_key = backup_encryption::load_key(&app_state.db_pool()).await?;
+    let secret = match (passphrase, recovery_key) {
+        (Some(passphrase), _) if !passphrase.is_empty() => {
+            Some(BackupSecret::Passphrase(passphrase))
         }
-    }
-    println!("Download URL: {}", download_url);
-
-    app_state.db_pool.close().await;
-    tracing::info!("Database connection pool closed.");
-
-    let db_folder = get_database_path(&app_handle)?;
-    let temp_path = db_folder.join("gym_data_tmp.sqlite");
-    let db_path = db_folder.join("gym_data.sqlite");
-    let backup_path = db_folder.join("gym_data_backup.sqlite");
-    let wal_path = db_folder.join("gym_data.sqlite-wal");
-    let shm_path = db_folder.join("gym_data.sqlite-shm");
-
-    tracing::info!("Backing up current database to: {:?}", backup_path);
-    if let Err(e) = tokio::fs::rename(&db_path, &backup_path).await {
-        let err_msg = format!(
-            "Failed to create local backup of current DB: {}. Please restart.",
-            e
-        );
-        tracing::error!("{}", err_msg);
-        return Err(AppError::Io(e));
-    }
-
-    match download_and_verify_backup(&temp_path, download_url, token, gym_code).await {
-        Ok(_) => {
-            tracing::info!(
-                "Verification successful. Replacing current database with downloaded version."
-            );
-            if let Err(e) = tokio::fs::rename(&temp_path, &db_path).await {
-                restore_local_backup(&backup_path, &db_path).await;
-                return Err(AppError::RestoreFailed(format!("CRITICAL: Failed to replace database after verification. Local backup has been restored. Error: {}", e)));
-            }
-            if backup_path.exists() {
-                if let Err(e) = tokio::fs::remove_file(&backup_path).await {
-                    tracing::warn!(
-                        "Could not remove temporary backup file {:?}: {}",
-                        backup_path,
-                        e
-                    );
-                }
-            }
-            tracing::info!("Database file successfully overwritten from remote backup.");
+        (_, Some(recovery_key)) if !recovery_key.is_empty() => {
+            Some(BackupSecret::RecoveryKey(recovery_key))
         }
-        Err(e) => {
-            tracing::error!("Restore failed: {}. Reverting to local backup.", e);
-            restore_local_backup(&backup_path, &db_path).await;
+        _ => None,
+    };
 
-            if temp_path.exists() {
-                let _ = tokio::fs::remove_file(&temp_path).await;
-            }
+    let db_path = app_state.database_path.read().await.clone();
+    let restored = backup::download_path(&db_path);
+    let manifest = backup::fetch_backup(
+        store.as_ref(),
+        version_id.as_deref(),
+        &restored,
+        secret.as_ref(),
+        &progress_events(&app_handle),
+    )
+    .await?;
 
-            return Err(AppError::RestoreFailed(format!("Restore failed due to an error: {}. Your previous data has been restored. Please restart the application to reconnect.", e)));
-        }
-    }
+    app_state.db_pool().close().await;
+    tracing::info!("Database connection pool closed.");
 
-    tracing::info!("Deleting old WAL/SHM files if they exist...");
-    let _ = tokio::fs::remove_file(&wal_path).await;
-    let _ = tokio::fs::remove_file(&shm_path).await;
-    Ok("Restore successful. Please restart the application.".to_string())
+    let replaced = backup::replace_database(
+        &restored,
+        &db_path,
+        &current_settings,
+        current_key.as_ref(),
+        &Actor::from(&session),
+        serde_json::json!({ "version_id": version_id, "manifest": manifest }),
+    )
+    .await;
+    // Restored or put back, the database at `db_path` is opened again, which
+    // also migrates a backup taken by an older version. From here on the app
+    // holds a pool on it, whatever fails next.
+    let pool = app_state.reopen_database(db_path.clone()).await?;
+    replaced?;
+    let settings = config::load_settings(&app_handle, &pool).await?;
+    let profile = app_state.profile.read().await.clone();
+    app_state
+        .switch_database(pool, settings.clone(), profile, db_path)
+        .await;
+    let last_backup = load_last_backup_date(&app_state).await?;
+    *app_state.last_backup.write().await = Some(last_backup);
+    let last_membership_check = utils::load_last_checked_membership_date(&app_state).await?;
+    *app_state.last_membership_check.write().await = Some(last_membership_check);
+    utils::backfill_entry_local_dates(&app_state).await;
+    tracing::info!("Restored database opened.");
+
+    app_handle
+        .emit("database_restored", &manifest)
+        .unwrap_or_else(|e| tracing::warn!("Failed to emit database_restored event: {}", e));
+    app_handle
+        .emit("settings_changed", AppSettingsView::from(&settings))
+        .unwrap_or_else(|e| tracing::warn!("Failed to emit settings_changed event: {}", e));
+    Ok("Restore successful.".to_string())
 }
@@ -0,0 +1,113 @@
+//! The gym's wall clock. Every "now" and "today" that business rules depend
+//! on is read in the configured gym timezone, never the timezone of the PC,
+//! and the time itself comes from a [`TimeSource`] that tests can pin.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+pub trait TimeSource: Send + Sync + Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The operating system clock.
+#[derive(Debug, Default)]
+pub struct SystemTime;
+
+impl TimeSource for SystemTime {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+#[derive(Debug)]
+pub struct FixedTime(Mutex<DateTime<Utc>>);
+
+impl FixedTime {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Mutex::new(now))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) += by;
+    }
+}
+
+impl TimeSource for FixedTime {
+    fn now(&self) -> DateTime<Utc> {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GymClock {
+    source: Arc<dyn TimeSource>,
+    tz: Tz,
+}
+
+impl GymClock {
+    pub fn new(source: Arc<dyn TimeSource>, tz: Tz) -> Self {
+        Self { source, tz }
+    }
+
+    pub fn system(tz: Tz) -> Self {
+        Self::new(Arc::new(SystemTime), tz)
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.tz
+    }
+
+    pub fn now(&self) -> DateTime<Tz> {
+        self.source.now().with_timezone(&self.tz)
+    }
+
+    /// Current time in UTC, the form timestamps are stored in.
+    pub fn now_utc(&self) -> NaiveDateTime {
+        self.source.now().naive_utc()
+    }
+
+    /// Current wall clock time at the gym.
+    pub fn now_local(&self) -> NaiveDateTime {
+        self.now().naive_local()
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    /// Wall clock time at the gym of a stored UTC timestamp.
+    pub fn to_local(&self, utc: NaiveDateTime) -> NaiveDateTime {
+        self.tz.from_utc_datetime(&utc).naive_local()
+    }
+
+    /// The UTC timestamp at which `date` starts at the gym. On a day whose
+    /// midnight is skipped by a DST change, the first hour that exists.
+    pub fn start_of_day_utc(&self, date: NaiveDate) -> NaiveDateTime {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+        (0..24)
+            .find_map(|hour| {
+                self.tz
+                    .from_local_datetime(&(midnight + Duration::hours(hour)))
+                    .earliest()
+            })
+            .map(|start| start.naive_utc())
+            .unwrap_or(midnight)
+    }
+}
@@ -1,15 +1,29 @@
 use crate::{
+    audit::{self, Actor},
+    auth::{self, Permission},
     backup::manual_trigger_backup,
-    config::{parse_backup_url, save_settings, AppSettings},
-    dto::{BackupMetadata, UserDisplay, UserPayload},
+    config::{
+        self, BackupStoreSettings, CheckInPolicy, CurrencySettings, LocalBackupSettings,
+        LoginLockoutSettings, PasswordPolicySettings,
+    },
+    dto::{
+        AppSettingsView, BackupEncryptionStatus, BackupMetadata, LocalBackupStatus,
+        LoginAttemptQueryParams, PaginatedResponse, UserDisplay, UserPayload,
+    },
     error::{ErrorCodes, Result as AppResult, TranslatableError},
-    models::User,
+    models::{LoginAttempt, User},
+    services::{
+        backup_encryption::{self, BackupKey},
+        backup_store::{self, DirectoryStore},
+        membership_types,
+    },
     state::AppState,
-    utils, AppError,
+    totp, utils, AppError,
 };
+use chrono::NaiveDateTime;
 use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 
 #[derive(Deserialize)]
 pub struct LoginPayload {
@@ -23,39 +37,128 @@ pub struct LoginResponse {
     message: String,
     username: Option<String>,
     role: Option<String>,
+    user_id: Option<i64>,
+    permissions: Vec<Permission>,
+    must_change_password: bool,
+    two_factor_required: bool,
+    locked_until: Option<NaiveDateTime>,
+}
+
+impl LoginResponse {
+    fn rejected(message: &str, locked_until: Option<NaiveDateTime>) -> Self {
+        Self {
+            success: false,
+            message: message.to_string(),
+            username: None,
+            role: None,
+            user_id: None,
+            permissions: Vec::new(),
+            must_change_password: false,
+            two_factor_required: false,
+            locked_until,
+        }
+    }
+}
+
+const USER_SELECT: &str = "SELECT id, username, role, password_hash, must_change_password, totp_secret, totp_enabled, totp_last_used_step, created_at, updated_at FROM users";
+
+async fn reject_login(state: &AppState, username: &str, reason: &str) -> AppResult<LoginResponse> {
+    let lockout_policy = state.settings.read().await.login_lockout.clone();
+    let locked_until =
+        auth::register_failed_login(&state.db_pool(), username, &lockout_policy).await?;
+    auth::record_login_attempt(&state.db_pool(), username, false, reason).await?;
+
+    let message = match locked_until {
+        Some(_) => "Too many failed login attempts. Account is temporarily locked.",
+        None => "Invalid username or password",
+    };
+    Ok(LoginResponse::rejected(message, locked_until))
+}
+
+/// Starts the session for a user who has passed every login factor.
+async fn complete_login(state: &AppState, user: User, reason: &str) -> AppResult<LoginResponse> {
+    let permissions = auth::load_role_permissions(&state.db_pool(), &user.role)
+        .await?
+        .ok_or_else(|| {
+            tracing::error!("User '{}' has unknown role '{}'.", user.username, user.role);
+            AppError::Validation(format!("Unknown role '{}'", user.role))
+        })?;
+    auth::clear_failed_logins(&state.db_pool(), &user.username).await?;
+    auth::record_login_attempt(&state.db_pool(), &user.username, true, reason).await?;
+    let mut granted: Vec<Permission> = permissions.iter().copied().collect();
+    granted.sort_by_key(|p| p.as_str());
+    auth::start_session(
+        state,
+        user.id,
+        &user.username,
+        &user.role,
+        permissions,
+        user.must_change_password,
+    )
+    .await;
+    if user.must_change_password {
+        tracing::warn!("User '{}' must change their password.", user.username);
+    }
+    tracing::info!("User '{}' logged in successfully.", user.username);
+    Ok(LoginResponse {
+        success: true,
+        message: "Login successful".to_string(),
+        username: Some(user.username),
+        role: Some(user.role),
+        user_id: Some(user.id),
+        permissions: granted,
+        must_change_password: user.must_change_password,
+        two_factor_required: false,
+        locked_until: None,
+    })
 }
 
 #[tauri::command]
 pub async fn login(payload: LoginPayload, state: State<'_, AppState>) -> AppResult<LoginResponse> {
     tracing::info!("Login attempt for user: {}", payload.username);
 
-    let user = sqlx::query_as!(
-        User,
-        "SELECT id as `id!`, username, role, password_hash, created_at, updated_at FROM users WHERE username = ?",
-        payload.username
-    )
-    .fetch_optional(&state.db_pool)
-    .await?;
+    if let Some(locked_until) =
+        auth::get_active_lockout(&state.db_pool(), &payload.username).await?
+    {
+        tracing::warn!(
+            "Login for '{}' rejected, account locked until {}.",
+            payload.username,
+            locked_until
+        );
+        auth::record_login_attempt(&state.db_pool(), &payload.username, false, "locked_out")
+            .await?;
+        return Ok(LoginResponse::rejected(
+            "Too many failed login attempts. Account is temporarily locked.",
+            Some(locked_until),
+        ));
+    }
+
+    // A new login attempt always abandons any half-finished two-factor login.
+    auth::clear_pending_two_factor(&state).await;
+
+    let user = sqlx::query_as::<_, User>(&format!("{} WHERE username = ?", USER_SELECT))
+        .bind(&payload.username)
+        .fetch_optional(&state.db_pool())
+        .await?;
 
     match user {
         Some(u) => match utils::verify_password(&payload.password, &u.password_hash) {
-            Ok(true) => {
-                tracing::info!("User '{}' logged in successfully.", payload.username);
+            Ok(true) if u.totp_enabled => {
+                tracing::info!(
+                    "Password accepted for '{}', waiting for two-factor code.",
+                    u.username
+                );
+                auth::begin_two_factor(&state, u.id, &u.username).await;
                 Ok(LoginResponse {
-                    success: true,
-                    message: "Login successful".to_string(),
                     username: Some(u.username),
-                    role: Some(u.role),
+                    two_factor_required: true,
+                    ..LoginResponse::rejected("Two-factor code required", None)
                 })
             }
+            Ok(true) => complete_login(&state, u, "success").await,
             Ok(false) => {
                 tracing::warn!("Invalid password for user '{}'.", payload.username);
-                Ok(LoginResponse {
-                    success: false,
-                    message: "Invalid username or password".to_string(),
-                    username: None,
-                    role: None,
-                })
+                reject_login(&state, &payload.username, "invalid_password").await
             }
             Err(e) => {
                 tracing::error!(
@@ -68,23 +171,274 @@ pub async fn login(payload: LoginPayload, state: State<'_, AppState>) -> AppResu
         },
         None => {
             tracing::warn!("User '{}' not found.", payload.username);
+            reject_login(&state, &payload.username, "unknown_user").await
+        }
+    }
+}
+
+async fn fetch_user_for_audit(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    id: i64,
+) -> AppResult<Option<User>> {
+    let user = sqlx::query_as::<_, User>(&format!("{} WHERE id = ?", USER_SELECT))
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    Ok(user)
+}
+
+/// Second login step for users with two-factor authentication. Accepts a
+/// TOTP code or one of the user's recovery codes.
+#[tauri::command]
+pub async fn verify_login_two_factor(
+    code: String,
+    state: State<'_, AppState>,
+) -> AppResult<LoginResponse> {
+    let pending = auth::get_pending_two_factor(&state).await?;
+
+    if let Some(locked_until) =
+        auth::get_active_lockout(&state.db_pool(), &pending.username).await?
+    {
+        auth::clear_pending_two_factor(&state).await;
+        auth::record_login_attempt(&state.db_pool(), &pending.username, false, "locked_out")
+            .await?;
+        return Ok(LoginResponse::rejected(
+            "Too many failed login attempts. Account is temporarily locked.",
+            Some(locked_until),
+        ));
+    }
+
+    let unix_time = chrono::Utc::now().timestamp() as u64;
+    match auth::verify_second_factor(&state.db_pool(), pending.user_id, &code, unix_time).await? {
+        Some(factor) => {
+            auth::clear_pending_two_factor(&state).await;
+            let user = sqlx::query_as::<_, User>(&format!("{} WHERE id = ?", USER_SELECT))
+                .bind(pending.user_id)
+                .fetch_optional(&state.db_pool())
+                .await?
+                .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+            let reason = format!("success_{}", factor.as_str());
+            complete_login(&state, user, &reason).await
+        }
+        None => {
+            tracing::warn!("Invalid two-factor code for user '{}'.", pending.username);
+            let response =
+                reject_login(&state, &pending.username, "invalid_two_factor_code").await?;
+            if response.locked_until.is_some() {
+                auth::clear_pending_two_factor(&state).await;
+            }
             Ok(LoginResponse {
-                success: false,
-                message: "Invalid username or password".to_string(),
-                username: None,
-                role: None,
+                username: Some(pending.username),
+                two_factor_required: response.locked_until.is_none(),
+                message: if response.locked_until.is_some() {
+                    response.message
+                } else {
+                    "Invalid two-factor code".to_string()
+                },
+                ..response
             })
         }
     }
 }
 
+#[tauri::command]
+pub async fn logout(state: State<'_, AppState>) -> AppResult<()> {
+    auth::clear_pending_two_factor(&state).await;
+    auth::end_session(&state).await;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorEnrollment {
+    secret: String,
+    otpauth_uri: String,
+}
+
+/// Generates a new TOTP secret for the current user. Two-factor stays off
+/// until [`confirm_two_factor_enrollment`] sees a valid code from it.
+#[tauri::command]
+pub async fn begin_two_factor_enrollment(
+    app_state: tauri::State<'_, AppState>,
+) -> AppResult<TwoFactorEnrollment> {
+    let session = auth::require_session(&app_state).await?;
+
+    let enabled: bool = sqlx::query_scalar("SELECT totp_enabled FROM users WHERE id = ?")
+        .bind(session.user_id)
+        .fetch_optional(&app_state.db_pool())
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    if enabled {
+        return Err(AppError::Translatable(TranslatableError::new(
+            ErrorCodes::TWO_FACTOR_ALREADY_ENABLED,
+            "Two-factor authentication is already enabled",
+        )));
+    }
+
+    let secret = totp::generate_secret();
+    sqlx::query("UPDATE users SET totp_secret = ?, totp_last_used_step = NULL WHERE id = ?")
+        .bind(&secret)
+        .bind(session.user_id)
+        .execute(&app_state.db_pool())
+        .await?;
+
+    let issuer = {
+        let gym_name = app_state.settings.read().await.gym_name.clone();
+        if gym_name.trim().is_empty() {
+            "Gym Manager".to_string()
+        } else {
+            gym_name
+        }
+    };
+    Ok(TwoFactorEnrollment {
+        otpauth_uri: totp::provisioning_uri(&secret, &session.username, &issuer),
+        secret,
+    })
+}
+
+/// Turns two-factor on once the user proves their authenticator works.
+/// Returns the recovery codes, which are never shown again.
+#[tauri::command]
+pub async fn confirm_two_factor_enrollment(
+    app_state: tauri::State<'_, AppState>,
+    code: String,
+) -> AppResult<Vec<String>> {
+    let session = auth::require_session(&app_state).await?;
+
+    let (secret, enabled): (Option<String>, bool) =
+        sqlx::query_as("SELECT totp_secret, totp_enabled FROM users WHERE id = ?")
+            .bind(session.user_id)
+            .fetch_optional(&app_state.db_pool())
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    if enabled {
+        return Err(AppError::Translatable(TranslatableError::new(
+            ErrorCodes::TWO_FACTOR_ALREADY_ENABLED,
+            "Two-factor authentication is already enabled",
+        )));
+    }
+    let secret = secret.ok_or_else(|| {
+        AppError::Validation("Two-factor enrollment has not been started".to_string())
+    })?;
+
+    let unix_time = chrono::Utc::now().timestamp() as u64;
+    let step = totp::verify(&secret, &code, unix_time)?.ok_or_else(|| {
+        AppError::Translatable(TranslatableError::new(
+            ErrorCodes::INVALID_TWO_FACTOR_CODE,
+            "Invalid two-factor code",
+        ))
+    })?;
+
+    let mut tx = app_state.db_pool().begin().await?;
+    sqlx::query("UPDATE users SET totp_enabled = TRUE, totp_last_used_step = ? WHERE id = ?")
+        .bind(step as i64)
+        .bind(session.user_id)
+        .execute(&mut *tx)
+        .await?;
+    let recovery_codes = auth::replace_recovery_codes(&mut tx, session.user_id).await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "two_factor_enable",
+        "user",
+        Some(session.user_id),
+        None,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        "Two-factor authentication enabled for '{}'.",
+        session.username
+    );
+    Ok(recovery_codes)
+}
+
+/// Turns two-factor off. Users turning off their own need a current code;
+/// resetting someone else's (a lost phone) needs user management rights.
+#[tauri::command]
+pub async fn disable_two_factor(
+    app_state: tauri::State<'_, AppState>,
+    user_id: i64,
+    code: Option<String>,
+) -> AppResult<()> {
+    let session = auth::require_session(&app_state).await?;
+    if session.user_id == user_id {
+        let code = code.unwrap_or_default();
+        auth::require_second_factor(&app_state.db_pool(), user_id, &code).await?;
+    } else {
+        auth::require_permission(&app_state, Permission::UsersManage).await?;
+    }
+
+    let mut tx = app_state.db_pool().begin().await?;
+    let result = sqlx::query(
+        "UPDATE users SET totp_secret = NULL, totp_enabled = FALSE, totp_last_used_step = NULL WHERE id = ?",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "two_factor_disable",
+        "user",
+        Some(user_id),
+        None,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        "Two-factor authentication disabled for user {} by '{}'.",
+        user_id,
+        session.username
+    );
+    Ok(())
+}
+
+/// Issues a fresh set of recovery codes for the current user, invalidating
+/// the old ones.
+#[tauri::command]
+pub async fn regenerate_recovery_codes(
+    app_state: tauri::State<'_, AppState>,
+    code: String,
+) -> AppResult<Vec<String>> {
+    let session = auth::require_session(&app_state).await?;
+    auth::require_second_factor(&app_state.db_pool(), session.user_id, &code).await?;
+
+    let mut tx = app_state.db_pool().begin().await?;
+    let recovery_codes = auth::replace_recovery_codes(&mut tx, session.user_id).await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "recovery_codes_regenerate",
+        "user",
+        Some(session.user_id),
+        None,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(recovery_codes)
+}
+
 #[tauri::command]
 pub async fn get_all_users(app_state: tauri::State<'_, AppState>) -> AppResult<Vec<UserDisplay>> {
+    auth::require_permission(&app_state, Permission::UsersManage).await?;
     let users = sqlx::query_as!(
         UserDisplay,
         "SELECT id as `id!`, username, role, created_at, updated_at FROM users"
     )
-    .fetch_all(&app_state.db_pool)
+    .fetch_all(&app_state.db_pool())
     .await?;
 
     Ok(users)
@@ -95,12 +449,13 @@ pub async fn get_user_by_id(
     app_state: tauri::State<'_, AppState>,
     user_id: i64,
 ) -> AppResult<Option<UserDisplay>> {
+    auth::require_permission(&app_state, Permission::UsersManage).await?;
     let user = sqlx::query_as!(
         UserDisplay,
         "SELECT id as `id!`, username, role, created_at, updated_at FROM users WHERE id = ?",
         user_id
     )
-    .fetch_optional(&app_state.db_pool)
+    .fetch_optional(&app_state.db_pool())
     .await?;
 
     Ok(user)
@@ -111,13 +466,19 @@ pub async fn save_user(
     app_state: tauri::State<'_, AppState>,
     payload: UserPayload,
 ) -> AppResult<UserDisplay> {
+    let session = auth::require_permission(&app_state, Permission::UsersManage).await?;
     if payload.username.is_empty() {
         return Err(AppError::Validation("Username cannot be empty".to_string()));
     }
-    if payload.role != "admin" && payload.role != "user" {
-        return Err(AppError::Validation(
-            "Role must be 'admin' or 'user'".to_string(),
-        ));
+    let role_exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM roles WHERE name = ?)")
+        .bind(&payload.role)
+        .fetch_one(&app_state.db_pool())
+        .await?;
+    if !role_exists {
+        return Err(AppError::Validation(format!(
+            "Role '{}' does not exist",
+            payload.role
+        )));
     }
     match payload.id {
         Some(id) => {
@@ -128,7 +489,7 @@ pub async fn save_user(
                 payload.username,
                 id
             )
-            .fetch_optional(&app_state.db_pool)
+            .fetch_optional(&app_state.db_pool())
             .await?;
             if existing_user.is_some() {
                 return Err(AppError::Translatable(TranslatableError::new(
@@ -137,17 +498,38 @@ pub async fn save_user(
                 )));
             }
             // update existing user
+            let mut tx = app_state.db_pool().begin().await?;
+            let before = fetch_user_for_audit(&mut tx, id).await?;
             let query = sqlx::query!(
                 "UPDATE users SET username = ?, role = ? WHERE id = ?",
                 payload.username,
                 payload.role,
                 id
             )
-            .execute(&app_state.db_pool)
+            .execute(&mut *tx)
             .await?;
             if query.rows_affected() == 0 {
                 return Err(AppError::NotFound("User not found".to_string()));
             }
+            if let Some(must_change_password) = payload.must_change_password {
+                sqlx::query("UPDATE users SET must_change_password = ? WHERE id = ?")
+                    .bind(must_change_password)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            let after = fetch_user_for_audit(&mut tx, id).await?;
+            audit::record(
+                &mut *tx,
+                &Actor::from(&session),
+                "update",
+                "user",
+                Some(id),
+                before.as_ref().and_then(audit::snapshot),
+                after.as_ref().and_then(audit::snapshot),
+            )
+            .await?;
+            tx.commit().await?;
             let updated_user = get_user_by_id(app_state, id).await?;
 
             if let Some(user) = updated_user {
@@ -166,16 +548,34 @@ pub async fn save_user(
                 ));
             }
 
-            let hashed_password = utils::hash_password(&payload.password.unwrap_or_default())?;
-            let user = sqlx::query_as!(
-                UserDisplay,
-                "INSERT INTO users (username, password_hash, role) VALUES (?, ?, ?) RETURNING id as `id!`, username, role, created_at, updated_at",
-                payload.username,
-                hashed_password,
-                payload.role
+            let password = payload.password.unwrap_or_default();
+            let password_policy = app_state.settings.read().await.password_policy.clone();
+            utils::validate_password(&password, &password_policy)?;
+
+            let hashed_password = utils::hash_password(&password)?;
+            let mut tx = app_state.db_pool().begin().await?;
+            let user = sqlx::query_as::<_, UserDisplay>(
+                "INSERT INTO users (username, password_hash, role, must_change_password) VALUES (?, ?, ?, ?) RETURNING id, username, role, created_at, updated_at",
+            )
+            .bind(&payload.username)
+            .bind(&hashed_password)
+            .bind(&payload.role)
+            .bind(payload.must_change_password.unwrap_or(false))
+            .fetch_one(&mut *tx)
+            .await?;
+            auth::record_password_history(&mut *tx, user.id, &hashed_password).await?;
+            let created = fetch_user_for_audit(&mut tx, user.id).await?;
+            audit::record(
+                &mut *tx,
+                &Actor::from(&session),
+                "create",
+                "user",
+                Some(user.id),
+                None,
+                created.as_ref().and_then(audit::snapshot),
             )
-            .fetch_one(&app_state.db_pool)
             .await?;
+            tx.commit().await?;
             Ok(user)
         }
     }
@@ -187,6 +587,13 @@ pub async fn change_user_password(
     user_id: i64,
     new_password: String,
 ) -> AppResult<()> {
+    let session = auth::require_session_for_password_change(&app_state).await?;
+    let is_own_password = session.user_id == user_id;
+    if !is_own_password {
+        // Resetting someone else's password needs user management rights
+        // and an up-to-date password of your own.
+        auth::require_permission(&app_state, Permission::UsersManage).await?;
+    }
     if new_password.is_empty() {
         return Err(AppError::Validation(
             "New password cannot be empty".to_string(),
@@ -195,7 +602,7 @@ pub async fn change_user_password(
 
     // Check if user exists
     let user_exists = sqlx::query!("SELECT COUNT(*) as count FROM users WHERE id = ?", user_id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(&app_state.db_pool())
         .await?
         .count
         > 0;
@@ -204,26 +611,71 @@ pub async fn change_user_password(
         return Err(AppError::NotFound("User not found".to_string()));
     }
 
+    let password_policy = app_state.settings.read().await.password_policy.clone();
+    utils::validate_password(&new_password, &password_policy)?;
+    auth::ensure_password_not_reused(
+        &app_state.db_pool(),
+        user_id,
+        &new_password,
+        password_policy.history_size,
+    )
+    .await?;
+
     // Hash the new password
     let hashed_password = utils::hash_password(&new_password)?;
 
-    // Update the user's password
-    sqlx::query!(
-        "UPDATE users SET password_hash = ? WHERE id = ?",
-        hashed_password,
-        user_id
+    // Update the user's password; a user rotating their own password clears the
+    // pending change, an admin reset leaves the flag as it was.
+    let mut tx = app_state.db_pool().begin().await?;
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET password_hash = ?,
+            must_change_password = CASE WHEN ? THEN FALSE ELSE must_change_password END,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?
+        "#,
     )
-    .execute(&app_state.db_pool)
+    .bind(&hashed_password)
+    .bind(is_own_password)
+    .bind(user_id)
+    .execute(&mut *tx)
     .await?;
+    auth::record_password_history(&mut *tx, user_id, &hashed_password).await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        if is_own_password {
+            "password_change"
+        } else {
+            "password_reset"
+        },
+        "user",
+        Some(user_id),
+        None,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    if is_own_password {
+        auth::mark_password_changed(&app_state, user_id).await;
+    }
+    tracing::info!(
+        "Password for user {} changed by '{}'.",
+        user_id,
+        session.username
+    );
 
     Ok(())
 }
 
 #[tauri::command]
 pub async fn delete_user(app_state: tauri::State<'_, AppState>, user_id: i64) -> AppResult<()> {
+    let session = auth::require_permission(&app_state, Permission::UsersManage).await?;
     // Check if user exists
     let user_exists = sqlx::query!("SELECT COUNT(*) as count FROM users WHERE id = ?", user_id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(&app_state.db_pool())
         .await?
         .count
         > 0;
@@ -233,16 +685,104 @@ pub async fn delete_user(app_state: tauri::State<'_, AppState>, user_id: i64) ->
     }
 
     // Delete the user
+    let mut tx = app_state.db_pool().begin().await?;
+    let before = fetch_user_for_audit(&mut tx, user_id).await?;
     sqlx::query!("DELETE FROM users WHERE id = ?", user_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "delete",
+        "user",
+        Some(user_id),
+        before.as_ref().and_then(audit::snapshot),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn get_app_settings(app_state: tauri::State<'_, AppState>) -> AppResult<AppSettings> {
-    Ok(app_state.settings.read().await.clone())
+pub async fn unlock_user(app_state: tauri::State<'_, AppState>, username: String) -> AppResult<()> {
+    let session = auth::require_permission(&app_state, Permission::UsersManage).await?;
+
+    if auth::clear_failed_logins(&app_state.db_pool(), &username).await? {
+        audit::record(
+            &app_state.db_pool(),
+            &Actor::from(&session),
+            "unlock",
+            "user",
+            None,
+            None,
+            Some(serde_json::json!({ "username": username })),
+        )
+        .await?;
+        tracing::info!("Account '{}' unlocked by '{}'.", username, session.username);
+    } else {
+        tracing::info!("Account '{}' had no failed logins to clear.", username);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_login_attempts(
+    app_state: tauri::State<'_, AppState>,
+    payload: LoginAttemptQueryParams,
+) -> AppResult<PaginatedResponse<LoginAttempt>> {
+    auth::require_permission(&app_state, Permission::UsersManage).await?;
+
+    let page = payload.page.unwrap_or(1).max(1);
+    let per_page = payload.per_page.unwrap_or(50).min(200).max(1);
+    let offset = (page - 1) * per_page;
+    let username = payload
+        .username
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty());
+
+    let where_clause = "(?1 IS NULL OR username = ?1) AND (?2 IS NULL OR success = ?2)";
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM login_attempts WHERE {}",
+        where_clause
+    ))
+    .bind(&username)
+    .bind(payload.success)
+    .fetch_one(&app_state.db_pool())
+    .await?;
+
+    let attempts = sqlx::query_as::<_, LoginAttempt>(&format!(
+        r#"
+        SELECT id, username, success, reason, attempted_at
+        FROM login_attempts
+        WHERE {}
+        ORDER BY attempted_at DESC
+        LIMIT ?3 OFFSET ?4
+        "#,
+        where_clause
+    ))
+    .bind(&username)
+    .bind(payload.success)
+    .bind(per_page as i64)
+    .bind(offset as i64)
+    .fetch_all(&app_state.db_pool())
+    .await?;
+
+    Ok(PaginatedResponse {
+        data: attempts,
+        total,
+        total_pages: ((total as f64) / (per_page as f64)).ceil() as i64,
+        page,
+        per_page,
+    })
+}
+
+#[tauri::command]
+pub async fn get_app_settings(app_state: tauri::State<'_, AppState>) -> AppResult<AppSettingsView> {
+    auth::require_session(&app_state).await?;
+    Ok(AppSettingsView::from(&*app_state.settings.read().await))
 }
 
 #[derive(Deserialize, Debug)]
@@ -250,10 +790,18 @@ pub struct UpdateAppSettingsPayload {
     pub language: Option<String>,
     pub theme: Option<String>,
     pub timezone: Option<String>,
-    pub backup_url: Option<String>, // Might be Some("") to clear
+    /// Shorthand for an HTTP gateway `backup_store`. Might be Some("") to clear
+    pub backup_url: Option<String>,
+    pub backup_store: Option<BackupStoreSettings>,
     pub backup_period_hours: Option<u64>,
     pub backup_enabled: Option<bool>,
     pub gym_name: Option<String>,
+    pub login_lockout: Option<LoginLockoutSettings>,
+    pub password_policy: Option<PasswordPolicySettings>,
+    pub check_in_policy: Option<CheckInPolicy>,
+    pub member_trash_retention_days: Option<u32>,
+    pub currency: Option<CurrencySettings>,
+    pub local_backup: Option<LocalBackupSettings>,
 }
 
 #[tauri::command]
@@ -262,23 +810,28 @@ pub async fn update_app_settings(
     app_state: tauri::State<'_, AppState>,
     payload: UpdateAppSettingsPayload,
 ) -> AppResult<()> {
+    let session = auth::require_permission(&app_state, Permission::SettingsManage).await?;
     let mut settings = app_state.settings.write().await;
+    let before = audit::snapshot(&AppSettingsView::from(&*settings));
+    // Changes go into a copy, which only replaces the live settings once it
+    // is valid and saved.
+    let mut next = settings.clone();
     let mut changed = false;
 
     if let Some(lang) = payload.language {
-        settings.language = lang;
+        next.language = lang;
         changed = true;
     }
     if let Some(theme) = payload.theme {
-        settings.theme = theme;
+        next.theme = theme;
         changed = true;
     }
     if let Some(gym_name) = payload.gym_name {
-        settings.gym_name = gym_name;
+        next.gym_name = gym_name;
         changed = true;
     }
     if let Some(backup_enabled) = payload.backup_enabled {
-        settings.backup_enabled = backup_enabled;
+        next.backup_enabled = backup_enabled;
         changed = true;
     }
 
@@ -290,40 +843,129 @@ pub async fn update_app_settings(
                 "Invalid timezone configuration!",
             ));
         })?;
-        settings.timezone = tz;
+        next.timezone = tz;
         changed = true;
     }
-    if payload.backup_url.is_some() {
-        let full_url = payload.backup_url.as_deref().unwrap_or("");
-        if full_url.is_empty() && !payload.backup_enabled.unwrap_or(false) {
-            settings.backup_url = None;
-            changed = true;
-        } else {
-            let url = parse_backup_url(full_url);
-            match url {
-                Ok(_) => {
-                    settings.backup_url = Some(full_url.to_string());
-                    changed = true;
-                }
-                Err(e) => {
-                    tracing::error!("Invalid backup URL format: {}", e);
-                    return Err(AppError::Translatable(TranslatableError::new(
-                        ErrorCodes::INVALID_BACKUP_URL,
-                        "Invalid backup URL format!",
-                    )));
-                }
-            }
+    let new_store = match (payload.backup_store, payload.backup_url) {
+        (Some(store), _) => Some(store),
+        (None, Some(url)) if url.is_empty() && !payload.backup_enabled.unwrap_or(false) => {
+            Some(BackupStoreSettings::Disabled)
         }
+        (None, Some(url)) => Some(BackupStoreSettings::Http { url }),
+        (None, None) => None,
+    };
+    if let Some(mut new_store) = new_store {
+        if let BackupStoreSettings::Directory { path } = &mut new_store {
+            *path = path.trim().to_string();
+        }
+        new_store.keep_secrets_from(&next.backup_store);
+        // Opening the store validates its settings.
+        backup_store::open(&new_store, &next.gym_code)?;
+        next.backup_store = new_store;
+        changed = true;
     }
     if payload.backup_period_hours.is_some() {
-        settings.backup_period_hours = payload.backup_period_hours;
+        next.backup_period_hours = payload.backup_period_hours;
+        changed = true;
+    }
+    if let Some(login_lockout) = payload.login_lockout {
+        if login_lockout.max_failed_attempts == 0 {
+            return Err(AppError::Validation(
+                "Max failed login attempts must be at least 1.".to_string(),
+            ));
+        }
+        if login_lockout.base_lockout_minutes == 0
+            || login_lockout.max_lockout_minutes < login_lockout.base_lockout_minutes
+        {
+            return Err(AppError::Validation(
+                "Lockout durations must be positive and max must not be below base.".to_string(),
+            ));
+        }
+        next.login_lockout = login_lockout;
+        changed = true;
+    }
+    if let Some(password_policy) = payload.password_policy {
+        if password_policy.min_length == 0 {
+            return Err(AppError::Validation(
+                "Minimum password length must be at least 1.".to_string(),
+            ));
+        }
+        next.password_policy = password_policy;
+        changed = true;
+    }
+    if let Some(check_in_policy) = payload.check_in_policy {
+        next.check_in_policy = check_in_policy;
+        changed = true;
+    }
+    if let Some(retention_days) = payload.member_trash_retention_days {
+        next.member_trash_retention_days = retention_days;
+        changed = true;
+    }
+    let mut rescale_digits = None;
+    if let Some(currency) = payload.currency {
+        let code_is_valid =
+            currency.code.len() == 3 && currency.code.chars().all(|c| c.is_ascii_uppercase());
+        if !code_is_valid {
+            return Err(AppError::Validation(
+                "Currency code must be a three letter ISO 4217 code, e.g. EUR.".to_string(),
+            ));
+        }
+        if currency.minor_unit_digits > CurrencySettings::MAX_MINOR_UNIT_DIGITS {
+            return Err(AppError::Validation(format!(
+                "Currency minor unit digits must be at most {}.",
+                CurrencySettings::MAX_MINOR_UNIT_DIGITS
+            )));
+        }
+        if currency.minor_unit_digits != next.currency.minor_unit_digits {
+            rescale_digits = Some((next.currency.minor_unit_digits, currency.minor_unit_digits));
+        }
+        next.currency = currency;
+        changed = true;
+    }
+
+    if let Some(mut local_backup) = payload.local_backup {
+        // An empty folder turns local backups off.
+        local_backup.directory = local_backup
+            .directory
+            .map(|dir| dir.trim().to_string())
+            .filter(|dir| !dir.is_empty());
+        if let Some(dir) = &local_backup.directory {
+            if !std::path::Path::new(dir).is_absolute() {
+                return Err(AppError::Validation(
+                    "The local backup folder must be an absolute path.".to_string(),
+                ));
+            }
+        }
+        if local_backup.keep_daily == 0 {
+            return Err(AppError::Validation(
+                "At least one daily local backup must be kept.".to_string(),
+            ));
+        }
+        next.local_backup = local_backup;
         changed = true;
     }
 
     if changed {
-        save_settings(&app_handle, &settings).await?;
+        // Prices are stored in minor units, so they move with the settings.
+        let mut tx = app_state.db_pool().begin().await?;
+        if let Some((from_digits, to_digits)) = rescale_digits {
+            membership_types::rescale_prices(&mut tx, from_digits, to_digits).await?;
+        }
+        config::write_settings(&mut tx, &next).await?;
+        tx.commit().await?;
+        *settings = next;
+        audit::record(
+            &app_state.db_pool(),
+            &Actor::from(&session),
+            "update",
+            "settings",
+            None,
+            before,
+            audit::snapshot(&AppSettingsView::from(&*settings)),
+        )
+        .await?;
         app_handle
-            .emit("settings_changed", settings.clone())
+            .emit("settings_changed", AppSettingsView::from(&*settings))
             .unwrap_or_else(|e| {
                 tracing::warn!("Failed to emit settings_changed event: {}", e);
             });
@@ -332,72 +974,139 @@ pub async fn update_app_settings(
 }
 
 #[tauri::command]
-pub async fn get_remote_backup_metadata(
+pub async fn get_backup_encryption_status(
     app_state: tauri::State<'_, AppState>,
-) -> AppResult<Vec<BackupMetadata>> {
-    let backup_url = app_state.settings.read().await.backup_url.clone();
-    let gym_code = app_state.settings.read().await.gym_code.clone();
-
-    if backup_url.is_none() {
-        tracing::warn!("Backup URL is not configured!.");
-        return Err(AppError::Translatable(TranslatableError::new(
-            ErrorCodes::BACKUP_URL_NOT_SET,
-            "Backup URL is not configured!",
-        )));
-    }
-    let url_data = parse_backup_url(backup_url.unwrap().as_str());
-    if url_data.is_err() {
-        tracing::error!("Invalid backup URL format: {}", url_data.unwrap_err());
-        return Err(AppError::BackupFailed(
-            "Invalid backup URL format".to_string(),
-        ));
-    }
-    let (base_url, token) = url_data.unwrap();
-    let metadata_url = format!("{}/backup/metadata", base_url);
-
-    let client = reqwest::Client::new();
-    let res = client
-        .get(&metadata_url)
-        .header("X-Api-Key", token)
-        .header("X-Gym-Code", gym_code)
-        .send()
-        .await;
+) -> AppResult<BackupEncryptionStatus> {
+    auth::require_permission(&app_state, Permission::BackupManage).await?;
+    backup_encryption::status(&app_state.db_pool()).await
+}
 
-    let response = match res {
-        Ok(response) => response,
-        Err(e) => {
-            tracing::error!("Failed to send request to backup metadata endpoint: {}", e);
-            return Err(AppError::BackupFailed(
-                "Failed to get metadata!".to_string(),
-            ));
+/// Sets the passphrase backups are encrypted under. The first call creates
+/// the backup key; later ones need the current passphrase and keep the key,
+/// so the recovery key stays valid.
+#[tauri::command]
+pub async fn set_backup_passphrase(
+    app_state: tauri::State<'_, AppState>,
+    passphrase: String,
+    current_passphrase: Option<String>,
+) -> AppResult<()> {
+    let session = auth::require_permission(&app_state, Permission::BackupManage).await?;
+    let pool = app_state.db_pool();
+    let key = match backup_encryption::load_key(&pool).await? {
+        Some(current) => {
+            current.verify_passphrase(current_passphrase.as_deref().unwrap_or_default())?;
+            current.rewrap(&passphrase)?
         }
+        None => BackupKey::generate(&passphrase)?,
     };
-    let status = response.status();
-    let response_text = response.text().await.map_err(|e| {
-        tracing::error!("Failed to read response text: {}", e);
-        AppError::BackupFailed("Failed to read metadata response".to_string())
+    backup_encryption::save_key(&pool, &key).await?;
+    audit::record(
+        &pool,
+        &Actor::from(&session),
+        "set_passphrase",
+        "backup_encryption",
+        None,
+        None,
+        Some(serde_json::json!({ "key_id": key.key_id() })),
+    )
+    .await?;
+    Ok(())
+}
+
+/// The recovery key, for the admin to print or store somewhere safe. It opens
+/// every backup without the passphrase, so the passphrase is asked again.
+#[tauri::command]
+pub async fn export_backup_recovery_key(
+    app_state: tauri::State<'_, AppState>,
+    passphrase: String,
+) -> AppResult<String> {
+    let session = auth::require_permission(&app_state, Permission::BackupManage).await?;
+    let pool = app_state.db_pool();
+    let key = backup_encryption::load_key(&pool).await?.ok_or_else(|| {
+        AppError::Translatable(TranslatableError::new(
+            ErrorCodes::BACKUP_ENCRYPTION_NOT_SET,
+            "No backup passphrase has been set.",
+        ))
     })?;
+    key.verify_passphrase(&passphrase)?;
+    audit::record(
+        &pool,
+        &Actor::from(&session),
+        "export_recovery_key",
+        "backup_encryption",
+        None,
+        None,
+        Some(serde_json::json!({ "key_id": key.key_id() })),
+    )
+    .await?;
+    Ok(key.recovery_key())
+}
 
-    if status.is_success() {
-        // Parse the JSON from the string
-        serde_json::from_str::<Vec<BackupMetadata>>(&response_text).map_err(|e| {
-            tracing::error!("Failed to parse backup metadata response: {}", e);
-            AppError::BackupFailed("Failed to parse metadata response".to_string())
-        })
-    } else {
-        Err(AppError::BackupFailed(format!(
-            "Failed to get metadata! Status: {}",
-            status
-        )))
+/// Copies in the local backup folder and the free space left there. `None`
+/// when no folder is configured.
+#[tauri::command]
+pub async fn get_local_backup_status(
+    app_state: tauri::State<'_, AppState>,
+) -> AppResult<Option<LocalBackupStatus>> {
+    auth::require_permission(&app_state, Permission::BackupManage).await?;
+    let local = DirectoryStore::local_backup(&*app_state.settings.read().await);
+    match local {
+        Some(local) => Ok(Some(local.status().await?)),
+        None => Ok(None),
     }
 }
 
+/// Backups in the configured backup store, newest first.
+#[tauri::command]
+pub async fn get_remote_backup_metadata(
+    app_state: tauri::State<'_, AppState>,
+) -> AppResult<Vec<BackupMetadata>> {
+    auth::require_permission(&app_state, Permission::BackupManage).await?;
+    let store = backup_store::configured(&*app_state.settings.read().await)?;
+    store.list().await
+}
+
+#[tauri::command]
+pub async fn delete_backup(
+    app_state: tauri::State<'_, AppState>,
+    version_id: String,
+) -> AppResult<()> {
+    let session = auth::require_permission(&app_state, Permission::BackupManage).await?;
+    let store = backup_store::configured(&*app_state.settings.read().await)?;
+    store.delete(&version_id).await?;
+    audit::record(
+        &app_state.db_pool(),
+        &Actor::from(&session),
+        "delete",
+        "backup",
+        None,
+        Some(serde_json::json!({ "version_id": version_id })),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn trigger_backup(app_handle: tauri::AppHandle) -> AppResult<()> {
+    let app_state = app_handle.state::<AppState>();
+    let session = auth::require_permission(&app_state, Permission::BackupManage).await?;
     tracing::info!("Triggering manual backup!");
-    let result = manual_trigger_backup(app_handle).await;
+    let result = manual_trigger_backup(app_handle.clone()).await;
     match result {
-        Ok(_) => Ok(()),
+        Ok(_) => {
+            audit::record(
+                &app_state.db_pool(),
+                &Actor::from(&session),
+                "backup",
+                "database",
+                None,
+                None,
+                None,
+            )
+            .await?;
+            Ok(())
+        }
         Err(e) => {
             tracing::info!("Manual backup failed");
             return Err(AppError::BackupFailed(format!(
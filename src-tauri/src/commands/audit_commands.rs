@@ -0,0 +1,76 @@
+use crate::auth::{self, Permission};
+use crate::dto::{AuditLogQueryParams, PaginatedResponse};
+use crate::{error::Result as AppResult, models::AuditLogEntry, state::AppState};
+use tauri::State;
+
+fn normalize_filter(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+#[tauri::command]
+pub async fn get_audit_log(
+    payload: AuditLogQueryParams,
+    state: State<'_, AppState>,
+) -> AppResult<PaginatedResponse<AuditLogEntry>> {
+    auth::require_permission(&state, Permission::AuditView).await?;
+
+    let page = payload.page.unwrap_or(1).max(1);
+    let per_page = payload.per_page.unwrap_or(50).min(200).max(1);
+    let offset = (page - 1) * per_page;
+    let actor_username = normalize_filter(payload.actor_username);
+    let action = normalize_filter(payload.action);
+    let entity_type = normalize_filter(payload.entity_type);
+
+    let where_clause = r#"
+        (?1 IS NULL OR actor_username = ?1)
+        AND (?2 IS NULL OR action = ?2)
+        AND (?3 IS NULL OR entity_type = ?3)
+        AND (?4 IS NULL OR entity_id = ?4)
+        AND (?5 IS NULL OR created_at >= ?5)
+        AND (?6 IS NULL OR created_at <= ?6)
+    "#;
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM audit_log WHERE {}",
+        where_clause
+    ))
+    .bind(&actor_username)
+    .bind(&action)
+    .bind(&entity_type)
+    .bind(payload.entity_id)
+    .bind(payload.from)
+    .bind(payload.to)
+    .fetch_one(&state.db_pool())
+    .await?;
+
+    let entries = sqlx::query_as::<_, AuditLogEntry>(&format!(
+        r#"
+        SELECT id, actor_user_id, actor_username, action, entity_type, entity_id, before_data, after_data, created_at
+        FROM audit_log
+        WHERE {}
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?7 OFFSET ?8
+        "#,
+        where_clause
+    ))
+    .bind(&actor_username)
+    .bind(&action)
+    .bind(&entity_type)
+    .bind(payload.entity_id)
+    .bind(payload.from)
+    .bind(payload.to)
+    .bind(per_page as i64)
+    .bind(offset as i64)
+    .fetch_all(&state.db_pool())
+    .await?;
+
+    Ok(PaginatedResponse {
+        data: entries,
+        total,
+        total_pages: ((total as f64) / (per_page as f64)).ceil() as i64,
+        page,
+        per_page,
+    })
+}
@@ -0,0 +1,77 @@
+use crate::audit::{self, Actor};
+use crate::auth::{self, Permission};
+use crate::dto::{DatabaseHealth, MaintenanceRunQueryParams, PaginatedResponse};
+use crate::maintenance::{self, MaintenanceTask};
+use crate::{error::Result as AppResult, models::MaintenanceRun, state::AppState};
+use tauri::State;
+
+/// Runs the given maintenance tasks now, or all of them when none are given.
+#[tauri::command]
+pub async fn run_database_maintenance(
+    tasks: Option<Vec<MaintenanceTask>>,
+    state: State<'_, AppState>,
+) -> AppResult<MaintenanceRun> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+    let tasks = match tasks {
+        Some(tasks) if !tasks.is_empty() => tasks,
+        _ => MaintenanceTask::ALL.to_vec(),
+    };
+
+    let pool = state.db_pool();
+    let db_path = state.database_path.read().await.clone();
+    let actor = Actor::from(&session);
+    let run = maintenance::run_and_record(&pool, &db_path, &tasks, "manual", &actor).await?;
+
+    audit::record(
+        &pool,
+        &actor,
+        "maintenance",
+        "database",
+        Some(run.id),
+        None,
+        Some(serde_json::json!({ "tasks": tasks, "status": run.status })),
+    )
+    .await?;
+    Ok(run)
+}
+
+#[tauri::command]
+pub async fn get_maintenance_runs(
+    payload: MaintenanceRunQueryParams,
+    state: State<'_, AppState>,
+) -> AppResult<PaginatedResponse<MaintenanceRun>> {
+    auth::require_permission(&state, Permission::SettingsManage).await?;
+
+    let page = payload.page.unwrap_or(1).max(1);
+    let per_page = payload.per_page.unwrap_or(20).min(200).max(1);
+    let offset = (page - 1) * per_page;
+
+    let pool = state.db_pool();
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM maintenance_runs")
+        .fetch_one(&pool)
+        .await?;
+    let runs = maintenance::fetch_runs(&pool, per_page as i64, offset as i64).await?;
+
+    Ok(PaginatedResponse {
+        data: runs,
+        total,
+        total_pages: ((total as f64) / (per_page as f64)).ceil() as i64,
+        page,
+        per_page,
+    })
+}
+
+#[tauri::command]
+pub async fn get_database_health(state: State<'_, AppState>) -> AppResult<DatabaseHealth> {
+    auth::require_permission(&state, Permission::SettingsManage).await?;
+
+    let pool = state.db_pool();
+    let last_run = maintenance::fetch_runs(&pool, 1, 0)
+        .await?
+        .into_iter()
+        .next();
+    Ok(DatabaseHealth {
+        last_run,
+        last_verified_healthy_at: maintenance::last_verified_healthy_at(&pool).await?,
+    })
+}
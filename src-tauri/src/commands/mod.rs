@@ -1,6 +1,12 @@
 pub mod admin_commands;
 pub mod analytics_commands;
+pub mod audit_commands;
 pub mod entry_log_commands;
+pub mod maintenance_commands;
 pub mod member_commands;
 pub mod membership_commands;
 pub mod membership_type_commands;
+pub mod operator_commands;
+pub mod profile_commands;
+pub mod role_commands;
+pub mod schedule_commands;
@@ -0,0 +1,115 @@
+use crate::{
+    audit::{self, Actor},
+    auth::{self, Operator, Permission},
+    error::Result as AppResult,
+    state::AppState,
+    utils, AppError,
+};
+use tauri::State;
+
+/// Sets or clears (`pin: None`) a user's operator PIN. Users manage their own
+/// PIN; setting someone else's needs user management rights.
+#[tauri::command]
+pub async fn set_operator_pin(
+    state: State<'_, AppState>,
+    user_id: i64,
+    pin: Option<String>,
+) -> AppResult<()> {
+    let session = auth::require_session(&state).await?;
+    if session.user_id != user_id {
+        auth::require_permission(&state, Permission::UsersManage).await?;
+    }
+
+    let pin_hash = match pin.as_deref().map(str::trim) {
+        Some(pin) => {
+            auth::validate_operator_pin(pin)?;
+            Some(utils::hash_password(pin)?)
+        }
+        None => None,
+    };
+
+    let mut tx = state.db_pool().begin().await?;
+    let result =
+        sqlx::query("UPDATE users SET pin_hash = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(&pin_hash)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        if pin_hash.is_some() {
+            "operator_pin_set"
+        } else {
+            "operator_pin_clear"
+        },
+        "user",
+        Some(user_id),
+        None,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Staff who can switch in at the front desk, i.e. users with a PIN.
+#[tauri::command]
+pub async fn get_operators(state: State<'_, AppState>) -> AppResult<Vec<Operator>> {
+    auth::require_session(&state).await?;
+
+    let operators: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, username FROM users WHERE pin_hash IS NOT NULL ORDER BY username",
+    )
+    .fetch_all(&state.db_pool())
+    .await?;
+
+    Ok(operators
+        .into_iter()
+        .map(|(user_id, username)| Operator { user_id, username })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn get_active_operator(state: State<'_, AppState>) -> AppResult<Option<Operator>> {
+    auth::require_session(&state).await?;
+    Ok(auth::get_active_operator(&state).await)
+}
+
+/// Makes another staff member the active operator on the current session.
+#[tauri::command]
+pub async fn switch_operator(
+    state: State<'_, AppState>,
+    username: String,
+    pin: String,
+) -> AppResult<Operator> {
+    let session = auth::require_session(&state).await?;
+    let lockout_policy = state.settings.read().await.login_lockout.clone();
+    let operator =
+        auth::verify_operator_pin(&state.db_pool(), &username, &pin, &lockout_policy).await?;
+
+    audit::record(
+        &state.db_pool(),
+        &Actor::from(&session),
+        "operator_switch",
+        "user",
+        Some(operator.user_id),
+        auth::get_active_operator(&state)
+            .await
+            .map(|previous| serde_json::json!({ "operator": previous.username })),
+        Some(serde_json::json!({ "operator": operator.username })),
+    )
+    .await?;
+    auth::set_active_operator(&state, operator.user_id, &operator.username).await;
+
+    tracing::info!(
+        "Operator switched to '{}' on session of '{}'.",
+        operator.username,
+        session.username
+    );
+    Ok(operator)
+}
@@ -0,0 +1,337 @@
+use crate::{
+    audit::{self, Actor},
+    auth::{self, Permission},
+    config::{self, AppSettings},
+    db::{self, sibling_path},
+    dto::AppSettingsView,
+    error::Result as AppResult,
+    profiles::{self, Profile},
+    services::backup,
+    state::AppState,
+    utils, AppError,
+};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Serializes edits of the profile registry file.
+static REGISTRY_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(Serialize)]
+pub struct ProfileList {
+    active_profile_id: String,
+    profiles: Vec<Profile>,
+}
+
+/// Lists the gym profiles. Available before login so the front desk can pick
+/// the location to log into.
+#[tauri::command]
+pub async fn get_profiles(app_handle: AppHandle) -> AppResult<ProfileList> {
+    let registry = profiles::load_registry(&app_handle).await?;
+    Ok(ProfileList {
+        active_profile_id: registry.active_profile_id,
+        profiles: registry.profiles,
+    })
+}
+
+/// Creates a profile with a fresh database (default admin user included) and
+/// settings named after it. The current profile stays open.
+#[tauri::command]
+pub async fn create_profile(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+) -> AppResult<Profile> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::Validation(
+            "Profile name cannot be empty.".to_string(),
+        ));
+    }
+
+    let _registry_guard = REGISTRY_LOCK.lock().await;
+    let mut registry = profiles::load_registry(&app_handle).await?;
+    if registry
+        .profiles
+        .iter()
+        .any(|p| p.name.eq_ignore_ascii_case(&name))
+    {
+        return Err(AppError::Validation(format!(
+            "A profile named '{}' already exists.",
+            name
+        )));
+    }
+
+    let profile = registry.new_profile(&name);
+    let db_path = profiles::profile_database_path(&app_handle, &profile)?;
+    if db_path.exists() {
+        return Err(AppError::Config(format!(
+            "Database file {:?} already exists.",
+            db_path
+        )));
+    }
+
+    let pool = db::init_db(&db_path).await?;
+    let settings = AppSettings {
+        gym_name: name.clone(),
+        ..AppSettings::default()
+    };
+    let saved = config::save_settings(&pool, &settings).await;
+    pool.close().await;
+    saved?;
+
+    registry.profiles.push(profile.clone());
+    profiles::save_registry(&app_handle, &registry).await?;
+
+    audit::record(
+        &state.db_pool(),
+        &Actor::from(&session),
+        "create",
+        "profile",
+        None,
+        None,
+        audit::snapshot(&profile),
+    )
+    .await?;
+    tracing::info!("Created profile '{}' at {:?}.", profile.name, db_path);
+    Ok(profile)
+}
+
+/// Opens another profile's database. The current session ends, since users
+/// belong to a profile. Before login anyone may pick a profile; once someone
+/// is logged in, switching away needs the settings permission.
+#[tauri::command]
+pub async fn switch_profile(app_handle: AppHandle, profile_id: String) -> AppResult<Profile> {
+    let state = app_handle.state::<AppState>();
+    let logged_in = state.session.read().await.is_some();
+    if logged_in {
+        auth::require_permission(&state, Permission::SettingsManage).await?;
+    }
+
+    let _registry_guard = REGISTRY_LOCK.lock().await;
+    let mut registry = profiles::load_registry(&app_handle).await?;
+    let profile = registry
+        .find(&profile_id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("Profile '{}' not found", profile_id)))?;
+    if state.profile.read().await.id == profile.id {
+        return Ok(profile);
+    }
+
+    let db_path = profiles::profile_database_path(&app_handle, &profile)?;
+    let pool = db::init_db(&db_path).await?;
+    let settings = match config::load_settings(&app_handle, &pool).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            pool.close().await;
+            return Err(e);
+        }
+    };
+
+    registry.active_profile_id = profile.id.clone();
+    profiles::save_registry(&app_handle, &registry).await?;
+
+    let previous_pool = state
+        .switch_database(pool, settings.clone(), profile.clone(), db_path)
+        .await;
+    // Let commands still running on the old database finish before closing it.
+    tauri::async_runtime::spawn(async move {
+        previous_pool.close().await;
+    });
+    utils::backfill_entry_local_dates(&state).await;
+
+    tracing::info!("Switched to profile '{}'.", profile.name);
+    app_handle
+        .emit("profile_changed", profile.clone())
+        .unwrap_or_else(|e| tracing::warn!("Failed to emit profile_changed event: {}", e));
+    app_handle
+        .emit("settings_changed", AppSettingsView::from(&settings))
+        .unwrap_or_else(|e| tracing::warn!("Failed to emit settings_changed event: {}", e));
+    Ok(profile)
+}
+
+/// Removes a profile from the registry. Its database is renamed, not
+/// deleted, so it can still be recovered by hand.
+#[tauri::command]
+pub async fn delete_profile(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    profile_id: String,
+) -> AppResult<()> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+
+    let _registry_guard = REGISTRY_LOCK.lock().await;
+    let mut registry = profiles::load_registry(&app_handle).await?;
+    let profile = registry
+        .find(&profile_id)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("Profile '{}' not found", profile_id)))?;
+    if profile.id == registry.active_profile_id || state.profile.read().await.id == profile.id {
+        return Err(AppError::Validation(
+            "The profile in use cannot be deleted. Switch to another profile first.".to_string(),
+        ));
+    }
+
+    let db_path = profiles::profile_database_path(&app_handle, &profile)?;
+    let suffix = format!(".deleted-{}", chrono::Utc::now().format("%Y%m%d%H%M%S"));
+    for extension in ["", "-wal", "-shm"] {
+        let file_name = format!("{}{}", profile.database_file, extension);
+        let path = db_path.with_file_name(&file_name);
+        if path.exists() {
+            tokio::fs::rename(
+                &path,
+                db_path.with_file_name(format!("{}{}", file_name, suffix)),
+            )
+            .await?;
+        }
+    }
+
+    registry.profiles.retain(|p| p.id != profile.id);
+    profiles::save_registry(&app_handle, &registry).await?;
+
+    audit::record(
+        &state.db_pool(),
+        &Actor::from(&session),
+        "delete",
+        "profile",
+        None,
+        audit::snapshot(&profile),
+        None,
+    )
+    .await?;
+    tracing::info!(
+        "Deleted profile '{}'; its database was kept as {}{}.",
+        profile.name,
+        profile.database_file,
+        suffix
+    );
+    Ok(())
+}
+
+/// Copies the live database to `path`, checks the copy and opens it. The old
+/// files are left untouched.
+async fn copy_database(from: &Path, to: &Path) -> AppResult<SqlitePool> {
+    let temp_path = sibling_path(to, ".tmp");
+
+    tokio::fs::copy(from, &temp_path).await?;
+    if let Err(e) = backup::check_db_integrity(&temp_path).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&temp_path, to).await?;
+    db::init_db(to).await
+}
+
+/// Moves the open profile's database to `target_dir`, or back to the app data
+/// directory when no directory is given. The WAL is checkpointed into the
+/// database file, which is then copied and checked before the app switches
+/// over; the old files are only removed once the new copy is in use.
+#[tauri::command]
+pub async fn relocate_database(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    target_dir: Option<String>,
+) -> AppResult<Profile> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+
+    let target_dir = match target_dir.map(|dir| dir.trim().to_string()) {
+        Some(dir) if !dir.is_empty() => {
+            let dir = PathBuf::from(dir);
+            if !dir.is_absolute() || !dir.is_dir() {
+                return Err(AppError::Validation(format!(
+                    "{:?} is not an existing directory.",
+                    dir
+                )));
+            }
+            Some(dir.canonicalize()?)
+        }
+        _ => None,
+    };
+
+    let _registry_guard = REGISTRY_LOCK.lock().await;
+    let mut registry = profiles::load_registry(&app_handle).await?;
+    let current_profile = state.profile.read().await.clone();
+    let profile = Profile {
+        database_dir: target_dir,
+        ..current_profile.clone()
+    };
+    let old_path = state.database_path.read().await.clone();
+    let new_path = profiles::profile_database_path(&app_handle, &profile)?;
+    if new_path.exists() {
+        return Err(AppError::Validation(format!(
+            "A database file already exists at {:?}.",
+            new_path
+        )));
+    }
+
+    tracing::info!("Relocating database from {:?} to {:?}.", old_path, new_path);
+    let old_pool = state.db_pool();
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&old_pool)
+        .await?;
+    // Closing the last connection checkpoints whatever was written since, so
+    // the database file alone holds all data.
+    old_pool.close().await;
+
+    let moved = match copy_database(&old_path, &new_path).await {
+        Ok(new_pool) => {
+            if let Some(entry) = registry.profiles.iter_mut().find(|p| p.id == profile.id) {
+                *entry = profile.clone();
+            }
+            match profiles::save_registry(&app_handle, &registry).await {
+                Ok(()) => Ok(new_pool),
+                Err(e) => {
+                    new_pool.close().await;
+                    Err(e)
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    let new_pool = match moved {
+        Ok(new_pool) => new_pool,
+        Err(e) => {
+            tracing::error!(
+                "Database relocation failed, reopening {:?}: {}",
+                old_path,
+                e
+            );
+            for suffix in ["", "-wal", "-shm", ".tmp"] {
+                let _ = tokio::fs::remove_file(sibling_path(&new_path, suffix)).await;
+            }
+            let pool = db::init_db(&old_path).await?;
+            state.replace_pool(pool, old_path).await;
+            return Err(e);
+        }
+    };
+    state.replace_pool(new_pool, new_path.clone()).await;
+    *state.profile.write().await = profile.clone();
+
+    for suffix in ["", "-wal", "-shm"] {
+        let path = sibling_path(&old_path, suffix);
+        if path.exists() {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                tracing::warn!("Could not remove old database file {:?}: {}", path, e);
+            }
+        }
+    }
+
+    audit::record(
+        &state.db_pool(),
+        &Actor::from(&session),
+        "relocate",
+        "database",
+        None,
+        Some(serde_json::json!({ "path": old_path })),
+        Some(serde_json::json!({ "path": new_path })),
+    )
+    .await?;
+    tracing::info!("Database relocated to {:?}.", new_path);
+    app_handle
+        .emit("profile_changed", profile.clone())
+        .unwrap_or_else(|e| tracing::warn!("Failed to emit profile_changed event: {}", e));
+    Ok(profile)
+}
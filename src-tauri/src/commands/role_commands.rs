@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use crate::audit::{self, Actor};
+use crate::auth::{self, Permission};
+use crate::dto::{PermissionDisplay, RoleDisplay, RolePayload};
+use crate::error::{ErrorCodes, TranslatableError};
+use crate::{
+    error::{AppError, Result as AppResult},
+    models::Role,
+    state::AppState,
+};
+use sqlx::SqlitePool;
+use tauri::State;
+
+// The admin role keeps every permission so nobody can lock themselves out
+// of user management.
+const ADMIN_ROLE_NAME: &str = "admin";
+
+async fn load_role_display(pool: &SqlitePool, role: Role) -> AppResult<RoleDisplay> {
+    let mut permissions: Vec<Permission> = auth::load_role_permissions(pool, &role.name)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    permissions.sort_by_key(|p| p.as_str());
+
+    let user_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = ?")
+        .bind(&role.name)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(RoleDisplay {
+        id: role.id,
+        name: role.name,
+        description: role.description,
+        is_system: role.is_system,
+        permissions,
+        user_count,
+    })
+}
+
+fn role_audit_snapshot(role: RoleDisplay) -> serde_json::Value {
+    serde_json::json!({
+        "name": role.name,
+        "description": role.description,
+        "permissions": role.permissions,
+    })
+}
+
+async fn fetch_role(pool: &SqlitePool, id: i64) -> AppResult<Role> {
+    sqlx::query_as::<_, Role>(
+        "SELECT id, name, description, is_system, created_at, updated_at FROM roles WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Role with id {} not found.", id)))
+}
+
+#[tauri::command]
+pub async fn get_all_permissions(state: State<'_, AppState>) -> AppResult<Vec<PermissionDisplay>> {
+    auth::require_permission(&state, Permission::UsersManage).await?;
+
+    let permissions = sqlx::query_as::<_, PermissionDisplay>(
+        "SELECT code, description FROM permissions ORDER BY code ASC",
+    )
+    .fetch_all(&state.db_pool())
+    .await?;
+
+    Ok(permissions)
+}
+
+#[tauri::command]
+pub async fn get_all_roles(state: State<'_, AppState>) -> AppResult<Vec<RoleDisplay>> {
+    auth::require_permission(&state, Permission::UsersManage).await?;
+
+    let roles = sqlx::query_as::<_, Role>(
+        "SELECT id, name, description, is_system, created_at, updated_at FROM roles ORDER BY name ASC",
+    )
+    .fetch_all(&state.db_pool())
+    .await?;
+
+    let mut result = Vec::with_capacity(roles.len());
+    for role in roles {
+        result.push(load_role_display(&state.db_pool(), role).await?);
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn save_role(payload: RolePayload, state: State<'_, AppState>) -> AppResult<RoleDisplay> {
+    let session = auth::require_permission(&state, Permission::UsersManage).await?;
+
+    let name = payload.name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(AppError::Validation(
+            "Role name cannot be empty".to_string(),
+        ));
+    }
+    let permissions: HashSet<Permission> = payload.permissions.into_iter().collect();
+
+    let mut tx = state.db_pool().begin().await?;
+    let now = chrono::Utc::now().naive_utc();
+    let mut before = None;
+
+    let role_id = match payload.id {
+        Some(id) => {
+            let existing = fetch_role(&state.db_pool(), id).await?;
+            before = Some(role_audit_snapshot(
+                load_role_display(&state.db_pool(), existing.clone()).await?,
+            ));
+            if existing.is_system && existing.name != name {
+                return Err(AppError::Validation(format!(
+                    "System role '{}' cannot be renamed",
+                    existing.name
+                )));
+            }
+            if existing.name == ADMIN_ROLE_NAME {
+                return Err(AppError::Validation(
+                    "Permissions of the admin role cannot be changed".to_string(),
+                ));
+            }
+
+            let result = sqlx::query(
+                "UPDATE roles SET name = ?, description = ?, updated_at = ? WHERE id = ?",
+            )
+            .bind(&name)
+            .bind(&payload.description)
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await;
+            match result {
+                Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                    return Err(AppError::Translatable(TranslatableError::with_params(
+                        ErrorCodes::ROLE_NAME_EXISTS,
+                        serde_json::json!({ "name": name }),
+                        "Role name already exists",
+                    )));
+                }
+                Err(e) => return Err(AppError::Sqlx(e)),
+                Ok(_) => {}
+            }
+
+            if existing.name != name {
+                sqlx::query("UPDATE users SET role = ? WHERE role = ?")
+                    .bind(&name)
+                    .bind(&existing.name)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            sqlx::query("DELETE FROM role_permissions WHERE role_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            id
+        }
+        None => {
+            let result = sqlx::query(
+                "INSERT INTO roles (name, description, is_system, created_at, updated_at) VALUES (?, ?, FALSE, ?, ?)",
+            )
+            .bind(&name)
+            .bind(&payload.description)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await;
+            match result {
+                Ok(query_result) => query_result.last_insert_rowid(),
+                Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                    return Err(AppError::Translatable(TranslatableError::with_params(
+                        ErrorCodes::ROLE_NAME_EXISTS,
+                        serde_json::json!({ "name": name }),
+                        "Role name already exists",
+                    )));
+                }
+                Err(e) => return Err(AppError::Sqlx(e)),
+            }
+        }
+    };
+
+    for permission in &permissions {
+        sqlx::query("INSERT INTO role_permissions (role_id, permission) VALUES (?, ?)")
+            .bind(role_id)
+            .bind(permission.as_str())
+            .execute(&mut *tx)
+            .await?;
+    }
+    let mut granted: Vec<Permission> = permissions.iter().copied().collect();
+    granted.sort_by_key(|p| p.as_str());
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        if before.is_some() { "update" } else { "create" },
+        "role",
+        Some(role_id),
+        before,
+        Some(serde_json::json!({
+            "name": name,
+            "description": payload.description,
+            "permissions": granted,
+        })),
+    )
+    .await?;
+    tx.commit().await?;
+
+    auth::refresh_session_permissions(&state, &name, &permissions).await;
+    tracing::info!("Role '{}' saved by '{}'.", name, session.username);
+
+    let role = fetch_role(&state.db_pool(), role_id).await?;
+    load_role_display(&state.db_pool(), role).await
+}
+
+#[tauri::command]
+pub async fn delete_role(id: i64, state: State<'_, AppState>) -> AppResult<()> {
+    let session = auth::require_permission(&state, Permission::UsersManage).await?;
+
+    let role = fetch_role(&state.db_pool(), id).await?;
+    if role.is_system {
+        return Err(AppError::Validation(format!(
+            "System role '{}' cannot be deleted",
+            role.name
+        )));
+    }
+
+    let user_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = ?")
+        .bind(&role.name)
+        .fetch_one(&state.db_pool())
+        .await?;
+    if user_count > 0 {
+        return Err(AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::ROLE_IN_USE,
+            serde_json::json!({ "name": role.name, "user_count": user_count }),
+            "Role is still assigned to users",
+        )));
+    }
+
+    let before = load_role_display(&state.db_pool(), role.clone()).await?;
+    let mut tx = state.db_pool().begin().await?;
+    sqlx::query("DELETE FROM roles WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "delete",
+        "role",
+        Some(id),
+        Some(role_audit_snapshot(before)),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!("Role '{}' deleted by '{}'.", role.name, session.username);
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use crate::audit::{self, Actor};
+use crate::auth::{self, Permission};
+use crate::dto::{ClosurePayload, DaySchedule, OpeningHoursPayload};
+use crate::{
+    error::{AppError, Result as AppResult},
+    models::{Closure, OpeningHours},
+    schedule,
+    state::AppState,
+};
+use chrono::{NaiveDate, Utc};
+use std::collections::HashSet;
+use tauri::State;
+
+const CLOSURE_SELECT: &str =
+    "SELECT id, date, opens_at, closes_at, reason, created_at, updated_at FROM closures";
+
+async fn fetch_closure_for_audit(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    id: i64,
+) -> AppResult<Option<Closure>> {
+    let closure = sqlx::query_as::<_, Closure>(&format!("{} WHERE id = ?", CLOSURE_SELECT))
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    Ok(closure)
+}
+
+#[tauri::command]
+pub async fn get_opening_hours(state: State<'_, AppState>) -> AppResult<Vec<OpeningHours>> {
+    auth::require_session(&state).await?;
+
+    let hours = sqlx::query_as::<_, OpeningHours>(
+        "SELECT weekday, is_closed, opens_at, closes_at, updated_at FROM opening_hours ORDER BY weekday",
+    )
+    .fetch_all(&state.db_pool())
+    .await?;
+    Ok(hours)
+}
+
+/// Replaces the weekly opening hours. Weekdays left out are open all day.
+#[tauri::command]
+pub async fn save_opening_hours(
+    payload: Vec<OpeningHoursPayload>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<OpeningHours>> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+
+    let mut seen = HashSet::new();
+    for hours in &payload {
+        if !(0..=6).contains(&hours.weekday) {
+            return Err(AppError::Validation(format!(
+                "Invalid weekday {}, expected 0 (Monday) to 6 (Sunday).",
+                hours.weekday
+            )));
+        }
+        if !seen.insert(hours.weekday) {
+            return Err(AppError::Validation(format!(
+                "Weekday {} is listed more than once.",
+                hours.weekday
+            )));
+        }
+        schedule::validate_times(hours.opens_at, hours.closes_at)?;
+    }
+
+    let mut tx = state.db_pool().begin().await?;
+    let before = sqlx::query_as::<_, OpeningHours>(
+        "SELECT weekday, is_closed, opens_at, closes_at, updated_at FROM opening_hours ORDER BY weekday",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM opening_hours")
+        .execute(&mut *tx)
+        .await?;
+    let now = Utc::now().naive_utc();
+    for hours in &payload {
+        sqlx::query(
+            "INSERT INTO opening_hours (weekday, is_closed, opens_at, closes_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(hours.weekday)
+        .bind(hours.is_closed)
+        .bind(hours.opens_at)
+        .bind(hours.closes_at)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let after = sqlx::query_as::<_, OpeningHours>(
+        "SELECT weekday, is_closed, opens_at, closes_at, updated_at FROM opening_hours ORDER BY weekday",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "update",
+        "opening_hours",
+        None,
+        audit::snapshot(&serde_json::json!({ "hours": before })),
+        audit::snapshot(&serde_json::json!({ "hours": after })),
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!("Opening hours updated for {} weekdays.", after.len());
+    Ok(after)
+}
+
+#[tauri::command]
+pub async fn get_closures(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<Closure>> {
+    auth::require_session(&state).await?;
+
+    let closures = sqlx::query_as::<_, Closure>(&format!(
+        "{} WHERE (?1 IS NULL OR date >= ?1) AND (?2 IS NULL OR date <= ?2) ORDER BY date",
+        CLOSURE_SELECT
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_all(&state.db_pool())
+    .await?;
+    Ok(closures)
+}
+
+/// Creates or updates a closure. Without opening times the gym is closed for
+/// the whole day.
+#[tauri::command]
+pub async fn save_closure(
+    payload: ClosurePayload,
+    state: State<'_, AppState>,
+) -> AppResult<Closure> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+    schedule::validate_times(payload.opens_at, payload.closes_at)?;
+    let reason = payload
+        .reason
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty());
+
+    let mut tx = state.db_pool().begin().await?;
+    let date_taken: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM closures WHERE date = ? AND (?2 IS NULL OR id != ?2)")
+            .bind(payload.date)
+            .bind(payload.id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if date_taken.is_some() {
+        return Err(AppError::Validation(format!(
+            "There is already a closure on {}.",
+            payload.date
+        )));
+    }
+
+    let now = Utc::now().naive_utc();
+    let (closure_id, before) = match payload.id {
+        Some(id) => {
+            let before = fetch_closure_for_audit(&mut tx, id)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("Closure with id {} not found", id)))?;
+            sqlx::query(
+                "UPDATE closures SET date = ?, opens_at = ?, closes_at = ?, reason = ?, updated_at = ? WHERE id = ?",
+            )
+            .bind(payload.date)
+            .bind(payload.opens_at)
+            .bind(payload.closes_at)
+            .bind(&reason)
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+            (id, Some(before))
+        }
+        None => {
+            let result = sqlx::query(
+                "INSERT INTO closures (date, opens_at, closes_at, reason, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(payload.date)
+            .bind(payload.opens_at)
+            .bind(payload.closes_at)
+            .bind(&reason)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            (result.last_insert_rowid(), None)
+        }
+    };
+
+    let after = fetch_closure_for_audit(&mut tx, closure_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Closure with id {} not found", closure_id)))?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        if before.is_some() { "update" } else { "create" },
+        "closure",
+        Some(closure_id),
+        before.as_ref().and_then(audit::snapshot),
+        audit::snapshot(&after),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(after)
+}
+
+#[tauri::command]
+pub async fn delete_closure(id: i64, state: State<'_, AppState>) -> AppResult<()> {
+    let session = auth::require_permission(&state, Permission::SettingsManage).await?;
+
+    let mut tx = state.db_pool().begin().await?;
+    let before = fetch_closure_for_audit(&mut tx, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Closure with id {} not found", id)))?;
+    sqlx::query("DELETE FROM closures WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut *tx,
+        &Actor::from(&session),
+        "delete",
+        "closure",
+        Some(id),
+        audit::snapshot(&before),
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Today's opening times in the gym's timezone, for the front desk.
+#[tauri::command]
+pub async fn get_today_schedule(state: State<'_, AppState>) -> AppResult<DaySchedule> {
+    auth::require_session(&state).await?;
+
+    let clock = state.gym_clock().await?;
+
+    let mut conn = state.db_pool().acquire().await?;
+    schedule::today_schedule(&mut conn, &clock).await
+}
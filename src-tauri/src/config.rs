@@ -1,14 +1,37 @@
+use crate::db;
 use crate::error::{AppError, Result};
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde_json::{Map, Value};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, SqliteConnection, SqlitePool};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Manager};
 use tokio::fs; // Use tokio's async fs
 
 const CONFIG_FILENAME: &str = "app_settings.json";
 
+/// Settings version written by this build. Bump it together with a new entry in
+/// [`SETTINGS_MIGRATIONS`] whenever a field is renamed, moved or reinterpreted;
+/// plain additions only need a serde default.
+pub const CURRENT_SETTINGS_VERSION: u32 = 3;
+
+/// Settings saved before versioning was introduced.
+const UNVERSIONED_SETTINGS_VERSION: u32 = 1;
+
+type SettingsMigration = fn(&mut Map<String, Value>);
+
+/// Upgrade steps, where entry `i` takes settings from version `i + 1` to `i + 2`.
+const SETTINGS_MIGRATIONS: [SettingsMigration; (CURRENT_SETTINGS_VERSION - 1) as usize] =
+    [migrate_settings_v1_to_v2, migrate_settings_v2_to_v3];
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct AppSettings {
-    pub backup_url: Option<String>,
+    pub settings_version: u32,
+    /// Where scheduled backups go.
+    pub backup_store: BackupStoreSettings,
     pub backup_period_hours: Option<u64>,
     pub language: String,
     pub theme: String,
@@ -16,14 +39,360 @@ pub struct AppSettings {
     pub backup_enabled: bool,
     pub gym_name: String,
     pub gym_code: String,
+    pub login_lockout: LoginLockoutSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub check_in_policy: CheckInPolicy,
+    /// Days a deleted member stays in the trash before it is purged for good.
+    /// 0 keeps deleted members until they are purged by hand.
+    pub member_trash_retention_days: u32,
+    pub currency: CurrencySettings,
+    pub local_backup: LocalBackupSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoginLockoutSettings {
+    /// Failed attempts allowed before the account is locked.
+    pub max_failed_attempts: u32,
+    /// Length of the first lockout; every further lockout doubles it.
+    pub base_lockout_minutes: u64,
+    pub max_lockout_minutes: u64,
+}
+
+impl Default for LoginLockoutSettings {
+    fn default() -> Self {
+        Self {
+            max_failed_attempts: 5,
+            base_lockout_minutes: 1,
+            max_lockout_minutes: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PasswordPolicySettings {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Number of previous passwords that may not be reused.
+    pub history_size: u32,
+}
+
+impl Default for PasswordPolicySettings {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: true,
+            require_symbol: false,
+            history_size: 3,
+        }
+    }
+}
+
+/// Storage backend for backups, see `services::backup_store`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackupStoreSettings {
+    #[default]
+    Disabled,
+    /// The backup gateway, addressed by a URL that carries a `token` query
+    /// parameter.
+    Http { url: String },
+    /// An S3-compatible bucket, e.g. AWS S3 or MinIO.
+    S3(S3StoreSettings),
+    #[serde(rename = "webdav")]
+    WebDav(WebDavStoreSettings),
+    /// A folder on this PC or on a network share.
+    Directory { path: String },
+}
+
+impl BackupStoreSettings {
+    pub fn is_configured(&self) -> bool {
+        !matches!(self, BackupStoreSettings::Disabled)
+    }
+
+    /// Whether a secret is set: the gateway token, the S3 secret key or the
+    /// WebDAV password.
+    pub fn has_secret(&self) -> bool {
+        match self {
+            BackupStoreSettings::Http { url } => {
+                backup_url_token(url).is_some_and(|token| !token.is_empty())
+            }
+            BackupStoreSettings::S3(s3) => !s3.secret_access_key.is_empty(),
+            BackupStoreSettings::WebDav(webdav) => {
+                webdav.password.as_deref().is_some_and(|p| !p.is_empty())
+            }
+            _ => false,
+        }
+    }
+
+    /// These settings with the secrets blanked, for showing them.
+    pub fn without_secrets(&self) -> Self {
+        let mut store = self.clone();
+        match &mut store {
+            BackupStoreSettings::Http { url } => *url = with_backup_url_token(url, ""),
+            BackupStoreSettings::S3(s3) => s3.secret_access_key.clear(),
+            BackupStoreSettings::WebDav(webdav) => webdav.password = None,
+            _ => {}
+        }
+        store
+    }
+
+    /// Fills secrets left blank with those of `current`, the settings being
+    /// replaced, as the UI only ever gets them blanked.
+    pub fn keep_secrets_from(&mut self, current: &BackupStoreSettings) {
+        match (self, current) {
+            (BackupStoreSettings::Http { url }, BackupStoreSettings::Http { url: current })
+                if backup_url_token(url).unwrap_or_default().is_empty() =>
+            {
+                if let Some(token) = backup_url_token(current) {
+                    *url = with_backup_url_token(url, &token);
+                }
+            }
+            (BackupStoreSettings::S3(new), BackupStoreSettings::S3(current))
+                if new.secret_access_key.is_empty() =>
+            {
+                new.secret_access_key = current.secret_access_key.clone();
+            }
+            (BackupStoreSettings::WebDav(new), BackupStoreSettings::WebDav(current))
+                if new.password.as_deref().unwrap_or_default().is_empty() =>
+            {
+                new.password = current.password.clone();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Written by hand, like the Debug of the settings below, so the gateway token
+// never ends up in a log line.
+impl fmt::Debug for BackupStoreSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupStoreSettings::Disabled => f.write_str("Disabled"),
+            BackupStoreSettings::Http { url } => f
+                .debug_struct("Http")
+                .field("url", &with_backup_url_token(url, "***"))
+                .finish(),
+            BackupStoreSettings::S3(s3) => f.debug_tuple("S3").field(s3).finish(),
+            BackupStoreSettings::WebDav(webdav) => f.debug_tuple("WebDav").field(webdav).finish(),
+            BackupStoreSettings::Directory { path } => {
+                f.debug_struct("Directory").field("path", path).finish()
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct S3StoreSettings {
+    /// Base URL of the service, e.g. `https://s3.eu-central-1.amazonaws.com`
+    /// or `http://localhost:9000` for MinIO.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Prepended to every object key, e.g. `gym-a/`.
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Address the bucket as a subdomain of the endpoint instead of as the
+    /// first path segment. MinIO wants path style.
+    #[serde(default)]
+    pub virtual_hosted_style: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebDavStoreSettings {
+    /// URL of the collection (folder) backups go into.
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+// Written by hand so the secrets never end up in a log line.
+impl fmt::Debug for S3StoreSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3StoreSettings")
+            .field("endpoint", &self.endpoint)
+            .field("region", &self.region)
+            .field("bucket", &self.bucket)
+            .field("prefix", &self.prefix)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"***")
+            .field("virtual_hosted_style", &self.virtual_hosted_style)
+            .finish()
+    }
+}
+
+impl fmt::Debug for WebDavStoreSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebDavStoreSettings")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// Backup copies kept in a folder on this PC, e.g. on a USB drive. They are
+/// written on the same schedule as the remote backup.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LocalBackupSettings {
+    /// Absolute path of the folder; `None` turns local backups off.
+    pub directory: Option<String>,
+    /// Number of most recent days whose last copy is kept.
+    pub keep_daily: u32,
+    /// Number of most recent weeks whose last copy is kept.
+    pub keep_weekly: u32,
+}
+
+impl Default for LocalBackupSettings {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+/// Business rules applied by `process_scan`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CheckInPolicy {
+    /// Regular entries a member may make per day; 0 means no limit.
+    pub max_entries_per_day: u32,
+    /// Minutes after an entry during which the member may come back in
+    /// without it counting as a new entry; 0 disables re-entry.
+    pub reentry_window_minutes: u32,
+    /// Whether a re-entry still uses up one of the membership's visits.
+    pub reentry_consumes_visit: bool,
+    /// Days after a membership's end date during which it still admits members
+    /// with visits left.
+    pub grace_days_after_end: u32,
+}
+
+impl Default for CheckInPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries_per_day: 1,
+            reentry_window_minutes: 0,
+            reentry_consumes_visit: false,
+            grace_days_after_end: 0,
+        }
+    }
+}
+
+impl CheckInPolicy {
+    pub fn is_within_grace_period(&self, end_date: NaiveDate, today: NaiveDate) -> bool {
+        end_date + chrono::Duration::days(self.grace_days_after_end as i64) >= today
+    }
+
+    pub fn is_daily_limit_reached(&self, entries_today: i64) -> bool {
+        self.max_entries_per_day > 0 && entries_today >= self.max_entries_per_day as i64
+    }
+
+    pub fn is_reentry(&self, last_entry: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+        self.reentry_window_minutes > 0
+            && last_entry.map_or(false, |last| {
+                now - last <= chrono::Duration::minutes(self.reentry_window_minutes as i64)
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CurrencySymbolPosition {
+    Before,
+    After,
+}
+
+/// Currency of all amounts and how they are shown. Amounts are stored as
+/// integers in the currency's minor unit, e.g. cents.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CurrencySettings {
+    /// ISO 4217 code, e.g. `EUR`.
+    pub code: String,
+    /// Digits of the minor unit: 2 for cents, 0 for currencies without one.
+    pub minor_unit_digits: u32,
+    pub symbol: String,
+    pub symbol_position: CurrencySymbolPosition,
+    pub decimal_separator: String,
+    pub thousands_separator: String,
+}
+
+impl Default for CurrencySettings {
+    fn default() -> Self {
+        Self {
+            code: "EUR".to_string(),
+            minor_unit_digits: 2,
+            symbol: "€".to_string(),
+            symbol_position: CurrencySymbolPosition::Before,
+            decimal_separator: ".".to_string(),
+            thousands_separator: ",".to_string(),
+        }
+    }
 }
+
+impl CurrencySettings {
+    /// Most minor unit digits in use by any currency.
+    pub const MAX_MINOR_UNIT_DIGITS: u32 = 4;
+
+    /// Formats an amount in minor units, e.g. 123456 as `€1,234.56`.
+    pub fn format(&self, amount_minor: i64) -> String {
+        let unit = 10u64.pow(self.minor_unit_digits);
+        let major = (amount_minor.unsigned_abs() / unit).to_string();
+        let minor = amount_minor.unsigned_abs() % unit;
+
+        let mut number = String::new();
+        for (i, digit) in major.chars().enumerate() {
+            if i > 0 && (major.len() - i) % 3 == 0 {
+                number.push_str(&self.thousands_separator);
+            }
+            number.push(digit);
+        }
+        if self.minor_unit_digits > 0 {
+            number = format!(
+                "{}{}{:0width$}",
+                number,
+                self.decimal_separator,
+                minor,
+                width = self.minor_unit_digits as usize
+            );
+        }
+
+        let sign = if amount_minor < 0 { "-" } else { "" };
+        match self.symbol_position {
+            CurrencySymbolPosition::Before => format!("{}{}{}", sign, self.symbol, number),
+            CurrencySymbolPosition::After => format!("{}{} {}", sign, number, self.symbol),
+        }
+    }
+}
+
+impl AppSettings {
+    pub fn gym_timezone(&self) -> Result<chrono_tz::Tz> {
+        self.timezone.parse().map_err(|e| {
+            tracing::error!("Failed to parse timezone from settings: {}", e);
+            AppError::Config("Invalid gym timezone configuration.".to_string())
+        })
+    }
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
+            settings_version: CURRENT_SETTINGS_VERSION,
             language: "en".to_string(),
             theme: "light".to_string(),
             timezone: "Europe/Belgrade".to_string(),
-            backup_url: None,
+            backup_store: BackupStoreSettings::Disabled,
             backup_period_hours: Some(12),
             backup_enabled: false,
             gym_name: "Gym".to_string(),
@@ -35,6 +404,12 @@ impl Default for AppSettings {
                     .collect();
                 code
             },
+            login_lockout: LoginLockoutSettings::default(),
+            password_policy: PasswordPolicySettings::default(),
+            check_in_policy: CheckInPolicy::default(),
+            member_trash_retention_days: 30,
+            currency: CurrencySettings::default(),
+            local_backup: LocalBackupSettings::default(),
         }
     }
 }
@@ -50,29 +425,286 @@ fn get_config_path(app_handle: &AppHandle) -> Result<PathBuf> {
     Ok(config_dir.join(CONFIG_FILENAME))
 }
 
-pub async fn load_settings(app_handle: &AppHandle) -> Result<AppSettings> {
+/// Loads settings from the `settings` table. On the first start after
+/// upgrading, the old JSON settings file is imported once and renamed.
+pub async fn load_settings(app_handle: &AppHandle, pool: &SqlitePool) -> Result<AppSettings> {
+    if let Some(settings) = load_stored_settings(pool).await? {
+        return Ok(settings);
+    }
+
+    let settings = match import_settings_file(app_handle).await? {
+        Some(settings) => settings,
+        None => {
+            tracing::info!("No stored settings found, creating defaults.");
+            AppSettings::default()
+        }
+    };
+    save_settings(pool, &settings).await?;
+    Ok(settings)
+}
+
+/// Reads the settings stored in the database, upgraded to the current version
+/// and written back if they were older or partly unreadable. Returns `None`
+/// when nothing is stored yet.
+pub async fn load_stored_settings(pool: &SqlitePool) -> Result<Option<AppSettings>> {
+    let stored = {
+        let mut conn = pool.acquire().await?;
+        read_stored_settings(&mut conn).await?
+    };
+    if stored.is_empty() {
+        return Ok(None);
+    }
+
+    let (settings, upgraded) = match upgrade_settings(stored.clone()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            tracing::warn!(
+                "Stored settings could not be read ({}). Unreadable values are reset to defaults.",
+                e
+            );
+            (salvage_settings(stored), true)
+        }
+    };
+    if upgraded {
+        save_settings(pool, &settings).await?;
+    }
+    Ok(Some(settings))
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &AppSettings) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    write_settings(&mut tx, settings).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub(crate) async fn write_settings(
+    conn: &mut SqliteConnection,
+    settings: &AppSettings,
+) -> Result<()> {
+    let fields = match serde_json::to_value(settings)? {
+        Value::Object(fields) => fields,
+        _ => unreachable!("AppSettings serializes to a JSON object"),
+    };
+    let now = chrono::Utc::now().naive_utc();
+    for (key, value) in fields {
+        sqlx::query(
+            r#"
+            INSERT INTO settings (key, value, updated_at) VALUES (?, ?, ?)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&key)
+        .bind(value.to_string())
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn read_stored_settings(conn: &mut SqliteConnection) -> Result<Map<String, Value>> {
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value FROM settings")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut fields = Map::new();
+    for (key, value) in rows {
+        match serde_json::from_str(&value) {
+            Ok(value) => {
+                fields.insert(key, value);
+            }
+            Err(e) => tracing::warn!("Ignoring unreadable setting '{}': {}", key, e),
+        }
+    }
+    Ok(fields)
+}
+
+/// A restored database may predate the `settings` table or come from an
+/// install that never stored the gym code. Fills the gaps from the settings
+/// in use before the restore, so the gym keeps its identity on the backup
+/// server instead of getting a freshly generated code.
+pub async fn seed_restored_settings(restored_db: &Path, current: &AppSettings) -> Result<()> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(restored_db)
+        .create_if_missing(false)
+        .connect()
+        .await?;
+    db::MIGRATOR.run(&mut conn).await?;
+
+    if read_stored_settings(&mut conn).await?.is_empty() {
+        tracing::info!("Restored database has no settings, keeping the current ones.");
+        write_settings(&mut conn, current).await?;
+    } else {
+        sqlx::query(
+            "INSERT INTO settings (key, value, updated_at) VALUES ('gym_code', ?, ?) ON CONFLICT(key) DO NOTHING",
+        )
+        .bind(Value::from(current.gym_code.as_str()).to_string())
+        .bind(chrono::Utc::now().naive_utc())
+        .execute(&mut conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// One-time import of the `app_settings.json` used by earlier versions.
+/// A corrupt file is moved aside and `None` returned.
+async fn import_settings_file(app_handle: &AppHandle) -> Result<Option<AppSettings>> {
     let config_path = get_config_path(app_handle)?;
     if !config_path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&config_path).await?;
+    let parsed = serde_json::from_str::<Value>(&content)
+        .map_err(|e| AppError::Config(format!("Failed to parse settings file: {}", e)))
+        .and_then(|value| match value {
+            Value::Object(fields) => upgrade_settings(fields),
+            _ => Err(AppError::Config(
+                "Settings file is not a JSON object".to_string(),
+            )),
+        });
+
+    match parsed {
+        Ok((settings, _)) => {
+            let imported_path = config_path.with_file_name(format!("{}.imported", CONFIG_FILENAME));
+            fs::rename(&config_path, &imported_path).await?;
+            tracing::info!(
+                "Imported settings from {:?} into the database; the file was renamed to {:?}.",
+                config_path,
+                imported_path
+            );
+            Ok(Some(settings))
+        }
+        Err(e) => {
+            let backup_path = quarantine_settings_file(&config_path).await?;
+            tracing::warn!(
+                "Settings file {:?} could not be read ({}). It was moved to {:?} and defaults are used instead.",
+                config_path,
+                e,
+                backup_path
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Upgrades settings of any known version. Returns the settings and whether
+/// they differ from what was stored, i.e. need to be written back.
+fn upgrade_settings(mut fields: Map<String, Value>) -> Result<(AppSettings, bool)> {
+    let stored_version = match fields.get("settings_version") {
+        None => UNVERSIONED_SETTINGS_VERSION,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= UNVERSIONED_SETTINGS_VERSION)
+            .ok_or_else(|| AppError::Config(format!("Invalid settings_version: {}", version)))?,
+    };
+
+    if stored_version > CURRENT_SETTINGS_VERSION {
+        // Written by a newer build; unknown fields are dropped on the next save.
+        tracing::warn!(
+            "Settings version {} is newer than supported version {}.",
+            stored_version,
+            CURRENT_SETTINGS_VERSION
+        );
+    }
+
+    for version in stored_version..CURRENT_SETTINGS_VERSION {
         tracing::info!(
-            "Settings file not found at {:?}, creating with defaults.",
-            config_path
+            "Migrating settings from version {} to {}.",
+            version,
+            version + 1
+        );
+        SETTINGS_MIGRATIONS[(version - UNVERSIONED_SETTINGS_VERSION) as usize](&mut fields);
+    }
+    let upgraded = stored_version < CURRENT_SETTINGS_VERSION;
+    if upgraded {
+        fields.insert(
+            "settings_version".to_string(),
+            Value::from(CURRENT_SETTINGS_VERSION),
         );
-        let default_settings = AppSettings::default();
-        save_settings(app_handle, &default_settings).await?;
-        return Ok(default_settings);
     }
-    let content = fs::read_to_string(config_path).await?;
-    let settings: AppSettings = serde_json::from_str(&content)
-        .map_err(|e| AppError::Config(format!("Failed to parse settings file: {}", e)))?;
 
-    Ok(settings)
+    let settings: AppSettings = serde_json::from_value(Value::Object(fields))
+        .map_err(|e| AppError::Config(format!("Failed to parse settings: {}", e)))?;
+    Ok((settings, upgraded))
 }
 
-pub async fn save_settings(app_handle: &AppHandle, settings: &AppSettings) -> Result<()> {
-    let config_path = get_config_path(app_handle)?;
-    let content = serde_json::to_string_pretty(settings)?;
-    fs::write(config_path, content).await?;
-    Ok(())
+/// Keeps every stored value that still deserializes on its own and falls
+/// back to the default for the rest.
+fn salvage_settings(stored: Map<String, Value>) -> AppSettings {
+    let mut fields = match serde_json::to_value(AppSettings::default()) {
+        Ok(Value::Object(fields)) => fields,
+        _ => return AppSettings::default(),
+    };
+    for (key, value) in stored {
+        if key == "settings_version" {
+            continue;
+        }
+        let mut candidate = fields.clone();
+        candidate.insert(key.clone(), value);
+        if serde_json::from_value::<AppSettings>(Value::Object(candidate.clone())).is_ok() {
+            fields = candidate;
+        } else {
+            tracing::warn!("Resetting unreadable setting '{}' to its default.", key);
+        }
+    }
+    serde_json::from_value(Value::Object(fields)).unwrap_or_default()
+}
+
+/// Version 2 introduced `settings_version` itself. The lockout and password
+/// policy sections added alongside it are filled in by serde defaults.
+fn migrate_settings_v1_to_v2(_fields: &mut Map<String, Value>) {}
+
+/// Version 3 replaced `backup_url` with the `backup_store` selection, in
+/// which the old URL is the HTTP gateway.
+fn migrate_settings_v2_to_v3(fields: &mut Map<String, Value>) {
+    let store = match fields.remove("backup_url") {
+        Some(Value::String(url)) if !url.is_empty() => {
+            serde_json::json!({ "kind": "http", "url": url })
+        }
+        _ => serde_json::json!({ "kind": "disabled" }),
+    };
+    fields.insert("backup_store".to_string(), store);
+}
+
+/// Moves an unreadable settings file aside so it can be inspected or fixed by
+/// hand, and returns where it went.
+async fn quarantine_settings_file(config_path: &Path) -> Result<PathBuf> {
+    let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S");
+    let backup_path =
+        config_path.with_file_name(format!("{}.corrupt-{}", CONFIG_FILENAME, timestamp));
+    fs::rename(config_path, &backup_path).await?;
+    Ok(backup_path)
+}
+
+/// The `token` query parameter of a backup gateway URL.
+fn backup_url_token(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()?
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// `url` with its `token` query parameter set to `token`. A URL that doesn't
+/// parse is dropped, as there is no telling where its token is.
+fn with_backup_url_token(url: &str, token: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return String::new();
+    };
+    let other_pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| key != "token")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    parsed
+        .query_pairs_mut()
+        .clear()
+        .extend_pairs(other_pairs)
+        .append_pair("token", token);
+    parsed.to_string()
 }
 
 pub fn parse_backup_url(full_url: &str) -> Result<(String, String)> {
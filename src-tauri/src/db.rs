@@ -1,16 +1,28 @@
 use crate::error::{AppError, Result};
 use crate::utils;
 use sqlx::{migrate::Migrator, sqlite::SqlitePoolOptions, SqlitePool};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Manager};
 
-static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
-pub async fn init_db(app_handle: &AppHandle) -> Result<SqlitePool> {
-    let db_path = get_database_path(app_handle)?;
+/// Opens (creating if needed) the database at `db_path` and brings it up to
+/// date.
+pub async fn init_db(db_path: &Path) -> Result<SqlitePool> {
+    let pool = connect(db_path).await?;
 
-    let db_path = db_path.join("gym_data.sqlite");
+    // Run migrations
+    tracing::info!("Running database migrations...");
+    MIGRATOR.run(&pool).await?;
+    tracing::info!("Database migrations completed.");
+    create_default_admin_user_if_not_exists(&pool).await?;
 
+    Ok(pool)
+}
+
+/// Opens (creating if needed) the database at `db_path` as it is, without
+/// migrating it.
+pub async fn connect(db_path: &Path) -> Result<SqlitePool> {
     // Ensure the directory exists before connecting
     if let Some(parent_dir) = db_path.parent() {
         std::fs::create_dir_all(parent_dir)?;
@@ -57,12 +69,6 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<SqlitePool> {
         .await
         .map_err(|e| AppError::Config(format!("Failed to enable foreign keys: {}", e)))?;
 
-    // Run migrations
-    tracing::info!("Running database migrations...");
-    MIGRATOR.run(&pool).await?;
-    tracing::info!("Database migrations completed.");
-    create_default_admin_user_if_not_exists(&pool).await?;
-
     Ok(pool)
 }
 
@@ -82,6 +88,16 @@ pub fn get_database_path(app_handle: &AppHandle) -> Result<PathBuf> {
     Ok(app_dir)
 }
 
+/// The path of a file next to the database named after it, e.g. its `-wal`
+/// file or a `.tmp` copy.
+pub fn sibling_path(db_path: &Path, suffix: &str) -> PathBuf {
+    let file_name = db_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    db_path.with_file_name(format!("{}{}", file_name, suffix))
+}
+
 async fn create_default_admin_user_if_not_exists(pool: &SqlitePool) -> Result<()> {
     let default_username = "admin";
     let default_password = "admin";
@@ -99,6 +115,7 @@ async fn create_default_admin_user_if_not_exists(pool: &SqlitePool) -> Result<()
             "Default admin user '{}' already exists. Skipping creation.",
             default_username
         );
+        flag_default_admin_password(pool, default_username, default_password).await?;
         return Ok(());
     }
 
@@ -110,17 +127,18 @@ async fn create_default_admin_user_if_not_exists(pool: &SqlitePool) -> Result<()
 
     let mut tx = pool.begin().await?;
 
-    sqlx::query!(
+    // The seeded credentials are public knowledge, so force a rotation on first login.
+    sqlx::query(
         r#"
-        INSERT INTO users (username, password_hash, role, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?)
+        INSERT INTO users (username, password_hash, role, must_change_password, created_at, updated_at)
+        VALUES (?, ?, ?, TRUE, ?, ?)
         "#,
-        default_username,
-        password_hash,
-        "admin",
-        now,
-        now
     )
+    .bind(default_username)
+    .bind(&password_hash)
+    .bind("admin")
+    .bind(now)
+    .bind(now)
     .execute(&mut *tx)
     .await?;
 
@@ -133,3 +151,31 @@ async fn create_default_admin_user_if_not_exists(pool: &SqlitePool) -> Result<()
     );
     Ok(())
 }
+
+/// Installs created before `must_change_password` existed may still be using
+/// the seeded credentials; flag them so the next login forces a rotation.
+async fn flag_default_admin_password(
+    pool: &SqlitePool,
+    username: &str,
+    default_password: &str,
+) -> Result<()> {
+    let password_hash: Option<String> =
+        sqlx::query_scalar("SELECT password_hash FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(pool)
+            .await?;
+
+    if let Some(hash) = password_hash {
+        if utils::verify_password(default_password, &hash)? {
+            tracing::warn!(
+                "User '{}' still uses the default password, requiring a password change.",
+                username
+            );
+            sqlx::query("UPDATE users SET must_change_password = TRUE WHERE username = ?")
+                .bind(username)
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,14 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-use crate::models::{Member, Membership, MembershipType};
+use crate::auth::Permission;
+use crate::config::AppSettings;
+use crate::models::{
+    EntryLogStatus, MaintenanceRun, Member, Membership, MembershipStatus, MembershipType,
+};
 
 #[derive(Deserialize)]
 pub struct MemberPayload {
@@ -43,6 +49,27 @@ pub struct GetMembersPaginatedPayload {
     pub filter_fields: Option<Vec<FilterField>>,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct GetDeletedMembersPayload {
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+    pub search_string: Option<String>,
+}
+
+/// A member in the trash.
+#[derive(Debug, Serialize, Clone, FromRow)]
+pub struct DeletedMember {
+    pub id: i64,
+    pub card_id: Option<String>,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
+    /// When the member will be purged; `None` if the trash is kept forever.
+    pub purge_after: Option<NaiveDateTime>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct FilterField {
     pub field: String, // Field name to filter by
@@ -93,7 +120,8 @@ pub struct NewMembershipTypePayload {
     pub duration_days: Option<i64>,
     pub visit_limit: Option<i64>,
     pub enter_by: Option<i64>,
-    pub price: f64,
+    /// Price in minor units of the configured currency.
+    pub price_minor: i64,
     pub description: Option<String>,
     pub is_active: Option<bool>,
 }
@@ -126,10 +154,10 @@ pub struct MemberInfo {
     pub membership_type_name: Option<String>,
 
     pub membership_id: Option<i64>,
-    pub membership_status: Option<String>,
+    pub membership_status: Option<MembershipStatus>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct MemberWithMembership {
     pub id: i64, // Member ID
     pub card_id: Option<String>,
@@ -146,17 +174,21 @@ pub struct MemberWithMembership {
     pub membership_type_duration_days: Option<i64>,
     pub membership_type_visit_limit: Option<i64>,
     pub membership_type_enter_by: Option<i64>,
-    pub membership_type_price: Option<f64>,
+    pub membership_type_price_minor: Option<i64>,
+    /// Currency code of the price, taken from the settings.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub currency: String,
 
     pub membership_id: Option<i64>,
     pub membership_start_date: Option<NaiveDate>,
     pub membership_end_date: Option<NaiveDate>,
-    pub membership_status: Option<String>,
+    pub membership_status: Option<MembershipStatus>,
     pub membership_remaining_visits: Option<i64>,
     pub membership_purchase_date: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct MembershipInfo {
     pub member_id: i64, // Member ID
     pub member_first_name: Option<String>,
@@ -169,7 +201,7 @@ pub struct MembershipInfo {
     pub membership_id: Option<i64>,
     pub membership_start_date: Option<NaiveDate>,
     pub membership_end_date: Option<NaiveDate>,
-    pub membership_status: Option<String>,
+    pub membership_status: Option<MembershipStatus>,
     pub membership_remaining_visits: Option<i64>,
     pub membership_purchase_date: Option<NaiveDateTime>,
 }
@@ -178,6 +210,7 @@ pub struct MembershipInfo {
 pub enum EntryStatus {
     Allowed,
     AllowedSingle,
+    AllowedReentry,
     DeniedNoMembership,
     DeniedMembershipExpired,
     DeniedNoVisitsLeft,
@@ -187,6 +220,7 @@ pub enum EntryStatus {
     DeniedMembershipSuspended,
     DeniedMemberNotFound,
     DeniedCardNotAssigned,
+    DeniedGymClosed,
     Error,
 }
 
@@ -199,6 +233,16 @@ pub struct ScanProcessingResult {
     pub membership_type_name: Option<String>,
     pub membership_end_date: Option<NaiveDate>,
     pub remaining_visits: Option<i64>,
+    /// Check-in policy rule that turned the member away, if any.
+    pub denial_rule: Option<CheckInRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckInRule {
+    OpeningHours,
+    DailyEntryLimit,
+    EnterByHour,
+    GracePeriod,
 }
 
 #[derive(Deserialize)]
@@ -237,7 +281,7 @@ pub struct EntryLogDisplay {
     pub membership_type_name: Option<String>,
     pub card_id: Option<String>,
     pub entry_time: NaiveDateTime,
-    pub status: Option<String>,
+    pub status: Option<EntryLogStatus>,
     pub notes: Option<String>,
 }
 
@@ -271,19 +315,215 @@ pub struct UserPayload {
     pub username: String,
     pub role: String,
     pub password: Option<String>, // Optional for updates, required for new users
+    pub must_change_password: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug, Serialize, Clone)]
+pub struct RoleDisplay {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_system: bool,
+    pub permissions: Vec<Permission>,
+    pub user_count: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RolePayload {
+    pub id: Option<i64>, // Optional ID for updates
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Serialize, Clone, FromRow)]
+pub struct PermissionDisplay {
+    pub code: String,
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginAttemptQueryParams {
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+    pub username: Option<String>,
+    pub success: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQueryParams {
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+    pub actor_username: Option<String>,
+    pub action: Option<String>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i64>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRunQueryParams {
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseHealth {
+    pub last_run: Option<MaintenanceRun>,
+    /// End of the last run whose integrity and foreign key checks found nothing.
+    pub last_verified_healthy_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpeningHoursPayload {
+    pub weekday: i64,
+    pub is_closed: bool,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClosurePayload {
+    pub id: Option<i64>,
+    pub date: NaiveDate,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+    pub reason: Option<String>,
+}
+
+/// Opening times in effect on one date, after applying closures.
+#[derive(Debug, Serialize, Clone)]
+pub struct DaySchedule {
+    pub date: NaiveDate,
+    pub weekday: i64,
+    pub is_closed: bool,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+    /// Set when a closure entry overrides the regular hours.
+    pub closure_reason: Option<String>,
+    pub is_special_day: bool,
+}
+
+impl DaySchedule {
+    /// Whether the gym closes on the next day, e.g. open from 06:00 to 02:00.
+    pub fn closes_after_midnight(&self) -> bool {
+        matches!((self.opens_at, self.closes_at), (Some(opens_at), Some(closes_at)) if closes_at < opens_at)
+    }
+
+    /// Missing opening or closing times mean open from the start or until the
+    /// end of the day. Hours past midnight belong to the next date, see
+    /// [`Self::is_open_past_midnight_at`].
+    pub fn is_open_at(&self, time: NaiveTime) -> bool {
+        let closes_in_time = self.closes_after_midnight()
+            || self.closes_at.map_or(true, |closes_at| time < closes_at);
+        !self.is_closed && self.opens_at.map_or(true, |opens_at| time >= opens_at) && closes_in_time
+    }
+
+    /// Whether this date's hours still run at `time` on the next date.
+    pub fn is_open_past_midnight_at(&self, time: NaiveTime) -> bool {
+        !self.is_closed
+            && self.closes_after_midnight()
+            && self.closes_at.map_or(false, |closes_at| time < closes_at)
+    }
+}
+
+/// A backup in a backup store.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BackupMetadata {
     #[serde(rename = "lastModified")]
-    last_modified: String,
+    pub last_modified: String,
 
     #[serde(rename = "sizeBytes")]
-    size_bytes: u64,
+    pub size_bytes: u64,
 
+    /// Identifies the backup within its store.
     #[serde(rename = "versionId")]
-    version_id: String,
+    pub version_id: String,
 
     #[serde(rename = "isLatest")]
-    is_latest: bool,
+    pub is_latest: bool,
+}
+
+/// The settings as sent to the UI. The backup store's secrets are left out;
+/// leaving them blank in an update keeps the stored ones.
+#[derive(Serialize, Debug, Clone)]
+pub struct AppSettingsView {
+    #[serde(flatten)]
+    pub settings: AppSettings,
+    /// Whether the gateway token, the S3 secret key or the WebDAV password is
+    /// set.
+    pub backup_store_has_secret: bool,
+}
+
+impl From<&AppSettings> for AppSettingsView {
+    fn from(settings: &AppSettings) -> Self {
+        Self {
+            settings: AppSettings {
+                backup_store: settings.backup_store.without_secrets(),
+                ..settings.clone()
+            },
+            backup_store_has_secret: settings.backup_store.has_secret(),
+        }
+    }
+}
+
+/// A copy in the local backup folder.
+#[derive(Serialize, Debug, Clone)]
+pub struct LocalBackupCopy {
+    pub file_name: String,
+    /// When the copy was taken, in the gym's timezone.
+    pub taken_at: NaiveDateTime,
+    pub size_bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+/// Progress of a backup upload or download, sent to the UI as
+/// `backup_progress` events.
+#[derive(Serialize, Debug, Clone)]
+pub struct TransferProgress {
+    pub direction: TransferDirection,
+    pub transferred_bytes: u64,
+    /// `None` when the server doesn't tell.
+    pub total_bytes: Option<u64>,
+}
+
+/// Written into every backup and checked when it is restored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupManifest {
+    /// Of the database file, hex encoded.
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub app_version: String,
+    /// The newest migration applied to the database.
+    pub migration_version: i64,
+    pub row_counts: BTreeMap<String, i64>,
+    /// When the backup was taken, in the gym's timezone.
+    pub created_at: DateTime<FixedOffset>,
+    pub timezone: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BackupEncryptionStatus {
+    /// Whether a backup passphrase has been set.
+    pub enabled: bool,
+    pub key_id: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    /// When the passphrase was last changed.
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LocalBackupStatus {
+    pub directory: String,
+    /// Space left on the drive holding the folder.
+    pub free_bytes: u64,
+    /// Newest first.
+    pub copies: Vec<LocalBackupCopy>,
 }
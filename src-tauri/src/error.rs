@@ -118,6 +118,32 @@ impl ErrorCodes {
     pub const BACKUP_URL_NOT_SET: &'static str = "error.backup_url_not_set";
     pub const INVALID_TIMEZONE: &'static str = "error.invalid_timezone";
     pub const INVALID_BACKUP_URL: &'static str = "error.invalid_backup_url";
+    pub const NOT_AUTHENTICATED: &'static str = "error.not_authenticated";
+    pub const SESSION_EXPIRED: &'static str = "error.session_expired";
+    pub const PERMISSION_DENIED: &'static str = "error.permission_denied";
+    pub const PASSWORD_CHANGE_REQUIRED: &'static str = "error.password_change_required";
+    pub const PASSWORD_POLICY_VIOLATION: &'static str = "error.password_policy_violation";
+    pub const PASSWORD_REUSED: &'static str = "error.password_reused";
+    pub const ROLE_NAME_EXISTS: &'static str = "error.role_name_exists";
+    pub const ROLE_IN_USE: &'static str = "error.role_in_use";
+    pub const TWO_FACTOR_NOT_PENDING: &'static str = "error.two_factor_not_pending";
+    pub const INVALID_TWO_FACTOR_CODE: &'static str = "error.invalid_two_factor_code";
+    pub const TWO_FACTOR_ALREADY_ENABLED: &'static str = "error.two_factor_already_enabled";
+    pub const TWO_FACTOR_NOT_ENABLED: &'static str = "error.two_factor_not_enabled";
+    pub const INVALID_OPERATOR_PIN_FORMAT: &'static str = "error.invalid_operator_pin_format";
+    pub const INVALID_OPERATOR_PIN: &'static str = "error.invalid_operator_pin";
+    pub const ACCOUNT_LOCKED: &'static str = "error.account_locked";
+    pub const CARD_BELONGS_TO_DELETED_MEMBER: &'static str = "error.card_belongs_to_deleted_member";
+    pub const BACKUP_NOT_CONFIGURED: &'static str = "error.backup_not_configured";
+    pub const LOCAL_BACKUP_DIR_UNAVAILABLE: &'static str = "error.local_backup_dir_unavailable";
+    pub const INSUFFICIENT_DISK_SPACE: &'static str = "error.insufficient_disk_space";
+    pub const BACKUP_ENCRYPTION_NOT_SET: &'static str = "error.backup_encryption_not_set";
+    pub const BACKUP_PASSPHRASE_REQUIRED: &'static str = "error.backup_passphrase_required";
+    pub const WRONG_BACKUP_PASSPHRASE: &'static str = "error.wrong_backup_passphrase";
+    pub const INVALID_RECOVERY_KEY: &'static str = "error.invalid_recovery_key";
+    pub const BACKUP_VERIFICATION_FAILED: &'static str = "error.backup_verification_failed";
+    pub const BACKUP_MANIFEST_MISMATCH: &'static str = "error.backup_manifest_mismatch";
+    pub const BACKUP_FROM_NEWER_VERSION: &'static str = "error.backup_from_newer_version";
 }
 
 impl std::error::Error for TranslatableError {}
@@ -1,11 +1,20 @@
+pub mod audit;
+pub mod auth;
 pub mod backup;
+pub mod clock;
 pub mod commands;
 pub mod config;
 pub mod db;
 pub mod dto;
 pub mod error;
+pub mod maintenance;
 pub mod models;
+pub mod profiles;
+pub mod schedule;
+pub mod services;
 pub mod state;
+pub mod totp;
+pub mod trash;
 pub mod utils;
 
 pub use error::{AppError, Result};
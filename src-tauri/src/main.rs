@@ -1,6 +1,8 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use gym_manager_lib::{backup, commands, config, db, utils, AppState};
+use gym_manager_lib::{
+    backup, commands, config, db, maintenance, profiles, trash, utils, AppState,
+};
 use tauri::Manager;
 
 fn main() {
@@ -21,10 +23,6 @@ fn main() {
 
         let rt = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
 
-        let settings =
-            tauri::async_runtime::block_on(config::load_settings(&app_handle)).map_err(Box::new)?;
-        tracing::info!("Loaded settings: {:?}", settings);
-
         // Verify app data directory access
         let app_dir = app_handle
             .path()
@@ -54,8 +52,25 @@ fn main() {
             }
         }
 
+        // --- Open the active gym profile ---
+        let registry = rt
+            .block_on(profiles::load_registry(&app_handle))
+            .map_err(Box::new)?;
+        let profile = registry.active().map_err(Box::new)?.clone();
+        let db_path = match profiles::profile_database_path(&app_handle, &profile) {
+            Ok(db_path) => db_path,
+            Err(e) => {
+                tracing::error!("Cannot open profile '{}': {}", profile.name, e);
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    e.to_string(),
+                )));
+            }
+        };
+        tracing::info!("Opening profile '{}' ({:?})", profile.name, db_path);
+
         // --- Initialize Database ---
-        let pool = match rt.block_on(db::init_db(&app_handle)) {
+        let pool = match rt.block_on(db::init_db(&db_path)) {
             Ok(pool) => pool,
             Err(e) => {
                 tracing::error!("Database initialization failed: {:?}", e);
@@ -67,10 +82,17 @@ fn main() {
         };
         tracing::info!("Database pool initialized and migrations run.");
 
+        // Settings live in the database, so they can only be read once it is up.
+        let settings = rt
+            .block_on(config::load_settings(&app_handle, &pool))
+            .map_err(Box::new)?;
+        tracing::info!("Loaded settings: {:?}", settings);
+
         // --- Create and Manage State ---
-        let app_state = AppState::new(pool.clone(), settings);
+        let app_state = AppState::new(pool.clone(), settings, profile, db_path);
         app.manage(app_state); // Register the state with Tauri
         tracing::info!("Application state created and managed.");
+        rt.block_on(utils::backfill_entry_local_dates(&app.state::<AppState>()));
 
         // --- Spawn Background Tasks ---
         let handle_for_membership_check = app.handle().clone();
@@ -82,6 +104,16 @@ fn main() {
         tauri::async_runtime::spawn(async move {
             backup::spawn_backup_check_task(handle_for_backup_check);
         });
+
+        let handle_for_trash_purge = app.handle().clone();
+        tauri::async_runtime::spawn(async move {
+            trash::spawn_trash_purge_task(handle_for_trash_purge);
+        });
+
+        let handle_for_maintenance = app.handle().clone();
+        tauri::async_runtime::spawn(async move {
+            maintenance::spawn_maintenance_task(handle_for_maintenance);
+        });
         tracing::info!("Background task(s) spawned.");
 
         Ok(())
@@ -91,6 +123,12 @@ fn main() {
     final_builder
         .invoke_handler(tauri::generate_handler![
             commands::admin_commands::login,
+            commands::admin_commands::logout,
+            commands::admin_commands::verify_login_two_factor,
+            commands::admin_commands::begin_two_factor_enrollment,
+            commands::admin_commands::confirm_two_factor_enrollment,
+            commands::admin_commands::disable_two_factor,
+            commands::admin_commands::regenerate_recovery_codes,
             commands::admin_commands::get_app_settings,
             commands::admin_commands::update_app_settings,
             commands::admin_commands::get_all_users,
@@ -98,8 +136,38 @@ fn main() {
             commands::admin_commands::change_user_password,
             commands::admin_commands::delete_user,
             commands::admin_commands::get_remote_backup_metadata,
+            commands::admin_commands::get_local_backup_status,
+            commands::admin_commands::delete_backup,
+            commands::admin_commands::get_backup_encryption_status,
+            commands::admin_commands::set_backup_passphrase,
+            commands::admin_commands::export_backup_recovery_key,
             commands::admin_commands::trigger_backup,
             commands::admin_commands::save_user,
+            commands::admin_commands::unlock_user,
+            commands::admin_commands::get_login_attempts,
+            commands::role_commands::get_all_roles,
+            commands::role_commands::get_all_permissions,
+            commands::role_commands::save_role,
+            commands::role_commands::delete_role,
+            commands::audit_commands::get_audit_log,
+            commands::operator_commands::set_operator_pin,
+            commands::operator_commands::get_operators,
+            commands::operator_commands::get_active_operator,
+            commands::operator_commands::switch_operator,
+            commands::schedule_commands::get_opening_hours,
+            commands::schedule_commands::save_opening_hours,
+            commands::schedule_commands::get_closures,
+            commands::schedule_commands::save_closure,
+            commands::schedule_commands::delete_closure,
+            commands::schedule_commands::get_today_schedule,
+            commands::profile_commands::get_profiles,
+            commands::profile_commands::create_profile,
+            commands::profile_commands::switch_profile,
+            commands::profile_commands::delete_profile,
+            commands::profile_commands::relocate_database,
+            commands::maintenance_commands::run_database_maintenance,
+            commands::maintenance_commands::get_maintenance_runs,
+            commands::maintenance_commands::get_database_health,
             backup::restore_from_backup,
             commands::membership_type_commands::add_membership_type,
             commands::membership_type_commands::get_all_membership_types,
@@ -112,6 +180,9 @@ fn main() {
             commands::member_commands::update_member,
             commands::member_commands::get_members_with_memberships_paginated,
             commands::member_commands::get_member_by_id_with_membership,
+            commands::member_commands::get_deleted_members,
+            commands::member_commands::restore_member,
+            commands::member_commands::purge_deleted_member,
             commands::membership_commands::get_all_memberships_for_member,
             commands::membership_commands::save_membership,
             commands::membership_commands::delete_membership,
@@ -0,0 +1,328 @@
+//! Database maintenance: integrity and foreign key checks, `ANALYZE`,
+//! `VACUUM`, WAL checkpoints and a size report. Runs on demand and weekly in
+//! the background; every run is kept in `maintenance_runs`.
+
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tauri::Manager;
+use tokio::time::interval;
+
+use crate::{
+    audit::Actor,
+    db::sibling_path,
+    error::{AppError, Result as AppResult},
+    models::MaintenanceRun,
+    state::AppState,
+};
+
+const MAINTENANCE_CHECK_INTERVAL_MINUTES: u64 = 60;
+const MAINTENANCE_PERIOD_DAYS: i64 = 7;
+
+const MAINTENANCE_RUN_SELECT: &str = "SELECT id, started_at, finished_at, trigger_type, triggered_by, tasks, status, report, error_message FROM maintenance_runs";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    IntegrityCheck,
+    ForeignKeyCheck,
+    WalCheckpoint,
+    Vacuum,
+    Analyze,
+    SizeReport,
+}
+
+impl MaintenanceTask {
+    /// Every task, in the order they run.
+    pub const ALL: [MaintenanceTask; 6] = [
+        MaintenanceTask::IntegrityCheck,
+        MaintenanceTask::ForeignKeyCheck,
+        MaintenanceTask::WalCheckpoint,
+        MaintenanceTask::Vacuum,
+        MaintenanceTask::Analyze,
+        MaintenanceTask::SizeReport,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+    pub fkid: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WalCheckpoint {
+    /// Whether the checkpoint was blocked by another connection.
+    pub busy: bool,
+    pub log_frames: i64,
+    pub checkpointed_frames: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TableRowCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DatabaseFileSizes {
+    pub database_bytes: u64,
+    pub wal_bytes: u64,
+    pub shm_bytes: u64,
+}
+
+/// Results of the tasks that ran; tasks that were not requested stay empty.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MaintenanceReport {
+    /// `PRAGMA integrity_check` output, just `["ok"]` for a sound database.
+    pub integrity: Option<Vec<String>>,
+    pub foreign_key_violations: Option<Vec<ForeignKeyViolation>>,
+    pub wal_checkpoint: Option<WalCheckpoint>,
+    pub vacuumed: bool,
+    pub analyzed: bool,
+    pub table_row_counts: Option<Vec<TableRowCount>>,
+    pub file_sizes: Option<DatabaseFileSizes>,
+}
+
+impl MaintenanceReport {
+    pub fn has_issues(&self) -> bool {
+        let integrity_failed = self
+            .integrity
+            .as_ref()
+            .map_or(false, |messages| messages.iter().any(|m| m != "ok"));
+        let dangling_references = self
+            .foreign_key_violations
+            .as_ref()
+            .map_or(false, |violations| !violations.is_empty());
+        integrity_failed || dangling_references
+    }
+}
+
+async fn file_size(path: &Path) -> u64 {
+    tokio::fs::metadata(path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+async fn table_row_counts(pool: &SqlitePool) -> AppResult<Vec<TableRowCount>> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut counts = Vec::with_capacity(tables.len());
+    for table in tables {
+        let rows: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM \"{}\"",
+            table.replace('"', "\"\"")
+        ))
+        .fetch_one(pool)
+        .await?;
+        counts.push(TableRowCount { table, rows });
+    }
+    Ok(counts)
+}
+
+/// Runs the given tasks against the open database.
+pub async fn run(
+    pool: &SqlitePool,
+    db_path: &Path,
+    tasks: &[MaintenanceTask],
+) -> AppResult<MaintenanceReport> {
+    let mut report = MaintenanceReport::default();
+
+    for task in MaintenanceTask::ALL.iter().filter(|t| tasks.contains(t)) {
+        tracing::info!("Running database maintenance task {:?}...", task);
+        match task {
+            MaintenanceTask::IntegrityCheck => {
+                report.integrity = Some(
+                    sqlx::query_scalar("PRAGMA integrity_check")
+                        .fetch_all(pool)
+                        .await?,
+                );
+            }
+            MaintenanceTask::ForeignKeyCheck => {
+                report.foreign_key_violations = Some(
+                    sqlx::query_as::<_, ForeignKeyViolation>("PRAGMA foreign_key_check")
+                        .fetch_all(pool)
+                        .await?,
+                );
+            }
+            MaintenanceTask::WalCheckpoint => {
+                let (busy, log_frames, checkpointed_frames): (i64, i64, i64) =
+                    sqlx::query_as("PRAGMA wal_checkpoint(TRUNCATE)")
+                        .fetch_one(pool)
+                        .await?;
+                report.wal_checkpoint = Some(WalCheckpoint {
+                    busy: busy != 0,
+                    log_frames,
+                    checkpointed_frames,
+                });
+            }
+            MaintenanceTask::Vacuum => {
+                sqlx::query("VACUUM").execute(pool).await?;
+                report.vacuumed = true;
+            }
+            MaintenanceTask::Analyze => {
+                sqlx::query("ANALYZE").execute(pool).await?;
+                report.analyzed = true;
+            }
+            MaintenanceTask::SizeReport => {
+                report.table_row_counts = Some(table_row_counts(pool).await?);
+                report.file_sizes = Some(DatabaseFileSizes {
+                    database_bytes: file_size(db_path).await,
+                    wal_bytes: file_size(&sibling_path(db_path, "-wal")).await,
+                    shm_bytes: file_size(&sibling_path(db_path, "-shm")).await,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Runs the tasks and stores the outcome. A failing task does not make this
+/// fail: the run is stored as `failed` with the error message.
+pub async fn run_and_record(
+    pool: &SqlitePool,
+    db_path: &Path,
+    tasks: &[MaintenanceTask],
+    trigger: &str,
+    actor: &Actor,
+) -> AppResult<MaintenanceRun> {
+    let started_at = chrono::Utc::now().naive_utc();
+    let outcome = run(pool, db_path, tasks).await;
+    let finished_at = chrono::Utc::now().naive_utc();
+
+    let (status, report, error_message) = match outcome {
+        Ok(report) if report.has_issues() => {
+            tracing::warn!("Database maintenance found issues: {:?}", report);
+            ("issues_found", report, None)
+        }
+        Ok(report) => ("healthy", report, None),
+        Err(e) => {
+            tracing::error!("Database maintenance failed: {:?}", e);
+            ("failed", MaintenanceReport::default(), Some(e.to_string()))
+        }
+    };
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO maintenance_runs (started_at, finished_at, trigger_type, triggered_by, tasks, status, report, error_message)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(started_at)
+    .bind(finished_at)
+    .bind(trigger)
+    .bind(&actor.username)
+    .bind(sqlx::types::Json(tasks))
+    .bind(status)
+    .bind(sqlx::types::Json(&report))
+    .bind(&error_message)
+    .execute(pool)
+    .await?;
+
+    fetch_run(pool, result.last_insert_rowid())
+        .await?
+        .ok_or_else(|| AppError::NotFound("Maintenance run not found after insert".to_string()))
+}
+
+pub async fn fetch_run(pool: &SqlitePool, id: i64) -> AppResult<Option<MaintenanceRun>> {
+    let run =
+        sqlx::query_as::<_, MaintenanceRun>(&format!("{} WHERE id = ?", MAINTENANCE_RUN_SELECT))
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(run)
+}
+
+pub async fn fetch_runs(
+    pool: &SqlitePool,
+    limit: i64,
+    offset: i64,
+) -> AppResult<Vec<MaintenanceRun>> {
+    let runs = sqlx::query_as::<_, MaintenanceRun>(&format!(
+        "{} ORDER BY started_at DESC, id DESC LIMIT ? OFFSET ?",
+        MAINTENANCE_RUN_SELECT
+    ))
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    Ok(runs)
+}
+
+/// When the database last passed both the integrity and the foreign key check.
+pub async fn last_verified_healthy_at(pool: &SqlitePool) -> AppResult<Option<NaiveDateTime>> {
+    let finished_at = sqlx::query_scalar(
+        r#"
+        SELECT MAX(finished_at) FROM maintenance_runs
+        WHERE status = 'healthy'
+            AND EXISTS (SELECT 1 FROM json_each(tasks) WHERE value = 'integrity_check')
+            AND EXISTS (SELECT 1 FROM json_each(tasks) WHERE value = 'foreign_key_check')
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(finished_at)
+}
+
+async fn is_maintenance_due(pool: &SqlitePool) -> AppResult<bool> {
+    let last_scheduled: Option<NaiveDateTime> = sqlx::query_scalar(
+        "SELECT MAX(started_at) FROM maintenance_runs WHERE trigger_type = 'scheduled'",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(last_scheduled.map_or(true, |last| {
+        chrono::Utc::now().naive_utc() - last >= chrono::Duration::days(MAINTENANCE_PERIOD_DAYS)
+    }))
+}
+
+pub fn spawn_maintenance_task(app_handle: tauri::AppHandle) {
+    tracing::info!(
+        "Spawning periodic database maintenance task (Interval: {} minutes, every {} days)",
+        MAINTENANCE_CHECK_INTERVAL_MINUTES,
+        MAINTENANCE_PERIOD_DAYS,
+    );
+    tokio::spawn(async move {
+        let mut check_timer =
+            interval(Duration::from_secs(MAINTENANCE_CHECK_INTERVAL_MINUTES * 60));
+        check_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            check_timer.tick().await;
+
+            let app_state = app_handle.state::<AppState>();
+            let pool = app_state.db_pool();
+            match is_maintenance_due(&pool).await {
+                Ok(true) => {
+                    let db_path = app_state.database_path.read().await.clone();
+                    match run_and_record(
+                        &pool,
+                        &db_path,
+                        &MaintenanceTask::ALL,
+                        "scheduled",
+                        &Actor::system(),
+                    )
+                    .await
+                    {
+                        Ok(run) => tracing::info!("Scheduled database maintenance: {}", run.status),
+                        Err(e) => {
+                            tracing::error!("Failed to record scheduled maintenance: {:?}", e)
+                        }
+                    }
+                }
+                Ok(false) => tracing::debug!("Database maintenance is not due yet."),
+                Err(e) => tracing::error!("Error checking for due maintenance: {:?}", e),
+            }
+        }
+    });
+}
@@ -1,4 +1,4 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -9,6 +9,12 @@ pub struct User {
     #[serde(skip_serializing)]
     pub password_hash: String,
     pub role: String,
+    pub must_change_password: bool,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    #[serde(skip_serializing)]
+    pub totp_last_used_step: Option<i64>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -35,7 +41,12 @@ pub struct MembershipType {
     pub duration_days: Option<i64>,
     pub visit_limit: Option<i64>,
     pub enter_by: Option<i64>,
-    pub price: f64,
+    /// Price in minor units of `currency`.
+    pub price_minor: i64,
+    /// Currency code of the price, taken from the settings.
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub currency: String,
     pub description: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
@@ -43,6 +54,123 @@ pub struct MembershipType {
     pub is_active: bool,
 }
 
+/// Lifecycle of a membership, stored as text in `memberships.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum MembershipStatus {
+    /// Starts in the future.
+    Pending,
+    Active,
+    /// Past its end date or out of visits.
+    Expired,
+    /// Put on hold by staff; left alone by the automatic status updates.
+    Suspended,
+    /// Its membership type was deleted.
+    Inactive,
+}
+
+impl MembershipStatus {
+    pub const ALL: [MembershipStatus; 5] = [
+        MembershipStatus::Pending,
+        MembershipStatus::Active,
+        MembershipStatus::Expired,
+        MembershipStatus::Suspended,
+        MembershipStatus::Inactive,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MembershipStatus::Pending => "pending",
+            MembershipStatus::Active => "active",
+            MembershipStatus::Expired => "expired",
+            MembershipStatus::Suspended => "suspended",
+            MembershipStatus::Inactive => "inactive",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        MembershipStatus::ALL
+            .into_iter()
+            .find(|s| s.as_str() == code)
+    }
+}
+
+/// Outcome of an entry attempt, stored as text in `entry_logs.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum EntryLogStatus {
+    Allowed,
+    /// A one-off visit outside any membership.
+    AllowedSingle,
+    /// Back in within the re-entry window; doesn't count towards the daily
+    /// limit.
+    AllowedReentry,
+    DeniedMemberNotFound,
+    DeniedNoMembership,
+    DeniedNoVisitsLeft,
+    DeniedMembershipExpired,
+    DeniedMembershipNotActiveYet,
+    DeniedMembershipInactive,
+    DeniedMembershipSuspended,
+    /// Only found in entries logged by older versions.
+    DeniedMembershipInvalidStatus,
+    DeniedAlreadyCheckedIn,
+    DeniedAfterHours,
+    DeniedGymClosed,
+    /// Only found in entries logged by older versions.
+    ErrorUpdatingMembership,
+    /// Only found in entries logged by older versions.
+    Error,
+}
+
+impl EntryLogStatus {
+    pub const ALL: [EntryLogStatus; 16] = [
+        EntryLogStatus::Allowed,
+        EntryLogStatus::AllowedSingle,
+        EntryLogStatus::AllowedReentry,
+        EntryLogStatus::DeniedMemberNotFound,
+        EntryLogStatus::DeniedNoMembership,
+        EntryLogStatus::DeniedNoVisitsLeft,
+        EntryLogStatus::DeniedMembershipExpired,
+        EntryLogStatus::DeniedMembershipNotActiveYet,
+        EntryLogStatus::DeniedMembershipInactive,
+        EntryLogStatus::DeniedMembershipSuspended,
+        EntryLogStatus::DeniedMembershipInvalidStatus,
+        EntryLogStatus::DeniedAlreadyCheckedIn,
+        EntryLogStatus::DeniedAfterHours,
+        EntryLogStatus::DeniedGymClosed,
+        EntryLogStatus::ErrorUpdatingMembership,
+        EntryLogStatus::Error,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryLogStatus::Allowed => "allowed",
+            EntryLogStatus::AllowedSingle => "allowed_single",
+            EntryLogStatus::AllowedReentry => "allowed_reentry",
+            EntryLogStatus::DeniedMemberNotFound => "denied_member_not_found",
+            EntryLogStatus::DeniedNoMembership => "denied_no_membership",
+            EntryLogStatus::DeniedNoVisitsLeft => "denied_no_visits_left",
+            EntryLogStatus::DeniedMembershipExpired => "denied_membership_expired",
+            EntryLogStatus::DeniedMembershipNotActiveYet => "denied_membership_not_active_yet",
+            EntryLogStatus::DeniedMembershipInactive => "denied_membership_inactive",
+            EntryLogStatus::DeniedMembershipSuspended => "denied_membership_suspended",
+            EntryLogStatus::DeniedMembershipInvalidStatus => "denied_membership_invalid_status",
+            EntryLogStatus::DeniedAlreadyCheckedIn => "denied_already_checked_in",
+            EntryLogStatus::DeniedAfterHours => "denied_after_hours",
+            EntryLogStatus::DeniedGymClosed => "denied_gym_closed",
+            EntryLogStatus::ErrorUpdatingMembership => "error_updating_membership",
+            EntryLogStatus::Error => "error",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        EntryLogStatus::ALL.into_iter().find(|s| s.as_str() == code)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 pub struct Membership {
     pub id: i64,
@@ -51,7 +179,7 @@ pub struct Membership {
     pub start_date: NaiveDate,
     pub end_date: Option<NaiveDate>,
     pub remaining_visits: Option<i64>,
-    pub status: String,
+    pub status: MembershipStatus,
     pub purchase_date: NaiveDateTime,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
@@ -64,11 +192,56 @@ pub struct EntryLog {
     pub member_id: i64,
     pub membership_id: Option<i64>,
     pub entry_time: NaiveDateTime,
-    pub status: String,
+    pub status: EntryLogStatus,
     pub created_at: NaiveDateTime,
     pub notes: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Role {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_system: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct LoginAttempt {
+    pub id: i64,
+    pub username: String,
+    pub success: bool,
+    pub reason: Option<String>,
+    pub attempted_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub actor_user_id: Option<i64>,
+    pub actor_username: String,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Option<i64>,
+    pub before_data: Option<sqlx::types::Json<serde_json::Value>>,
+    pub after_data: Option<sqlx::types::Json<serde_json::Value>>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct MaintenanceRun {
+    pub id: i64,
+    pub started_at: NaiveDateTime,
+    pub finished_at: NaiveDateTime,
+    pub trigger_type: String,
+    pub triggered_by: String,
+    pub tasks: sqlx::types::Json<serde_json::Value>,
+    pub status: String,
+    pub report: sqlx::types::Json<serde_json::Value>,
+    pub error_message: Option<String>,
+}
+
 #[derive(sqlx::FromRow, Debug)]
 pub struct CronCheck {
     pub id: i64,
@@ -80,3 +253,24 @@ pub struct CronCheck {
 }
 
 // --- API / Command Payloads ---
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct OpeningHours {
+    /// 0 = Monday ... 6 = Sunday.
+    pub weekday: i64,
+    pub is_closed: bool,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Closure {
+    pub id: i64,
+    pub date: NaiveDate,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
@@ -0,0 +1,158 @@
+//! Gym profiles: one installation can serve several locations, each with its
+//! own database file (and so its own settings, users and members). The
+//! registry of profiles lives in the app data directory in `profiles.json`;
+//! it also records where each database is, since a database can be kept on
+//! another drive.
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::AppHandle;
+use tokio::fs;
+
+use crate::db::get_database_path;
+use crate::error::{AppError, Result as AppResult};
+
+const REGISTRY_FILENAME: &str = "profiles.json";
+pub const DEFAULT_PROFILE_ID: &str = "default";
+/// The database every installation had before profiles existed.
+const DEFAULT_DATABASE_FILE: &str = "gym_data.sqlite";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    /// File name inside the database directory.
+    pub database_file: String,
+    /// Directory holding the database, e.g. on a data drive or an encrypted
+    /// volume. `None` keeps it in the app data directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_dir: Option<PathBuf>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileRegistry {
+    pub active_profile_id: String,
+    pub profiles: Vec<Profile>,
+}
+
+impl ProfileRegistry {
+    fn with_default_profile() -> Self {
+        Self {
+            active_profile_id: DEFAULT_PROFILE_ID.to_string(),
+            profiles: vec![Profile {
+                id: DEFAULT_PROFILE_ID.to_string(),
+                name: "Gym".to_string(),
+                database_file: DEFAULT_DATABASE_FILE.to_string(),
+                database_dir: None,
+                created_at: chrono::Utc::now().naive_utc(),
+            }],
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.id == id)
+    }
+
+    pub fn active(&self) -> AppResult<&Profile> {
+        self.find(&self.active_profile_id).ok_or_else(|| {
+            AppError::Config(format!(
+                "Active profile '{}' is missing from the profile registry",
+                self.active_profile_id
+            ))
+        })
+    }
+
+    /// Builds a new, not yet registered profile with an id derived from `name`.
+    pub fn new_profile(&self, name: &str) -> Profile {
+        let slug: String = name
+            .trim()
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect::<String>()
+            .split('-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let base = if slug.is_empty() {
+            "profile".to_string()
+        } else {
+            slug
+        };
+
+        let mut id = base.clone();
+        let mut suffix = 2;
+        while self.find(&id).is_some()
+            || self
+                .profiles
+                .iter()
+                .any(|p| p.database_file == database_file_for(&id))
+        {
+            id = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+
+        Profile {
+            database_file: database_file_for(&id),
+            database_dir: None,
+            id,
+            name: name.trim().to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+fn database_file_for(profile_id: &str) -> String {
+    format!("gym_data_{}.sqlite", profile_id)
+}
+
+fn get_registry_path(app_handle: &AppHandle) -> AppResult<PathBuf> {
+    Ok(get_database_path(app_handle)?.join(REGISTRY_FILENAME))
+}
+
+/// Full path of a profile's database file. A custom database directory must
+/// already exist: it is never created, so an unmounted drive is reported
+/// instead of silently getting an empty database.
+pub fn profile_database_path(app_handle: &AppHandle, profile: &Profile) -> AppResult<PathBuf> {
+    match &profile.database_dir {
+        Some(dir) if !dir.is_dir() => Err(AppError::Config(format!(
+            "The database directory {:?} of profile '{}' is not available. Connect the drive or volume holding it and start the application again.",
+            dir, profile.name
+        ))),
+        Some(dir) => Ok(dir.join(&profile.database_file)),
+        None => Ok(get_database_path(app_handle)?.join(&profile.database_file)),
+    }
+}
+
+/// Loads the registry, creating one with the pre-profile database as the
+/// default profile on first start.
+pub async fn load_registry(app_handle: &AppHandle) -> AppResult<ProfileRegistry> {
+    let registry_path = get_registry_path(app_handle)?;
+    if !registry_path.exists() {
+        tracing::info!(
+            "Profile registry not found at {:?}, creating the default profile.",
+            registry_path
+        );
+        let registry = ProfileRegistry::with_default_profile();
+        save_registry(app_handle, &registry).await?;
+        return Ok(registry);
+    }
+
+    let content = fs::read_to_string(&registry_path).await?;
+    let registry: ProfileRegistry = serde_json::from_str(&content)
+        .map_err(|e| AppError::Config(format!("Failed to parse profile registry: {}", e)))?;
+    registry.active()?;
+    Ok(registry)
+}
+
+pub async fn save_registry(app_handle: &AppHandle, registry: &ProfileRegistry) -> AppResult<()> {
+    let registry_path = get_registry_path(app_handle)?;
+    let content = serde_json::to_string_pretty(registry)?;
+    // Write to a temporary file first so a crash can't leave half a registry.
+    let temp_path = registry_path.with_extension("json.tmp");
+    fs::write(&temp_path, content).await?;
+    fs::rename(&temp_path, &registry_path).await?;
+    Ok(())
+}
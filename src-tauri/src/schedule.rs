@@ -0,0 +1,108 @@
+//! Gym-wide opening hours per weekday, overridden per date by closures
+//! (holidays, shortened days).
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use sqlx::SqliteConnection;
+
+use crate::{
+    clock::GymClock,
+    dto::DaySchedule,
+    error::{AppError, Result as AppResult},
+    models::{Closure, OpeningHours},
+};
+
+/// Rejects opening times that close when they open. A closing time before
+/// the opening time means the gym closes after midnight.
+pub fn validate_times(opens_at: Option<NaiveTime>, closes_at: Option<NaiveTime>) -> AppResult<()> {
+    if let (Some(opens_at), Some(closes_at)) = (opens_at, closes_at) {
+        if closes_at == opens_at {
+            return Err(AppError::Validation(format!(
+                "Closing time {} must differ from opening time {}.",
+                closes_at.format("%H:%M"),
+                opens_at.format("%H:%M")
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub fn weekday_index(date: NaiveDate) -> i64 {
+    date.weekday().num_days_from_monday() as i64
+}
+
+/// Works out the opening times for `date`. A closure entry wins over the
+/// regular hours; a weekday without regular hours is open all day.
+pub async fn day_schedule(conn: &mut SqliteConnection, date: NaiveDate) -> AppResult<DaySchedule> {
+    let weekday = weekday_index(date);
+
+    let closure = sqlx::query_as::<_, Closure>(
+        "SELECT id, date, opens_at, closes_at, reason, created_at, updated_at FROM closures WHERE date = ?",
+    )
+    .bind(date)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(closure) = closure {
+        let closed_all_day = closure.opens_at.is_none() && closure.closes_at.is_none();
+        return Ok(DaySchedule {
+            date,
+            weekday,
+            is_closed: closed_all_day,
+            opens_at: closure.opens_at,
+            closes_at: closure.closes_at,
+            closure_reason: closure.reason,
+            is_special_day: true,
+        });
+    }
+
+    let hours = sqlx::query_as::<_, OpeningHours>(
+        "SELECT weekday, is_closed, opens_at, closes_at, updated_at FROM opening_hours WHERE weekday = ?",
+    )
+    .bind(weekday)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(match hours {
+        Some(hours) => DaySchedule {
+            date,
+            weekday,
+            is_closed: hours.is_closed,
+            opens_at: hours.opens_at,
+            closes_at: hours.closes_at,
+            closure_reason: None,
+            is_special_day: false,
+        },
+        None => DaySchedule {
+            date,
+            weekday,
+            is_closed: false,
+            opens_at: None,
+            closes_at: None,
+            closure_reason: None,
+            is_special_day: false,
+        },
+    })
+}
+
+/// Today's schedule in the gym's timezone, as shown at the front desk.
+pub async fn today_schedule(
+    conn: &mut SqliteConnection,
+    clock: &GymClock,
+) -> AppResult<DaySchedule> {
+    day_schedule(conn, clock.today()).await
+}
+
+/// Whether the gym is open at the gym-local time `at`, counting the hours of
+/// the day before that run past midnight.
+pub async fn is_open_at(conn: &mut SqliteConnection, at: NaiveDateTime) -> AppResult<bool> {
+    let date = at.date();
+    if day_schedule(conn, date).await?.is_open_at(at.time()) {
+        return Ok(true);
+    }
+    match date.pred_opt() {
+        Some(day_before) => Ok(day_schedule(conn, day_before)
+            .await?
+            .is_open_past_midnight_at(at.time())),
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use crate::clock::GymClock;
+use crate::config::AppSettings;
+use crate::error::Result as AppResult;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use sqlx::SqlitePool;
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct MembershipTypeDistributionItem {
+    pub membership_type_name: String,
+    pub active_member_count: i64,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct ActiveMembershipsOverTimeItem {
+    pub year_month: String, // e.g., "2023-01"
+    pub active_member_count: i64,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct HourlyVisitCount {
+    pub hour_of_day: i64, // Or u32
+    pub visit_count: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct DailyHourlyVisitCount {
+    pub day_of_week: i64, // 0-6
+    pub hour_of_day: i64, // 0-23
+    pub visit_count: i64,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct RevenueByMembershipTypeItem {
+    pub membership_type_name: String,
+    /// Revenue in minor units of `currency`.
+    pub total_revenue_minor: i64,
+    /// Currency code of the revenue, taken from the settings.
+    #[sqlx(skip)]
+    pub currency: String,
+    pub count: i64, // Number of memberships of this type
+}
+
+const MEMBERSHIP_TYPE_DISTRIBUTION_QUERY: &str = r#"
+SELECT
+    mt.name AS membership_type_name,
+    COUNT(DISTINCT m.id) AS active_member_count
+FROM
+    membership_types mt
+JOIN
+    memberships ms ON mt.id = ms.membership_type_id
+JOIN
+    members m ON ms.member_id = m.id
+WHERE
+    ms.status = 'active'
+    AND (ms.is_deleted IS NULL OR ms.is_deleted = FALSE)
+    AND (mt.is_deleted IS NULL OR mt.is_deleted = FALSE)
+GROUP BY
+    mt.name
+ORDER BY
+    active_member_count DESC;
+  "#;
+
+// Entry times are stored in UTC; they are grouped by gym-local day and hour
+// in Rust since SQLite only knows the timezone of the PC.
+const VISIT_TIMES_QUERY: &str = r#"
+SELECT
+    entry_time
+FROM
+    entry_logs
+WHERE
+    status IN ('allowed', 'allowed_single')
+    AND entry_time >= ?1
+    AND entry_time < ?2
+"#;
+
+const REVENUE_BY_MEMBERSHIP_TYPE_QUERY: &str = r#"
+SELECT
+    mt.name AS membership_type_name,
+    SUM(mt.price_minor) AS total_revenue_minor, -- Summing the price of the type for each membership instance created
+    COUNT(ms.id) AS count
+FROM
+    membership_types mt
+JOIN
+    memberships ms ON mt.id = ms.membership_type_id
+WHERE
+    ms.purchase_date >= ?1
+    AND ms.purchase_date < ?2
+    AND (ms.is_deleted IS NULL OR ms.is_deleted = FALSE)
+    AND (mt.is_deleted IS NULL OR mt.is_deleted = FALSE)
+GROUP BY
+    mt.name
+ORDER BY
+    total_revenue_minor DESC;
+"#;
+
+const ACTIVE_MEMBERSHIPS_OVER_TIME_QUERY: &str = r#"
+WITH RECURSIVE MonthSeries(month_start, month_end) AS (
+    SELECT
+        DATE(?1, 'start of month') as month_start,
+        DATE(?1, 'start of month', '+1 month', '-1 day') as month_end
+    UNION ALL
+    SELECT
+        DATE(month_start, '+1 month'),
+        DATE(month_start, '+2 months', '-1 day')
+    FROM MonthSeries
+    WHERE DATE(month_start, '+1 month') <= DATE(?2, 'start of month')
+)
+SELECT
+    strftime('%Y-%m', ms.month_start) AS year_month,
+    COUNT(DISTINCT mship.member_id) AS active_member_count
+FROM
+    MonthSeries ms
+LEFT JOIN
+    memberships mship ON
+        (mship.is_deleted IS NULL OR mship.is_deleted = 0 OR mship.is_deleted = FALSE)
+        AND mship.start_date <= ms.month_end
+        AND (mship.end_date IS NULL OR mship.end_date >= ms.month_start)
+        AND mship.status IN ('active', 'pending', 'expired')
+GROUP BY
+    ms.month_start
+ORDER BY
+    ms.month_start ASC
+"#;
+pub async fn get_membership_type_distribution(
+    pool: &SqlitePool,
+) -> AppResult<Vec<MembershipTypeDistributionItem>> {
+    let rows =
+        sqlx::query_as::<_, MembershipTypeDistributionItem>(MEMBERSHIP_TYPE_DISTRIBUTION_QUERY)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(rows)
+}
+
+pub async fn get_daily_hourly_visit_count(
+    pool: &SqlitePool,
+    clock: &GymClock,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> AppResult<Vec<DailyHourlyVisitCount>> {
+    let entry_times: Vec<NaiveDateTime> = sqlx::query_scalar(VISIT_TIMES_QUERY)
+        .bind(clock.start_of_day_utc(start_date))
+        .bind(clock.start_of_day_utc(end_date + chrono::Duration::days(1)))
+        .fetch_all(pool)
+        .await?;
+
+    let mut counts: BTreeMap<(i64, i64), i64> = BTreeMap::new();
+    for entry_time in entry_times {
+        let local = clock.to_local(entry_time);
+        let day_of_week = local.weekday().num_days_from_sunday() as i64;
+        *counts
+            .entry((day_of_week, local.hour() as i64))
+            .or_default() += 1;
+    }
+
+    Ok(counts
+        .into_iter()
+        .map(
+            |((day_of_week, hour_of_day), visit_count)| DailyHourlyVisitCount {
+                day_of_week,
+                hour_of_day,
+                visit_count,
+            },
+        )
+        .collect())
+}
+
+pub async fn get_revenue_by_membership_type(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    clock: &GymClock,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> AppResult<Vec<RevenueByMembershipTypeItem>> {
+    let rows = sqlx::query_as::<_, RevenueByMembershipTypeItem>(REVENUE_BY_MEMBERSHIP_TYPE_QUERY)
+        .bind(clock.start_of_day_utc(start_date))
+        .bind(clock.start_of_day_utc(end_date + chrono::Duration::days(1)))
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|item| RevenueByMembershipTypeItem {
+            currency: settings.currency.code.clone(),
+            ..item
+        })
+        .collect())
+}
+
+pub async fn get_active_memberships_over_time(
+    pool: &SqlitePool,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> AppResult<Vec<ActiveMembershipsOverTimeItem>> {
+    tracing::info!(
+        "Fetching active memberships over time from {} to {}",
+        start_date,
+        end_date
+    );
+    let rows =
+        sqlx::query_as::<_, ActiveMembershipsOverTimeItem>(ACTIVE_MEMBERSHIPS_OVER_TIME_QUERY)
+            .bind(start_date)
+            .bind(end_date)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(rows)
+}
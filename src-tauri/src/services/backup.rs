@@ -0,0 +1,383 @@
+//! Database snapshots and restoring them from a backup store. Backups are
+//! packed on disk, compressed and usually encrypted, so a large database
+//! never has to fit in memory. Restoring swaps files on disk, so the caller
+//! fetches the backup first, then closes its pool to the database, replaces
+//! it and reopens it afterwards.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, SqlitePool};
+
+use chrono::NaiveDateTime;
+
+use crate::audit::{self, Actor};
+use crate::clock::GymClock;
+use crate::config::{self, AppSettings};
+use crate::db::sibling_path;
+use crate::dto::BackupManifest;
+use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
+use crate::services::backup_encryption::{self, BackupKey, BackupSecret};
+use crate::services::backup_manifest::{self, HashingWriter};
+use crate::services::backup_store::{BackupStore, ProgressReporter};
+
+/// A fast level; the database compresses well even so.
+const ZSTD_LEVEL: i32 = 3;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Writes a consistent copy of the database to `snapshot_path` with
+/// `VACUUM INTO`.
+pub async fn snapshot(pool: &SqlitePool, snapshot_path: &Path) -> AppResult<()> {
+    let snapshot_path_str = snapshot_path.to_str().ok_or_else(|| {
+        AppError::Config(format!(
+            "Failed to convert backup path to string: {:?}",
+            snapshot_path
+        ))
+    })?;
+    // VACUUM INTO refuses to overwrite, e.g. what a crashed run left behind.
+    if snapshot_path.exists() {
+        tokio::fs::remove_file(snapshot_path).await?;
+    }
+
+    tracing::info!(
+        "Executing VACUUM INTO temporary file: {}",
+        snapshot_path_str
+    );
+    sqlx::query(&format!(
+        "VACUUM INTO '{}'",
+        snapshot_path_str.replace('\'', "''")
+    ))
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute VACUUM command: {}", e);
+        AppError::Sqlx(e)
+    })?;
+    Ok(())
+}
+
+/// Snapshots the database and packs it into `backup_path`, ready to be
+/// stored. Returns the manifest written into the backup.
+pub async fn prepare_backup(
+    pool: &SqlitePool,
+    snapshot_path: &Path,
+    backup_path: &Path,
+    key: Option<&BackupKey>,
+    clock: &GymClock,
+) -> AppResult<BackupManifest> {
+    snapshot(pool, snapshot_path).await?;
+    let packed = async {
+        let manifest = backup_manifest::build(snapshot_path, clock).await?;
+        pack(snapshot_path, backup_path, &manifest, key).await?;
+        Ok(manifest)
+    }
+    .await;
+    if let Err(e) = tokio::fs::remove_file(snapshot_path).await {
+        tracing::error!("Failed to remove temporary backup file: {}", e);
+    }
+    packed
+}
+
+/// Writes `manifest` and the database file at `source`, compressed with
+/// zstd, into `dest`, and encrypts both given a key. Returns the size of
+/// `dest`.
+pub async fn pack(
+    source: &Path,
+    dest: &Path,
+    manifest: &BackupManifest,
+    key: Option<&BackupKey>,
+) -> AppResult<u64> {
+    let (source, dest, manifest, key) = (
+        source.to_path_buf(),
+        dest.to_path_buf(),
+        manifest.clone(),
+        key.cloned(),
+    );
+    tokio::task::spawn_blocking(move || -> AppResult<u64> {
+        let mut input = BufReader::new(File::open(&source)?);
+        let mut output = BufWriter::new(File::create(&dest)?);
+        let output = match key {
+            Some(key) => {
+                let mut writer = key.encrypting_writer(output)?;
+                backup_manifest::write_frame(&mut writer, &manifest)?;
+                let mut encoder = zstd::stream::write::Encoder::new(writer, ZSTD_LEVEL)?;
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.finish()?
+            }
+            None => {
+                backup_manifest::write_frame(&mut output, &manifest)?;
+                let mut encoder = zstd::stream::write::Encoder::new(output, ZSTD_LEVEL)?;
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?
+            }
+        };
+        let file = output.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(file.metadata()?.len())
+    })
+    .await
+    .map_err(|e| AppError::BackupFailed(format!("Packing the backup failed: {}", e)))?
+}
+
+/// Turns a stored backup back into a database file: decrypts it with
+/// `secret` if it is encrypted and decompresses it if it is compressed, so
+/// plain copies made by older versions restore as well. The database file
+/// has to match the checksum of the manifest, which is returned, if the
+/// backup has one.
+pub async fn unpack(
+    source: &Path,
+    dest: &Path,
+    secret: Option<&BackupSecret>,
+) -> AppResult<Option<BackupManifest>> {
+    let (source, dest, secret) = (source.to_path_buf(), dest.to_path_buf(), secret.cloned());
+    tokio::task::spawn_blocking(move || -> AppResult<Option<BackupManifest>> {
+        let mut input = BufReader::new(File::open(&source)?);
+        let decrypted: Box<dyn Read> = if backup_encryption::is_encrypted(input.fill_buf()?) {
+            let secret = secret.ok_or_else(|| {
+                AppError::Translatable(TranslatableError::new(
+                    ErrorCodes::BACKUP_PASSPHRASE_REQUIRED,
+                    "This backup is encrypted. Enter the backup passphrase or the recovery key.",
+                ))
+            })?;
+            Box::new(backup_encryption::decrypting_reader(input, &secret)?)
+        } else {
+            Box::new(input)
+        };
+        let mut decrypted = BufReader::new(decrypted);
+        let manifest = if backup_manifest::has_frame(
+            decrypted
+                .fill_buf()
+                .map_err(backup_encryption::read_error)?,
+        ) {
+            Some(backup_manifest::read_frame(&mut decrypted).map_err(|e| {
+                tracing::error!("Failed to read the backup manifest: {}", e);
+                backup_manifest::mismatch()
+            })?)
+        } else {
+            tracing::warn!("Backup has no manifest, it was made by an older version.");
+            None
+        };
+        let mut output = HashingWriter::new(BufWriter::new(File::create(&dest)?));
+        if decrypted
+            .fill_buf()
+            .map_err(backup_encryption::read_error)?
+            .starts_with(&ZSTD_MAGIC)
+        {
+            let mut decoder = zstd::stream::read::Decoder::with_buffer(decrypted)?;
+            io::copy(&mut decoder, &mut output).map_err(backup_encryption::read_error)?;
+        } else {
+            io::copy(&mut decrypted, &mut output).map_err(backup_encryption::read_error)?;
+        }
+        let (output, sha256, size_bytes) = output.finish();
+        output
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        if let Some(manifest) = &manifest {
+            if manifest.sha256 != sha256 || manifest.size_bytes != size_bytes {
+                tracing::error!(
+                    "Unpacked database has SHA-256 {} ({} bytes), the manifest says {} ({} bytes)",
+                    sha256,
+                    size_bytes,
+                    manifest.sha256,
+                    manifest.size_bytes
+                );
+                return Err(backup_manifest::mismatch());
+            }
+        }
+        Ok(manifest)
+    })
+    .await
+    .map_err(|e| AppError::RestoreFailed(format!("Unpacking the backup failed: {}", e)))?
+}
+
+pub async fn check_db_integrity(db_path: &Path) -> AppResult<()> {
+    tracing::info!("Performing integrity check on: {:?}", db_path);
+
+    if !db_path.exists() {
+        return Err(AppError::Config(format!(
+            "Database file does not exist at: {:?}",
+            db_path
+        )));
+    }
+
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(false)
+        .read_only(true);
+
+    let mut conn = options
+        .connect()
+        .await
+        .map_err(|e| AppError::Config(format!("Failed to connect to database: {}", e)))?;
+
+    // The PRAGMA returns a single row with the text 'ok' if the DB is not corrupt.
+    let result: (String,) = sqlx::query_as("PRAGMA integrity_check;")
+        .fetch_one(&mut conn)
+        .await
+        .map_err(|e| AppError::Sqlx(e))?;
+
+    if result.0.to_lowercase() == "ok" {
+        tracing::info!("Integrity check passed for: {:?}", db_path);
+        Ok(())
+    } else {
+        tracing::error!(
+            "Integrity check failed for {:?}. Result: {}",
+            db_path,
+            result.0
+        );
+        Err(AppError::RestoreFailed(result.0))
+    }
+}
+
+async fn restore_local_backup(backup_path: &Path, db_path: &Path) {
+    if backup_path.exists() {
+        tracing::info!(
+            "Restoring local backup from {:?} to {:?}",
+            backup_path,
+            db_path
+        );
+        if let Err(e) = tokio::fs::rename(backup_path, db_path).await {
+            tracing::error!("CRITICAL FAILURE: Could not restore local backup. The database file might be missing. Error: {}", e);
+        }
+    }
+}
+
+/// Where [`fetch_backup`] puts a backup before it replaces `db_path`.
+pub fn download_path(db_path: &Path) -> PathBuf {
+    sibling_path(db_path, ".tmp")
+}
+
+/// Stores the packed backup at `backup_path` in `store` and reads it back
+/// to make sure the store holds what was sent. Returns its version id.
+pub async fn upload_verified(
+    store: &dyn BackupStore,
+    backup_path: &Path,
+    taken_at: NaiveDateTime,
+    progress: &ProgressReporter,
+) -> AppResult<String> {
+    let version_id = store.upload(backup_path, taken_at, progress).await?;
+    backup_manifest::verify_upload(store, &version_id, backup_path, progress).await?;
+    Ok(version_id)
+}
+
+/// Downloads a backup from `store`, the latest one unless `version_id` is
+/// given, unpacks it to `dest` with `secret` and checks that it is a sound
+/// database matching its manifest, which is returned. Runs while the current
+/// database is still open, so a wrong passphrase or a damaged backup leaves
+/// everything as it was.
+pub async fn fetch_backup(
+    store: &dyn BackupStore,
+    version_id: Option<&str>,
+    dest: &Path,
+    secret: Option<&BackupSecret>,
+    progress: &ProgressReporter,
+) -> AppResult<Option<BackupManifest>> {
+    let download = sibling_path(dest, ".download");
+    let fetched = async {
+        store.download(version_id, &download, progress).await?;
+        let manifest = unpack(&download, dest, secret).await?;
+        check_db_integrity(dest).await?;
+        if let Some(manifest) = &manifest {
+            backup_manifest::verify_restored(dest, manifest).await?;
+        }
+        Ok(manifest)
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&download).await;
+    if fetched.is_err() && dest.exists() {
+        let _ = tokio::fs::remove_file(dest).await;
+    }
+    fetched
+}
+
+/// Replaces the database file at `db_path` with `restored`, a backup checked
+/// by [`fetch_backup`]. The audit log, the device-bound settings and the
+/// backup key of the current database are carried over. On any failure the
+/// current database is put back in place.
+///
+/// No connection to `db_path` may be open while this runs.
+pub async fn replace_database(
+    restored: &Path,
+    db_path: &Path,
+    current_settings: &AppSettings,
+    current_key: Option<&BackupKey>,
+    actor: &Actor,
+    restore_details: serde_json::Value,
+) -> AppResult<()> {
+    let backup_path = sibling_path(db_path, ".backup");
+
+    tracing::info!("Backing up current database to: {:?}", backup_path);
+    if let Err(e) = tokio::fs::rename(db_path, &backup_path).await {
+        tracing::error!(
+            "Failed to create local backup of current DB: {}. Please restart.",
+            e
+        );
+        return Err(AppError::Io(e));
+    }
+
+    tracing::info!("Verification successful. Replacing current database with downloaded version.");
+    if let Err(e) = tokio::fs::rename(restored, db_path).await {
+        restore_local_backup(&backup_path, db_path).await;
+        return Err(AppError::RestoreFailed(format!("CRITICAL: Failed to replace database after verification. Local backup has been restored. Error: {}", e)));
+    }
+
+    // The downloaded snapshot may be older than our audit trail.
+    match audit::carry_over(db_path, &backup_path, actor, restore_details).await {
+        Ok(copied) => {
+            tracing::info!(
+                "Carried {} audit log rows over into restored database.",
+                copied
+            )
+        }
+        Err(e) => {
+            tracing::error!("Failed to carry audit log over, reverting restore: {}", e);
+            let _ = tokio::fs::remove_file(db_path).await;
+            restore_local_backup(&backup_path, db_path).await;
+            return Err(AppError::RestoreFailed(format!(
+                "Failed to preserve the audit log. Your previous data has been restored. Error: {}",
+                e
+            )));
+        }
+    }
+    if let Err(e) = config::seed_restored_settings(db_path, current_settings).await {
+        tracing::error!(
+            "Failed to prepare restored settings, reverting restore: {}",
+            e
+        );
+        let _ = tokio::fs::remove_file(db_path).await;
+        restore_local_backup(&backup_path, db_path).await;
+        return Err(AppError::RestoreFailed(format!(
+            "Failed to restore the settings. Your previous data has been restored. Error: {}",
+            e
+        )));
+    }
+    if let Some(key) = current_key {
+        if let Err(e) = backup_encryption::seed_restored_key(db_path, key).await {
+            tracing::error!("Failed to keep the backup key, reverting restore: {}", e);
+            let _ = tokio::fs::remove_file(db_path).await;
+            restore_local_backup(&backup_path, db_path).await;
+            return Err(AppError::RestoreFailed(format!(
+                "Failed to keep the backup key. Your previous data has been restored. Error: {}",
+                e
+            )));
+        }
+    }
+    if backup_path.exists() {
+        if let Err(e) = tokio::fs::remove_file(&backup_path).await {
+            tracing::warn!(
+                "Could not remove temporary backup file {:?}: {}",
+                backup_path,
+                e
+            );
+        }
+    }
+    tracing::info!("Database file successfully overwritten from backup.");
+
+    tracing::info!("Deleting old WAL/SHM files if they exist...");
+    let _ = tokio::fs::remove_file(sibling_path(db_path, "-wal")).await;
+    let _ = tokio::fs::remove_file(sibling_path(db_path, "-shm")).await;
+    Ok(())
+}
@@ -0,0 +1,565 @@
+//! Encryption of backups before they leave the machine.
+//!
+//! Snapshots are encrypted with a random 256-bit data key using
+//! XChaCha20-Poly1305, in chunks so that backups are encrypted and decrypted
+//! as they stream through.
+//! The data key is kept in this database, so scheduled backups run without
+//! the passphrase. Every backup also carries the data key wrapped with a key
+//! derived from the admin passphrase (Argon2id), so a backup can be restored
+//! on a new PC with the passphrase alone, or with the recovery key, which is
+//! the data key itself written out for safekeeping.
+//!
+//! Changing the passphrase rewraps the same data key. Backups taken before
+//! the change still open with the old passphrase or with the recovery key.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chrono::NaiveDateTime;
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Executor, Sqlite, SqlitePool};
+
+use crate::dto::BackupEncryptionStatus;
+use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
+
+/// Start of every encrypted backup.
+const MAGIC: &[u8; 8] = b"GYMBKENC";
+const ENVELOPE_VERSION: u8 = 1;
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const KEY_ID_LEN: usize = 8;
+const WRAP_NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+/// XChaCha20's 24 byte nonce minus the 5 bytes the STREAM construction uses
+/// for the chunk counter and the last-chunk flag.
+const STREAM_NONCE_LEN: usize = 19;
+/// version, Argon2 m/t/p costs, salt, key id, nonce and the wrapped key.
+const ENVELOPE_LEN: usize = 1 + 3 * 4 + SALT_LEN + KEY_ID_LEN + WRAP_NONCE_LEN + KEY_LEN + TAG_LEN;
+const HEADER_LEN: usize = MAGIC.len() + ENVELOPE_LEN + STREAM_NONCE_LEN;
+const CHUNK_LEN: usize = 64 * 1024;
+const SEALED_CHUNK_LEN: usize = CHUNK_LEN + TAG_LEN;
+
+/// Upper bounds for the Argon2 costs read from a backup, so a crafted file
+/// can't make us allocate gigabytes.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+pub const MIN_PASSPHRASE_LENGTH: usize = 12;
+
+/// What unlocks an encrypted backup.
+#[derive(Debug, Clone)]
+pub enum BackupSecret {
+    Passphrase(String),
+    RecoveryKey(String),
+}
+
+/// The data key together with its passphrase-wrapped form.
+#[derive(Clone)]
+pub struct BackupKey {
+    data_key: [u8; KEY_LEN],
+    envelope: Vec<u8>,
+}
+
+impl std::fmt::Debug for BackupKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackupKey")
+            .field("key_id", &self.key_id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl BackupKey {
+    /// A new random data key, wrapped with `passphrase`.
+    pub fn generate(passphrase: &str) -> AppResult<Self> {
+        validate_passphrase(passphrase)?;
+        let mut data_key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut data_key);
+        Self::wrap(data_key, passphrase)
+    }
+
+    /// The same data key, wrapped with another passphrase.
+    pub fn rewrap(&self, passphrase: &str) -> AppResult<Self> {
+        validate_passphrase(passphrase)?;
+        Self::wrap(self.data_key, passphrase)
+    }
+
+    fn wrap(data_key: [u8; KEY_LEN], passphrase: &str) -> AppResult<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; WRAP_NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut envelope = Vec::with_capacity(ENVELOPE_LEN);
+        envelope.push(ENVELOPE_VERSION);
+        envelope.extend_from_slice(&Params::DEFAULT_M_COST.to_le_bytes());
+        envelope.extend_from_slice(&Params::DEFAULT_T_COST.to_le_bytes());
+        envelope.extend_from_slice(&Params::DEFAULT_P_COST.to_le_bytes());
+        envelope.extend_from_slice(&salt);
+        envelope.extend_from_slice(&key_id_bytes(&data_key));
+        envelope.extend_from_slice(&nonce);
+
+        let kek = derive_kek(
+            passphrase,
+            &salt,
+            Params::DEFAULT_M_COST,
+            Params::DEFAULT_T_COST,
+            Params::DEFAULT_P_COST,
+        )?;
+        let wrapped = XChaCha20Poly1305::new(Key::from_slice(&kek))
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &data_key,
+                    aad: &envelope,
+                },
+            )
+            .map_err(|_| AppError::BackupFailed("Failed to wrap the backup key.".to_string()))?;
+        envelope.extend_from_slice(&wrapped);
+        Ok(Self { data_key, envelope })
+    }
+
+    /// Unwraps the data key of `envelope` with `secret`. A wrong passphrase
+    /// or recovery key is reported as such, not as a damaged backup.
+    fn unlock(envelope: &[u8], secret: &BackupSecret) -> AppResult<Self> {
+        let envelope = Envelope::parse(envelope)?;
+        let data_key = match secret {
+            BackupSecret::Passphrase(passphrase) => {
+                let kek = derive_kek(
+                    passphrase,
+                    envelope.salt,
+                    envelope.m_cost,
+                    envelope.t_cost,
+                    envelope.p_cost,
+                )?;
+                let data_key = XChaCha20Poly1305::new(Key::from_slice(&kek))
+                    .decrypt(
+                        XNonce::from_slice(envelope.nonce),
+                        Payload {
+                            msg: envelope.wrapped,
+                            aad: envelope.authenticated,
+                        },
+                    )
+                    .map_err(|_| {
+                        AppError::Translatable(TranslatableError::new(
+                            ErrorCodes::WRONG_BACKUP_PASSPHRASE,
+                            "The backup passphrase is wrong.",
+                        ))
+                    })?;
+                <[u8; KEY_LEN]>::try_from(data_key.as_slice()).map_err(|_| {
+                    AppError::RestoreFailed("The backup key has the wrong length.".to_string())
+                })?
+            }
+            BackupSecret::RecoveryKey(recovery_key) => {
+                let data_key = parse_recovery_key(recovery_key)?;
+                if key_id_bytes(&data_key) != envelope.key_id {
+                    return Err(invalid_recovery_key());
+                }
+                data_key
+            }
+        };
+        Ok(Self {
+            data_key,
+            envelope: envelope.bytes.to_vec(),
+        })
+    }
+
+    /// Unwraps the stored key with `passphrase`, to confirm the admin knows it.
+    pub fn verify_passphrase(&self, passphrase: &str) -> AppResult<()> {
+        Self::unlock(
+            &self.envelope,
+            &BackupSecret::Passphrase(passphrase.to_string()),
+        )
+        .map(|_| ())
+    }
+
+    /// Short fingerprint of the data key, shown to tell keys apart.
+    pub fn key_id(&self) -> String {
+        HEXLOWER.encode(&key_id_bytes(&self.data_key))
+    }
+
+    /// The data key in groups of four base32 characters, e.g.
+    /// `ABCD-EFGH-...`. Whoever holds it can read every backup.
+    pub fn recovery_key(&self) -> String {
+        BASE32_NOPAD
+            .encode(&self.data_key)
+            .as_bytes()
+            .chunks(4)
+            .map(|group| String::from_utf8_lossy(group).into_owned())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Starts an encrypted backup on `inner`. The last chunk is sealed by
+    /// [`EncryptingWriter::finish`], without it the backup won't open.
+    pub fn encrypting_writer<W: Write>(&self, mut inner: W) -> io::Result<EncryptingWriter<W>> {
+        let mut stream_nonce = [0u8; STREAM_NONCE_LEN];
+        OsRng.fill_bytes(&mut stream_nonce);
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&self.envelope);
+        header.extend_from_slice(&stream_nonce);
+        inner.write_all(&header)?;
+        Ok(EncryptingWriter {
+            inner,
+            encryptor: EncryptorBE32::from_aead(
+                XChaCha20Poly1305::new(Key::from_slice(&self.data_key)),
+                stream_nonce.as_slice().into(),
+            ),
+            header,
+            pending: Vec::with_capacity(CHUNK_LEN),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> AppResult<Vec<u8>> {
+        let mut writer = self.encrypting_writer(Vec::new())?;
+        writer.write_all(plaintext)?;
+        Ok(writer.finish()?)
+    }
+}
+
+/// Encrypts everything written to it in chunks of [`CHUNK_LEN`].
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    encryptor: EncryptorBE32<XChaCha20Poly1305>,
+    header: Vec<u8>,
+    /// Plaintext of the chunk being filled. A full chunk is only sealed once
+    /// more data follows, as the last one is sealed differently.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    /// Seals the last chunk and hands back the inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        let Self {
+            mut inner,
+            encryptor,
+            header,
+            pending,
+        } = self;
+        let sealed = encryptor
+            .encrypt_last(Payload {
+                msg: &pending,
+                aad: &header,
+            })
+            .map_err(|_| encryption_failed())?;
+        inner.write_all(&sealed)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pending.len() == CHUNK_LEN {
+            let sealed = self
+                .encryptor
+                .encrypt_next(Payload {
+                    msg: &self.pending,
+                    aad: &self.header,
+                })
+                .map_err(|_| encryption_failed())?;
+            self.inner.write_all(&sealed)?;
+            self.pending.clear();
+        }
+        let taken = buf.len().min(CHUNK_LEN - self.pending.len());
+        self.pending.extend_from_slice(&buf[..taken]);
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Opens a backup made by [`BackupKey::encrypting_writer`], checking `secret`
+/// before anything is decrypted. Damage found while reading shows up as
+/// [`io::ErrorKind::InvalidData`], see [`read_error`].
+pub fn decrypting_reader<R: Read>(
+    mut inner: R,
+    secret: &BackupSecret,
+) -> AppResult<DecryptingReader<R>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    inner
+        .by_ref()
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+    if header.len() < HEADER_LEN || !is_encrypted(&header) {
+        return Err(damaged());
+    }
+    let key = BackupKey::unlock(&header[MAGIC.len()..MAGIC.len() + ENVELOPE_LEN], secret)?;
+    let decryptor = DecryptorBE32::from_aead(
+        XChaCha20Poly1305::new(Key::from_slice(&key.data_key)),
+        header[MAGIC.len() + ENVELOPE_LEN..].into(),
+    );
+    let next = read_sealed_chunk(&mut inner)?;
+    Ok(DecryptingReader {
+        inner,
+        decryptor: Some(decryptor),
+        header,
+        next,
+        plaintext: Vec::new(),
+        position: 0,
+    })
+}
+
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    /// `None` once the last chunk is open.
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    header: Vec<u8>,
+    /// The sealed chunk after the current one, read ahead to tell whether the
+    /// current one is the last.
+    next: Vec<u8>,
+    plaintext: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            let sealed = std::mem::take(&mut self.next);
+            if sealed.len() == SEALED_CHUNK_LEN {
+                self.next = read_sealed_chunk(&mut self.inner)?;
+            }
+            let payload = Payload {
+                msg: &sealed,
+                aad: &self.header,
+            };
+            let opened = if self.next.is_empty() {
+                let decryptor = self.decryptor.take().expect("checked above");
+                decryptor.decrypt_last(payload)
+            } else {
+                let decryptor = self.decryptor.as_mut().expect("checked above");
+                decryptor.decrypt_next(payload)
+            };
+            self.plaintext = opened.map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The backup is damaged or was modified.",
+                )
+            })?;
+            self.position = 0;
+        }
+        let read = buf.len().min(self.plaintext.len() - self.position);
+        buf[..read].copy_from_slice(&self.plaintext[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Decrypts a backup made by [`BackupKey::encrypt`], checking `secret` first.
+pub fn decrypt(bytes: &[u8], secret: &BackupSecret) -> AppResult<Vec<u8>> {
+    let mut plaintext = Vec::with_capacity(bytes.len());
+    decrypting_reader(bytes, secret)?
+        .read_to_end(&mut plaintext)
+        .map_err(read_error)?;
+    Ok(plaintext)
+}
+
+/// Maps an error from reading a [`DecryptingReader`], reporting damage as such.
+pub fn read_error(e: io::Error) -> AppError {
+    if e.kind() == io::ErrorKind::InvalidData {
+        damaged()
+    } else {
+        AppError::Io(e)
+    }
+}
+
+fn read_sealed_chunk<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(SEALED_CHUNK_LEN);
+    reader
+        .by_ref()
+        .take(SEALED_CHUNK_LEN as u64)
+        .read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+fn encryption_failed() -> io::Error {
+    io::Error::other("Failed to encrypt the backup.")
+}
+
+/// The key backups are encrypted with, `None` until a passphrase is set.
+pub async fn load_key(pool: &SqlitePool) -> AppResult<Option<BackupKey>> {
+    let row: Option<(Vec<u8>, Vec<u8>)> =
+        sqlx::query_as("SELECT data_key, envelope FROM backup_encryption_key WHERE id = 1")
+            .fetch_optional(pool)
+            .await?;
+    row.map(|(data_key, envelope)| {
+        let data_key = <[u8; KEY_LEN]>::try_from(data_key.as_slice()).map_err(|_| {
+            AppError::Database("The stored backup key has the wrong length.".to_string())
+        })?;
+        Ok(BackupKey { data_key, envelope })
+    })
+    .transpose()
+}
+
+pub async fn save_key<'e, E>(executor: E, key: &BackupKey) -> AppResult<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO backup_encryption_key (id, key_id, data_key, envelope) VALUES (1, ?, ?, ?)
+         ON CONFLICT(id) DO UPDATE SET key_id = excluded.key_id, data_key = excluded.data_key,
+             envelope = excluded.envelope, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(key.key_id())
+    .bind(key.data_key.as_slice())
+    .bind(key.envelope.as_slice())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn status(pool: &SqlitePool) -> AppResult<BackupEncryptionStatus> {
+    let row: Option<(String, NaiveDateTime, Option<NaiveDateTime>)> = sqlx::query_as(
+        "SELECT key_id, created_at, updated_at FROM backup_encryption_key WHERE id = 1",
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(match row {
+        Some((key_id, created_at, updated_at)) => BackupEncryptionStatus {
+            enabled: true,
+            key_id: Some(key_id),
+            created_at: Some(created_at),
+            updated_at,
+        },
+        None => BackupEncryptionStatus {
+            enabled: false,
+            key_id: None,
+            created_at: None,
+            updated_at: None,
+        },
+    })
+}
+
+/// Keeps the current key in a restored database, which may hold an older
+/// wrapping of it or none at all. Expects the restored database migrated.
+pub async fn seed_restored_key(restored_db: &Path, current: &BackupKey) -> AppResult<()> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(restored_db)
+        .create_if_missing(false)
+        .connect()
+        .await?;
+    save_key(&mut conn, current).await
+}
+
+pub fn validate_passphrase(passphrase: &str) -> AppResult<()> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+        return Err(AppError::Validation(format!(
+            "The backup passphrase must have at least {} characters.",
+            MIN_PASSPHRASE_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+struct Envelope<'a> {
+    bytes: &'a [u8],
+    /// Everything but the wrapped key, which is bound to it as associated data.
+    authenticated: &'a [u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: &'a [u8],
+    key_id: &'a [u8],
+    nonce: &'a [u8],
+    wrapped: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    fn parse(bytes: &'a [u8]) -> AppResult<Self> {
+        if bytes.len() != ENVELOPE_LEN || bytes[0] != ENVELOPE_VERSION {
+            return Err(damaged());
+        }
+        let cost = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let (m_cost, t_cost, p_cost) = (cost(1), cost(5), cost(9));
+        if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+            return Err(damaged());
+        }
+        let salt_at = 13;
+        let key_id_at = salt_at + SALT_LEN;
+        let nonce_at = key_id_at + KEY_ID_LEN;
+        let wrapped_at = nonce_at + WRAP_NONCE_LEN;
+        Ok(Self {
+            bytes,
+            authenticated: &bytes[..wrapped_at],
+            m_cost,
+            t_cost,
+            p_cost,
+            salt: &bytes[salt_at..key_id_at],
+            key_id: &bytes[key_id_at..nonce_at],
+            nonce: &bytes[nonce_at..wrapped_at],
+            wrapped: &bytes[wrapped_at..],
+        })
+    }
+}
+
+fn derive_kek(
+    passphrase: &str,
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> AppResult<[u8; KEY_LEN]> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN))
+        .map_err(|e| AppError::Config(format!("Invalid Argon2 parameters: {}", e)))?;
+    let mut kek = [0u8; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut kek)
+        .map_err(|e| AppError::Config(format!("Failed to derive the backup key: {}", e)))?;
+    Ok(kek)
+}
+
+fn key_id_bytes(data_key: &[u8; KEY_LEN]) -> [u8; KEY_ID_LEN] {
+    let digest = Sha256::new()
+        .chain_update(b"gym-manager backup key id")
+        .chain_update(data_key)
+        .finalize();
+    let mut key_id = [0u8; KEY_ID_LEN];
+    key_id.copy_from_slice(&digest[..KEY_ID_LEN]);
+    key_id
+}
+
+fn parse_recovery_key(recovery_key: &str) -> AppResult<[u8; KEY_LEN]> {
+    let normalized: String = recovery_key
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    BASE32_NOPAD
+        .decode(normalized.as_bytes())
+        .ok()
+        .and_then(|bytes| <[u8; KEY_LEN]>::try_from(bytes.as_slice()).ok())
+        .ok_or_else(invalid_recovery_key)
+}
+
+fn invalid_recovery_key() -> AppError {
+    AppError::Translatable(TranslatableError::new(
+        ErrorCodes::INVALID_RECOVERY_KEY,
+        "The recovery key does not belong to this backup.",
+    ))
+}
+
+fn damaged() -> AppError {
+    AppError::RestoreFailed("The backup is damaged or was modified.".to_string())
+}
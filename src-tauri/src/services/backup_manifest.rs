@@ -0,0 +1,248 @@
+//! The manifest every backup carries, so a restore can tell it got back
+//! exactly what was backed up.
+//!
+//! The manifest is written in front of the compressed database, inside the
+//! encryption when there is any, so it is as tamper-proof as the backup
+//! itself. It records the SHA-256 and size of the database file, which
+//! unpacking checks as it writes the file out, and the migration version and
+//! row counts, which are checked against the unpacked database.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, SqliteConnection};
+use tokio::io::AsyncReadExt;
+
+use crate::clock::GymClock;
+use crate::db::MIGRATOR;
+use crate::dto::BackupManifest;
+use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
+use crate::services::backup_store::{BackupStore, ProgressReporter};
+
+/// Start of the manifest frame, followed by the length of the manifest JSON
+/// as a little-endian u32 and the JSON itself.
+const MAGIC: &[u8; 8] = b"GYMBKMAN";
+/// Manifests are a few KiB; anything larger is not one.
+const MAX_MANIFEST_LEN: u32 = 1024 * 1024;
+
+/// Describes the database snapshot at `snapshot_path`, taken now.
+pub async fn build(snapshot_path: &Path, clock: &GymClock) -> AppResult<BackupManifest> {
+    let (sha256, size_bytes) = file_sha256(snapshot_path).await?;
+    let mut conn = open_read_only(snapshot_path).await?;
+    Ok(BackupManifest {
+        sha256,
+        size_bytes,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        migration_version: migration_version(&mut conn).await?,
+        row_counts: row_counts(&mut conn).await?,
+        created_at: clock.now().fixed_offset(),
+        timezone: clock.timezone().to_string(),
+    })
+}
+
+pub fn write_frame(writer: &mut impl Write, manifest: &BackupManifest) -> io::Result<()> {
+    let json = serde_json::to_vec(manifest)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(&json)
+}
+
+/// Whether `bytes`, the start of an unpacked backup, is a manifest frame.
+/// Backups made before manifests were added have none.
+pub fn has_frame(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn read_frame(reader: &mut impl Read) -> io::Result<BackupManifest> {
+    let mut magic = [0u8; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if &magic != MAGIC || len > MAX_MANIFEST_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid backup manifest",
+        ));
+    }
+    let mut json = vec![0u8; len as usize];
+    reader.read_exact(&mut json)?;
+    serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Checks the unpacked database at `db_path` against its manifest: the
+/// migration version and the number of rows in every table must match. The
+/// checksum was already checked while unpacking.
+pub async fn verify_restored(db_path: &Path, manifest: &BackupManifest) -> AppResult<()> {
+    let latest_known = MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0);
+    if manifest.migration_version > latest_known {
+        tracing::error!(
+            "Backup from app version {} has migration {}, this version knows up to {}",
+            manifest.app_version,
+            manifest.migration_version,
+            latest_known
+        );
+        return Err(AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::BACKUP_FROM_NEWER_VERSION,
+            serde_json::json!({ "app_version": manifest.app_version }),
+            "This backup was made by a newer version of the app. Please update before restoring it.",
+        )));
+    }
+
+    let mut conn = open_read_only(db_path).await?;
+    let migration_version = migration_version(&mut conn).await?;
+    if migration_version != manifest.migration_version {
+        tracing::error!(
+            "Restored database is at migration {}, the manifest says {}",
+            migration_version,
+            manifest.migration_version
+        );
+        return Err(mismatch());
+    }
+    let counts = row_counts(&mut conn).await?;
+    if counts != manifest.row_counts {
+        tracing::error!(
+            "Restored row counts {:?} differ from the manifest {:?}",
+            counts,
+            manifest.row_counts
+        );
+        return Err(mismatch());
+    }
+    Ok(())
+}
+
+/// Reads the backup `version_id` back from `store` and checks that it is
+/// byte for byte the packed backup at `packed_path` that was uploaded.
+pub async fn verify_upload(
+    store: &dyn BackupStore,
+    version_id: &str,
+    packed_path: &Path,
+    progress: &ProgressReporter,
+) -> AppResult<()> {
+    let file_name = packed_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let download = packed_path.with_file_name(format!("{}.verify", file_name));
+    let verified = async {
+        store
+            .download(Some(version_id), &download, progress)
+            .await?;
+        Ok::<_, AppError>(file_sha256(&download).await? == file_sha256(packed_path).await?)
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&download).await;
+    if !verified? {
+        tracing::error!(
+            "Backup {} in {:?} differs from the upload",
+            version_id,
+            store
+        );
+        return Err(AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::BACKUP_VERIFICATION_FAILED,
+            serde_json::json!({ "version_id": version_id }),
+            "The stored backup differs from what was uploaded.",
+        )));
+    }
+    tracing::info!("Verified backup {} in {:?}", version_id, store);
+    Ok(())
+}
+
+/// Hex SHA-256 and size of the file at `path`.
+pub async fn file_sha256(path: &Path) -> AppResult<(String, u64)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((data_encoding::HEXLOWER.encode(&hasher.finalize()), size))
+}
+
+/// Passes everything written on to `inner`, hashing and counting it.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    /// `inner`, the hex SHA-256 and the number of bytes written.
+    pub fn finish(self) -> (W, String, u64) {
+        let sha256 = data_encoding::HEXLOWER.encode(&self.hasher.finalize());
+        (self.inner, sha256, self.written)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn mismatch() -> AppError {
+    AppError::Translatable(TranslatableError::new(
+        ErrorCodes::BACKUP_MANIFEST_MISMATCH,
+        "The backup doesn't match its manifest and was not restored.",
+    ))
+}
+
+async fn open_read_only(db_path: &Path) -> AppResult<SqliteConnection> {
+    Ok(SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(false)
+        .read_only(true)
+        .connect()
+        .await?)
+}
+
+async fn migration_version(conn: &mut SqliteConnection) -> AppResult<i64> {
+    let version: Option<i64> =
+        sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1")
+            .fetch_one(&mut *conn)
+            .await?;
+    Ok(version.unwrap_or(0))
+}
+
+async fn row_counts(conn: &mut SqliteConnection) -> AppResult<BTreeMap<String, i64>> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut counts = BTreeMap::new();
+    for table in tables {
+        let count: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM \"{}\"",
+            table.replace('"', "\"\"")
+        ))
+        .fetch_one(&mut *conn)
+        .await?;
+        counts.insert(table, count);
+    }
+    Ok(counts)
+}
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{
+    backup_name, newest_first, parse_backup_name, require_backup_name, BackupStore,
+    ProgressReporter, STREAM_BUFFER_SIZE,
+};
+use crate::config::AppSettings;
+use crate::dto::{BackupMetadata, LocalBackupCopy, LocalBackupStatus, TransferDirection};
+use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
+
+/// A folder, on this PC or a mounted share, holding backups named after the
+/// time they were taken. Files that don't follow that naming are left alone.
+#[derive(Debug, Clone)]
+pub struct DirectoryStore {
+    directory: PathBuf,
+    /// Days and weeks to keep a copy of, `None` to keep everything.
+    retention: Option<(u32, u32)>,
+}
+
+impl DirectoryStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            retention: None,
+        }
+    }
+
+    /// The local backup folder with its rotation, `None` when no folder is
+    /// configured.
+    pub fn local_backup(settings: &AppSettings) -> Option<Self> {
+        let local = &settings.local_backup;
+        let directory = local.directory.as_deref()?;
+        Some(Self {
+            directory: PathBuf::from(directory),
+            retention: Some((local.keep_daily, local.keep_weekly)),
+        })
+    }
+
+    /// The folder has to exist already. A USB drive that isn't plugged in
+    /// must not turn into a new folder on the system disk.
+    fn ensure_available(&self) -> AppResult<()> {
+        if self.directory.is_dir() {
+            return Ok(());
+        }
+        tracing::error!(
+            "Backup folder {:?} does not exist or is not mounted.",
+            self.directory
+        );
+        Err(AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::LOCAL_BACKUP_DIR_UNAVAILABLE,
+            serde_json::json!({ "directory": self.directory }),
+            "The backup folder is not available. Is the drive connected?",
+        )))
+    }
+
+    pub fn free_bytes(&self) -> AppResult<u64> {
+        self.ensure_available()?;
+        fs2::available_space(&self.directory).map_err(|e| {
+            tracing::error!("Failed to read free space of {:?}: {}", self.directory, e);
+            AppError::Io(e)
+        })
+    }
+
+    /// Copies the backup at `source`, taken at `taken_at`, gym-local time,
+    /// into the folder, then deletes the copies no longer covered by the
+    /// retention.
+    pub async fn store(
+        &self,
+        source: &Path,
+        taken_at: NaiveDateTime,
+        progress: &ProgressReporter,
+    ) -> AppResult<LocalBackupCopy> {
+        let free_bytes = self.free_bytes()?;
+        let size_bytes = tokio::fs::metadata(source).await?.len();
+        if free_bytes < size_bytes {
+            return Err(AppError::Translatable(TranslatableError::with_params(
+                ErrorCodes::INSUFFICIENT_DISK_SPACE,
+                serde_json::json!({ "required": size_bytes, "available": free_bytes }),
+                "Not enough free space for the backup.",
+            )));
+        }
+
+        let file_name = backup_name(taken_at);
+        // Written under another name first, so an unplugged drive never
+        // leaves a truncated copy that looks complete.
+        let partial_path = self.directory.join(format!("{}.partial", file_name));
+        let path = self.directory.join(&file_name);
+        let written =
+            match copy_file(source, &partial_path, TransferDirection::Upload, progress).await {
+                Ok(()) => tokio::fs::rename(&partial_path, &path).await,
+                Err(e) => Err(e),
+            };
+        if let Err(e) = written {
+            tracing::error!("Failed to write backup {:?}: {}", path, e);
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            return Err(AppError::Io(e));
+        }
+        tracing::info!("Backup written to {:?}", path);
+
+        self.rotate().await?;
+        Ok(LocalBackupCopy {
+            file_name,
+            taken_at,
+            size_bytes,
+        })
+    }
+
+    /// Copies in the folder, newest first.
+    pub async fn copies(&self) -> AppResult<Vec<LocalBackupCopy>> {
+        self.ensure_available()?;
+        let mut entries = tokio::fs::read_dir(&self.directory).await?;
+        let mut copies = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(taken_at) = parse_backup_name(&file_name) else {
+                continue;
+            };
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                copies.push(LocalBackupCopy {
+                    file_name,
+                    taken_at,
+                    size_bytes: metadata.len(),
+                });
+            }
+        }
+        copies.sort_by(|a, b| b.taken_at.cmp(&a.taken_at));
+        Ok(copies)
+    }
+
+    pub async fn status(&self) -> AppResult<LocalBackupStatus> {
+        Ok(LocalBackupStatus {
+            directory: self.directory.to_string_lossy().into_owned(),
+            free_bytes: self.free_bytes()?,
+            copies: self.copies().await?,
+        })
+    }
+
+    /// Keeps the newest copy of each of the last `keep_daily` days and of each
+    /// of the last `keep_weekly` weeks that have copies, and deletes the
+    /// rest. Returns the deleted copies.
+    pub async fn rotate(&self) -> AppResult<Vec<LocalBackupCopy>> {
+        let Some((keep_daily, keep_weekly)) = self.retention else {
+            return Ok(Vec::new());
+        };
+        let mut days = HashSet::new();
+        let mut weeks = HashSet::new();
+        let mut expired = Vec::new();
+        for copy in self.copies().await? {
+            let day = copy.taken_at.date();
+            let week = day.iso_week();
+            let keeps_day = !days.contains(&day) && days.len() < keep_daily as usize;
+            let keeps_week = !weeks.contains(&week) && weeks.len() < keep_weekly as usize;
+            if keeps_day {
+                days.insert(day);
+            }
+            if keeps_week {
+                weeks.insert(week);
+            }
+            if !keeps_day && !keeps_week {
+                expired.push(copy);
+            }
+        }
+
+        for copy in &expired {
+            let path = self.directory.join(&copy.file_name);
+            tokio::fs::remove_file(&path).await.map_err(|e| {
+                tracing::error!("Failed to remove old backup {:?}: {}", path, e);
+                AppError::Io(e)
+            })?;
+            tracing::info!("Removed old backup {:?}", path);
+        }
+        Ok(expired)
+    }
+}
+
+#[async_trait]
+impl BackupStore for DirectoryStore {
+    async fn upload(
+        &self,
+        source: &Path,
+        taken_at: NaiveDateTime,
+        progress: &ProgressReporter,
+    ) -> AppResult<String> {
+        Ok(self.store(source, taken_at, progress).await?.file_name)
+    }
+
+    async fn list(&self) -> AppResult<Vec<BackupMetadata>> {
+        let mut backups = Vec::new();
+        for copy in self.copies().await? {
+            let modified = tokio::fs::metadata(self.directory.join(&copy.file_name))
+                .await?
+                .modified()?;
+            backups.push(BackupMetadata {
+                last_modified: DateTime::<Utc>::from(modified).to_rfc3339(),
+                size_bytes: copy.size_bytes,
+                version_id: copy.file_name,
+                is_latest: false,
+            });
+        }
+        Ok(newest_first(backups))
+    }
+
+    async fn download(
+        &self,
+        version_id: Option<&str>,
+        dest: &Path,
+        progress: &ProgressReporter,
+    ) -> AppResult<()> {
+        self.ensure_available()?;
+        let name = super::resolve_version(self, version_id).await?;
+        let path = self.directory.join(&name);
+        tracing::info!("Copying backup {:?} to {:?}", path, dest);
+        copy_file(&path, dest, TransferDirection::Download, progress)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to copy backup {:?}: {}", path, e);
+                AppError::RestoreFailed(format!("Failed to read backup {}: {}", name, e))
+            })?;
+        Ok(())
+    }
+
+    async fn delete(&self, version_id: &str) -> AppResult<()> {
+        self.ensure_available()?;
+        require_backup_name(version_id)?;
+        let path = self.directory.join(version_id);
+        tokio::fs::remove_file(&path).await.map_err(|e| {
+            tracing::error!("Failed to delete backup {:?}: {}", path, e);
+            AppError::Io(e)
+        })?;
+        tracing::info!("Deleted backup {:?}", path);
+        Ok(())
+    }
+}
+
+/// Copies `source` to `dest`, reporting progress, and syncs the copy to disk
+/// so a drive pulled right after holds it completely.
+async fn copy_file(
+    source: &Path,
+    dest: &Path,
+    direction: TransferDirection,
+    progress: &ProgressReporter,
+) -> std::io::Result<()> {
+    let mut input = tokio::fs::File::open(source).await?;
+    let total = input.metadata().await?.len();
+    let mut output = tokio::fs::File::create(dest).await?;
+    let mut buffer = vec![0u8; STREAM_BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        let read = input.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        output.write_all(&buffer[..read]).await?;
+        copied += read as u64;
+        progress.report(direction, copied, Some(total));
+    }
+    output.sync_all().await
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose;
+use base64::Engine;
+use chrono::NaiveDateTime;
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use tokio::io::AsyncWriteExt;
+
+use super::{file_body, response_error, with_retries, BackupStore, ProgressReporter};
+use crate::config::parse_backup_url;
+use crate::dto::{BackupMetadata, TransferDirection};
+use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
+
+/// Our backup gateway, configured by a URL carrying its `token`. The gateway
+/// keeps the versions itself, so the version ids are whatever it returns.
+#[derive(Clone)]
+pub struct HttpGatewayStore {
+    base_url: String,
+    token: String,
+    gym_code: String,
+}
+
+impl fmt::Debug for HttpGatewayStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpGatewayStore")
+            .field("base_url", &self.base_url)
+            .field("gym_code", &self.gym_code)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpGatewayStore {
+    pub fn new(backup_url: &str, gym_code: &str) -> AppResult<Self> {
+        let (base_url, token) = parse_backup_url(backup_url).map_err(|e| {
+            tracing::error!("Invalid backup URL format: {}", e);
+            AppError::Translatable(TranslatableError::new(
+                ErrorCodes::INVALID_BACKUP_URL,
+                "Invalid backup URL format",
+            ))
+        })?;
+        Ok(Self {
+            base_url,
+            token,
+            gym_code: gym_code.to_string(),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .request(method, format!("{}{}", self.base_url, path))
+            .header("X-Api-Key", &self.token)
+            .header("X-Gym-Code", &self.gym_code)
+    }
+}
+
+#[async_trait]
+impl BackupStore for HttpGatewayStore {
+    async fn upload(
+        &self,
+        source: &Path,
+        _taken_at: NaiveDateTime,
+        progress: &ProgressReporter,
+    ) -> AppResult<String> {
+        with_retries("Backup", || async move {
+            let (body, size) = file_body(source, progress).await?;
+            let response = self
+                .request(reqwest::Method::POST, "/backup")
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .header(CONTENT_LENGTH, size)
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| {
+                    tracing::error!("Backup request failed: {:?}", e);
+                    AppError::Reqwest(e)
+                })?;
+            if !response.status().is_success() {
+                return Err(response_error(response, "Backup").await);
+            }
+            Ok(())
+        })
+        .await?;
+        // The gateway picks the version id; ours is the newest one now.
+        self.list()
+            .await?
+            .into_iter()
+            .find(|backup| backup.is_latest)
+            .map(|backup| backup.version_id)
+            .ok_or_else(|| AppError::BackupFailed("The uploaded backup is not listed.".to_string()))
+    }
+
+    async fn list(&self) -> AppResult<Vec<BackupMetadata>> {
+        let response = self
+            .request(reqwest::Method::GET, "/backup/metadata")
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to send request to backup metadata endpoint: {}", e);
+                AppError::BackupFailed("Failed to get metadata!".to_string())
+            })?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "Fetching backup metadata").await);
+        }
+        let response_text = response.text().await.map_err(|e| {
+            tracing::error!("Failed to read response text: {}", e);
+            AppError::BackupFailed("Failed to read metadata response".to_string())
+        })?;
+        serde_json::from_str::<Vec<BackupMetadata>>(&response_text).map_err(|e| {
+            tracing::error!("Failed to parse backup metadata response: {}", e);
+            AppError::BackupFailed("Failed to parse metadata response".to_string())
+        })
+    }
+
+    async fn download(
+        &self,
+        version_id: Option<&str>,
+        dest: &Path,
+        progress: &ProgressReporter,
+    ) -> AppResult<()> {
+        tracing::info!("Downloading backup {:?} to: {:?}", version_id, dest);
+        // The gateway can't send ranges of its base64 body, so a failed
+        // download starts over.
+        with_retries("Backup download", || async move {
+            let mut request = self.request(reqwest::Method::GET, "/backup");
+            if let Some(vid) = version_id.filter(|vid| !vid.is_empty() && *vid != "null") {
+                request = request.query(&[("versionId", vid)]);
+            }
+            let response = request.send().await.map_err(|e| {
+                tracing::error!("Failed to download backup file: {:?}", e);
+                AppError::Reqwest(e)
+            })?;
+            if !response.status().is_success() {
+                return Err(response_error(response, "Backup download").await);
+            }
+            write_base64_body(response, dest, progress).await
+        })
+        .await?;
+        tracing::info!("Backup file downloaded successfully.");
+        Ok(())
+    }
+
+    async fn delete(&self, version_id: &str) -> AppResult<()> {
+        let response = self
+            .request(reqwest::Method::DELETE, "/backup")
+            .query(&[("versionId", version_id)])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "Deleting the backup").await);
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the base64 body our Lambda sends into `dest` as it arrives.
+/// Progress counts the encoded bytes, since that's what the length is of.
+async fn write_base64_body(
+    response: reqwest::Response,
+    dest: &Path,
+    progress: &ProgressReporter,
+) -> AppResult<()> {
+    let total = response.content_length();
+    let mut file = tokio::fs::File::create(dest).await?;
+    let mut pending = Vec::new();
+    let mut received = 0;
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        pending.extend(chunk.iter().filter(|b| !b.is_ascii_whitespace()));
+        // Only whole 4 character groups decode on their own.
+        let whole = pending.len() - pending.len() % 4;
+        let decoded = general_purpose::STANDARD.decode(&pending[..whole])?;
+        file.write_all(&decoded).await?;
+        pending.drain(..whole);
+        progress.report(TransferDirection::Download, received, total);
+    }
+    if !pending.is_empty() {
+        tracing::error!("Backup download ended inside a base64 group");
+        return Err(AppError::RestoreFailed(
+            "The downloaded backup is incomplete.".to_string(),
+        ));
+    }
+    file.sync_all().await?;
+    Ok(())
+}
@@ -0,0 +1,372 @@
+//! Storage backends for backups. Each one keeps packed database snapshots
+//! and can list, fetch and delete them again; the settings pick which one
+//! scheduled backups go to. Apart from the HTTP gateway, which keeps its own
+//! versions, backups are named after the gym-local time they were taken.
+//!
+//! Backups are streamed from and to files rather than held in memory, and
+//! transfers that fail halfway are retried, downloads from where they stopped
+//! when the server allows it.
+
+mod directory;
+mod http;
+mod s3;
+mod webdav;
+
+pub use directory::DirectoryStore;
+pub use http::HttpGatewayStore;
+pub use s3::S3Store;
+pub use webdav::WebDavStore;
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use futures_util::{StreamExt, TryStreamExt};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use crate::config::{AppSettings, BackupStoreSettings};
+use crate::dto::{BackupMetadata, TransferDirection, TransferProgress};
+use crate::error::{AppError, ErrorCodes, Result as AppResult, TranslatableError};
+
+const BACKUP_NAME_PREFIX: &str = "gym-backup-";
+const BACKUP_NAME_EXTENSION: &str = ".sqlite";
+const BACKUP_NAME_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+const TRANSFER_ATTEMPTS: u32 = 3;
+/// Waited before the first retry, and that much longer before each next one.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const STREAM_BUFFER_SIZE: usize = 256 * 1024;
+
+#[async_trait]
+pub trait BackupStore: Send + Sync + Debug {
+    /// Stores the packed backup at `source`, taken at `taken_at`, gym-local
+    /// time. Returns the version id it is stored under.
+    async fn upload(
+        &self,
+        source: &Path,
+        taken_at: NaiveDateTime,
+        progress: &ProgressReporter,
+    ) -> AppResult<String>;
+
+    /// Backups in the store, newest first.
+    async fn list(&self) -> AppResult<Vec<BackupMetadata>>;
+
+    /// Writes the backup `version_id`, or the newest one, to `dest`.
+    async fn download(
+        &self,
+        version_id: Option<&str>,
+        dest: &Path,
+        progress: &ProgressReporter,
+    ) -> AppResult<()>;
+
+    async fn delete(&self, version_id: &str) -> AppResult<()>;
+}
+
+/// The store selected in the settings, `None` while backups are disabled.
+pub fn from_settings(settings: &AppSettings) -> AppResult<Option<Box<dyn BackupStore>>> {
+    open(&settings.backup_store, &settings.gym_code)
+}
+
+/// Opens the store described by `store`, which also validates it.
+pub fn open(
+    store: &BackupStoreSettings,
+    gym_code: &str,
+) -> AppResult<Option<Box<dyn BackupStore>>> {
+    let store: Box<dyn BackupStore> = match store {
+        BackupStoreSettings::Disabled => return Ok(None),
+        BackupStoreSettings::Http { url } => Box::new(HttpGatewayStore::new(url, gym_code)?),
+        BackupStoreSettings::S3(s3) => Box::new(S3Store::new(s3)?),
+        BackupStoreSettings::WebDav(webdav) => Box::new(WebDavStore::new(webdav)?),
+        BackupStoreSettings::Directory { path } => {
+            if !Path::new(path).is_absolute() {
+                return Err(AppError::Validation(
+                    "The backup folder must be an absolute path.".to_string(),
+                ));
+            }
+            Box::new(DirectoryStore::new(path))
+        }
+    };
+    Ok(Some(store))
+}
+
+/// Like [`from_settings`], for actions that can't do without a store.
+pub fn configured(settings: &AppSettings) -> AppResult<Box<dyn BackupStore>> {
+    from_settings(settings)?.ok_or_else(|| {
+        AppError::Translatable(TranslatableError::new(
+            ErrorCodes::BACKUP_NOT_CONFIGURED,
+            "No backup storage is configured. Please choose one in the settings.",
+        ))
+    })
+}
+
+fn backup_name(taken_at: NaiveDateTime) -> String {
+    format!(
+        "{}{}{}",
+        BACKUP_NAME_PREFIX,
+        taken_at.format(BACKUP_NAME_TIME_FORMAT),
+        BACKUP_NAME_EXTENSION
+    )
+}
+
+/// When a backup was taken, read from its name. `None` for anything that
+/// isn't named like a backup, which also keeps names such as `../x` out.
+fn parse_backup_name(name: &str) -> Option<NaiveDateTime> {
+    let stamp = name
+        .strip_prefix(BACKUP_NAME_PREFIX)?
+        .strip_suffix(BACKUP_NAME_EXTENSION)?;
+    NaiveDateTime::parse_from_str(stamp, BACKUP_NAME_TIME_FORMAT).ok()
+}
+
+fn require_backup_name(version_id: &str) -> AppResult<NaiveDateTime> {
+    parse_backup_name(version_id)
+        .ok_or_else(|| AppError::Validation(format!("Unknown backup: {}", version_id)))
+}
+
+/// Sorts backups named by [`backup_name`] newest first and marks the newest.
+fn newest_first(mut backups: Vec<BackupMetadata>) -> Vec<BackupMetadata> {
+    backups.sort_by(|a, b| b.version_id.cmp(&a.version_id));
+    for (i, backup) in backups.iter_mut().enumerate() {
+        backup.is_latest = i == 0;
+    }
+    backups
+}
+
+/// The backup to fetch: `version_id` if given, the newest one otherwise.
+async fn resolve_version(store: &dyn BackupStore, version_id: Option<&str>) -> AppResult<String> {
+    match version_id.filter(|id| !id.is_empty() && *id != "null") {
+        Some(id) => {
+            require_backup_name(id)?;
+            Ok(id.to_string())
+        }
+        None => store
+            .list()
+            .await?
+            .into_iter()
+            .next()
+            .map(|latest| latest.version_id)
+            .ok_or_else(|| AppError::RestoreFailed("The backup store is empty.".to_string())),
+    }
+}
+
+/// Passes the progress of uploads and downloads on, e.g. to the UI. Reports
+/// come at most every [`PROGRESS_INTERVAL`], apart from the final one.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    callback: Option<Arc<dyn Fn(TransferProgress) + Send + Sync>>,
+    last_report: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("enabled", &self.callback.is_some())
+            .finish()
+    }
+}
+
+impl ProgressReporter {
+    pub fn new(callback: impl Fn(TransferProgress) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(Arc::new(callback)),
+            last_report: Arc::default(),
+        }
+    }
+
+    /// Reports nowhere.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn report(
+        &self,
+        direction: TransferDirection,
+        transferred_bytes: u64,
+        total_bytes: Option<u64>,
+    ) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let finished = total_bytes == Some(transferred_bytes);
+        {
+            let mut last_report = self
+                .last_report
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if !finished && last_report.is_some_and(|at| at.elapsed() < PROGRESS_INTERVAL) {
+                return;
+            }
+            *last_report = Some(Instant::now());
+        }
+        callback(TransferProgress {
+            direction,
+            transferred_bytes,
+            total_bytes,
+        });
+    }
+}
+
+/// Runs `attempt` until it succeeds, up to [`TRANSFER_ATTEMPTS`] times for
+/// errors that may go away, like a dropped connection or a busy server.
+async fn with_retries<T, F, Fut>(action: &str, mut attempt: F) -> AppResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AppResult<T>>,
+{
+    let mut tries = 1;
+    loop {
+        match attempt().await {
+            Err(e) if tries < TRANSFER_ATTEMPTS && is_transient(&e) => {
+                tracing::warn!(
+                    "{} failed (attempt {} of {}), retrying: {}",
+                    action,
+                    tries,
+                    TRANSFER_ATTEMPTS,
+                    e
+                );
+                tokio::time::sleep(RETRY_DELAY * tries).await;
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(e: &AppError) -> bool {
+    match e {
+        AppError::Reqwest(_) => true,
+        AppError::ApiError { status, .. } => {
+            *status >= 500 || *status == StatusCode::TOO_MANY_REQUESTS.as_u16()
+        }
+        _ => false,
+    }
+}
+
+/// A request body streaming the file at `path`, and its length, which has to
+/// be sent along as streamed bodies have none of their own.
+async fn file_body(path: &Path, progress: &ProgressReporter) -> AppResult<(reqwest::Body, u64)> {
+    let file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let progress = progress.clone();
+    let mut sent = 0;
+    let stream = ReaderStream::with_capacity(file, STREAM_BUFFER_SIZE).inspect_ok(move |chunk| {
+        sent += chunk.len() as u64;
+        progress.report(TransferDirection::Upload, sent, Some(size));
+    });
+    Ok((reqwest::Body::wrap_stream(stream), size))
+}
+
+/// Streams the response to `request` into `dest`. When the connection drops
+/// the download continues where it stopped with a `Range` request, or starts
+/// over if the server doesn't do ranges.
+async fn download_resumable<F>(
+    action: &str,
+    request: F,
+    dest: &Path,
+    progress: &ProgressReporter,
+) -> AppResult<()>
+where
+    F: Fn() -> AppResult<reqwest::RequestBuilder>,
+{
+    let mut file = tokio::fs::File::create(dest).await?;
+    let mut written = 0;
+    let mut total = None;
+    let mut tries = 1;
+    loop {
+        match download_into(
+            action,
+            &request,
+            &mut file,
+            &mut written,
+            &mut total,
+            progress,
+        )
+        .await
+        {
+            Err(e) if tries < TRANSFER_ATTEMPTS && is_transient(&e) => {
+                tracing::warn!(
+                    "{} stopped after {} bytes (attempt {} of {}), resuming: {}",
+                    action,
+                    written,
+                    tries,
+                    TRANSFER_ATTEMPTS,
+                    e
+                );
+                tokio::time::sleep(RETRY_DELAY * tries).await;
+                tries += 1;
+            }
+            result => {
+                result?;
+                break;
+            }
+        }
+    }
+    file.sync_all().await?;
+    Ok(())
+}
+
+async fn download_into<F>(
+    action: &str,
+    request: &F,
+    file: &mut tokio::fs::File,
+    written: &mut u64,
+    total: &mut Option<u64>,
+    progress: &ProgressReporter,
+) -> AppResult<()>
+where
+    F: Fn() -> AppResult<reqwest::RequestBuilder>,
+{
+    let mut request = request()?;
+    if *written > 0 {
+        request = request.header(RANGE, format!("bytes={}-", written));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE && *total == Some(*written) {
+        // Everything had arrived when the connection dropped.
+        return Ok(());
+    }
+    if !status.is_success() {
+        return Err(response_error(response, action).await);
+    }
+    if *written > 0 && status != StatusCode::PARTIAL_CONTENT {
+        // The server sent the whole file again.
+        file.set_len(0).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+        *written = 0;
+    }
+    if total.is_none() {
+        *total = response.content_length().map(|length| length + *written);
+    }
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        *written += chunk.len() as u64;
+        progress.report(TransferDirection::Download, *written, *total);
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Maps a non-success HTTP response to an error with its status and body.
+async fn response_error(response: reqwest::Response, action: &str) -> AppError {
+    let status = response.status();
+    let error_text = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "No error message".to_string());
+    tracing::error!("{} failed with status {}: {}", action, status, error_text);
+    AppError::ApiError {
+        status: status.as_u16(),
+        message: format!("{} failed: {}", action, error_text),
+    }
+}
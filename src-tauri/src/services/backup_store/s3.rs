@@ -0,0 +1,511 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use reqwest::header::ETAG;
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use super::{
+    backup_name, download_resumable, newest_first, parse_backup_name, require_backup_name,
+    resolve_version, response_error, with_retries, BackupStore, ProgressReporter,
+};
+use crate::config::S3StoreSettings;
+use crate::dto::{BackupMetadata, TransferDirection};
+use crate::error::{AppError, Result as AppResult};
+
+const EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+/// Backups larger than this go up in parts of this size, each retried on its
+/// own. S3 wants parts of at least 5 MiB, apart from the last one.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// An S3 compatible bucket (AWS, MinIO, Wasabi, Backblaze B2, ...). Requests
+/// are signed with AWS Signature Version 4.
+#[derive(Clone)]
+pub struct S3Store {
+    endpoint: url::Url,
+    region: String,
+    bucket: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+    virtual_hosted_style: bool,
+}
+
+impl fmt::Debug for S3Store {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Store")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("region", &self.region)
+            .field("bucket", &self.bucket)
+            .field("prefix", &self.prefix)
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Store {
+    pub fn new(settings: &S3StoreSettings) -> AppResult<Self> {
+        let endpoint = url::Url::parse(settings.endpoint.trim()).map_err(|_| {
+            AppError::Validation(format!("Invalid S3 endpoint: {}", settings.endpoint))
+        })?;
+        if !matches!(endpoint.scheme(), "http" | "https") || endpoint.host_str().is_none() {
+            return Err(AppError::Validation(format!(
+                "Invalid S3 endpoint: {}",
+                settings.endpoint
+            )));
+        }
+        for (field, value) in [
+            ("region", &settings.region),
+            ("bucket", &settings.bucket),
+            ("access key", &settings.access_key_id),
+            ("secret key", &settings.secret_access_key),
+        ] {
+            if value.trim().is_empty() {
+                return Err(AppError::Validation(format!(
+                    "The S3 {} is required.",
+                    field
+                )));
+            }
+        }
+        let prefix = settings.prefix.trim_matches('/');
+        Ok(Self {
+            endpoint,
+            region: settings.region.trim().to_string(),
+            bucket: settings.bucket.trim().to_string(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+            access_key_id: settings.access_key_id.trim().to_string(),
+            secret_access_key: settings.secret_access_key.clone(),
+            virtual_hosted_style: settings.virtual_hosted_style,
+        })
+    }
+
+    /// Host (with port, if not the default one) and path of `key` in the
+    /// bucket, or of the bucket itself for an empty key.
+    fn host_and_path(&self, key: &str) -> (String, String) {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let base_path = self.endpoint.path().trim_end_matches('/');
+        if self.virtual_hosted_style {
+            (
+                format!("{}.{}", self.bucket, host),
+                format!("{}/{}", base_path, uri_encode(key, false)),
+            )
+        } else {
+            (
+                host,
+                format!(
+                    "{}/{}/{}",
+                    base_path,
+                    uri_encode(&self.bucket, true),
+                    uri_encode(key, false)
+                ),
+            )
+        }
+    }
+
+    /// A SigV4 signed request for `key`, with `query` given unencoded. The
+    /// `payload` is only hashed; the caller attaches it as the body.
+    fn signed_request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        payload: Option<&[u8]>,
+    ) -> reqwest::RequestBuilder {
+        let (host, path) = self.host_and_path(key);
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let payload_hash = match payload {
+            Some(payload) => HEXLOWER.encode(&Sha256::digest(payload)),
+            None => EMPTY_PAYLOAD_SHA256.to_string(),
+        };
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_stamp = now.format("%Y%m%d").to_string();
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            path,
+            canonical_query,
+            host,
+            payload_hash,
+            amz_date,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date_stamp, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            HEXLOWER.encode(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+            hmac_sha256(
+                format!("AWS4{}", self.secret_access_key).as_bytes(),
+                date_stamp.as_bytes(),
+            ),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+        let signature = HEXLOWER.encode(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        );
+
+        let mut url = format!("{}://{}{}", self.endpoint.scheme(), host, path);
+        if !canonical_query.is_empty() {
+            url = format!("{}?{}", url, canonical_query);
+        }
+        reqwest::Client::new()
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header(reqwest::header::AUTHORIZATION, authorization)
+    }
+
+    /// Sends a signed request for `key` with `body`, see [`Self::signed_request`].
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Option<Vec<u8>>,
+    ) -> AppResult<reqwest::Response> {
+        let mut request = self.signed_request(method, key, query, body.as_deref());
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        request.send().await.map_err(|e| {
+            tracing::error!("S3 request failed: {:?}", e);
+            AppError::Reqwest(e)
+        })
+    }
+
+    /// Uploads `source` in parts of [`PART_SIZE`]. A failed upload is aborted
+    /// so the bucket doesn't keep (and bill for) the parts already sent.
+    async fn upload_multipart(
+        &self,
+        key: &str,
+        source: &Path,
+        size: u64,
+        progress: &ProgressReporter,
+    ) -> AppResult<()> {
+        let response = self
+            .send(Method::POST, key, &[("uploads", "")], None)
+            .await?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "Starting the S3 upload").await);
+        }
+        let upload_id = parse_upload_id(&response.text().await?)?;
+
+        let result = self
+            .upload_parts(key, &upload_id, source, size, progress)
+            .await;
+        if result.is_err() {
+            let aborted = self
+                .send(
+                    Method::DELETE,
+                    key,
+                    &[("uploadId", upload_id.as_str())],
+                    None,
+                )
+                .await;
+            if !aborted.is_ok_and(|response| response.status().is_success()) {
+                tracing::warn!("Failed to abort S3 upload {} of {}", upload_id, key);
+            }
+        }
+        result
+    }
+
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        source: &Path,
+        size: u64,
+        progress: &ProgressReporter,
+    ) -> AppResult<()> {
+        let mut file = tokio::fs::File::open(source).await?;
+        let mut part = Vec::with_capacity(PART_SIZE);
+        let mut etags = Vec::new();
+        let mut sent = 0;
+        loop {
+            part.clear();
+            (&mut file)
+                .take(PART_SIZE as u64)
+                .read_to_end(&mut part)
+                .await?;
+            if part.is_empty() {
+                break;
+            }
+            let part_number = (etags.len() + 1).to_string();
+            let query = [
+                ("partNumber", part_number.as_str()),
+                ("uploadId", upload_id),
+            ];
+            let (query, body) = (&query, &part);
+            let etag = with_retries("S3 part upload", || async move {
+                let response = self
+                    .send(Method::PUT, key, query, Some(body.clone()))
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(response_error(response, "S3 part upload").await);
+                }
+                response
+                    .headers()
+                    .get(ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        AppError::BackupFailed("S3 returned no ETag for a part".to_string())
+                    })
+            })
+            .await?;
+            etags.push(etag);
+            sent += part.len() as u64;
+            progress.report(TransferDirection::Upload, sent, Some(size));
+        }
+
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    i + 1,
+                    etag
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+        let response = self
+            .send(
+                Method::POST,
+                key,
+                &[("uploadId", upload_id)],
+                Some(body.into_bytes()),
+            )
+            .await?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "Completing the S3 upload").await);
+        }
+        // The completion can still fail after S3 answered 200, in which case
+        // the body is an error document.
+        let body = response.text().await?;
+        if roxmltree::Document::parse(&body)
+            .is_ok_and(|document| document.root_element().tag_name().name() == "Error")
+        {
+            tracing::error!("Completing the S3 upload failed: {}", body);
+            return Err(AppError::BackupFailed(
+                "Completing the S3 upload failed".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BackupStore for S3Store {
+    async fn upload(
+        &self,
+        source: &Path,
+        taken_at: NaiveDateTime,
+        progress: &ProgressReporter,
+    ) -> AppResult<String> {
+        let name = backup_name(taken_at);
+        let key = format!("{}{}", self.prefix, name);
+        let size = tokio::fs::metadata(source).await?.len();
+        if size > PART_SIZE as u64 {
+            self.upload_multipart(&key, source, size, progress).await?;
+        } else {
+            let body = tokio::fs::read(source).await?;
+            let (key, body) = (key.as_str(), &body);
+            with_retries("S3 upload", || async move {
+                let response = self.send(Method::PUT, key, &[], Some(body.clone())).await?;
+                if !response.status().is_success() {
+                    return Err(response_error(response, "S3 upload").await);
+                }
+                Ok(())
+            })
+            .await?;
+            progress.report(TransferDirection::Upload, size, Some(size));
+        }
+        tracing::info!("Backup uploaded to S3 as {}", key);
+        Ok(name)
+    }
+
+    async fn list(&self) -> AppResult<Vec<BackupMetadata>> {
+        let mut backups = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let response = self.send(Method::GET, "", &query, None).await?;
+            if !response.status().is_success() {
+                return Err(response_error(response, "S3 listing").await);
+            }
+            let body = response.text().await?;
+            let page = parse_list_objects(&body)?;
+            for object in page.objects {
+                let Some(name) = object.key.strip_prefix(&self.prefix) else {
+                    continue;
+                };
+                if parse_backup_name(name).is_some() {
+                    backups.push(BackupMetadata {
+                        last_modified: object.last_modified,
+                        size_bytes: object.size,
+                        version_id: name.to_string(),
+                        is_latest: false,
+                    });
+                }
+            }
+            match page.next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => break,
+            }
+        }
+        Ok(newest_first(backups))
+    }
+
+    async fn download(
+        &self,
+        version_id: Option<&str>,
+        dest: &Path,
+        progress: &ProgressReporter,
+    ) -> AppResult<()> {
+        let name = resolve_version(self, version_id).await?;
+        let key = format!("{}{}", self.prefix, name);
+        tracing::info!("Downloading backup {} from S3 to: {:?}", key, dest);
+        // Signed anew for every attempt, signatures only hold for a while.
+        download_resumable(
+            "S3 download",
+            || Ok(self.signed_request(Method::GET, &key, &[], None)),
+            dest,
+            progress,
+        )
+        .await
+    }
+
+    async fn delete(&self, version_id: &str) -> AppResult<()> {
+        require_backup_name(version_id)?;
+        let key = format!("{}{}", self.prefix, version_id);
+        let response = self.send(Method::DELETE, &key, &[], None).await?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "S3 delete").await);
+        }
+        tracing::info!("Deleted backup {} from S3", key);
+        Ok(())
+    }
+}
+
+struct ListedObject {
+    key: String,
+    last_modified: String,
+    size: u64,
+}
+
+struct ListPage {
+    objects: Vec<ListedObject>,
+    next_continuation_token: Option<String>,
+}
+
+/// Reads a ListObjectsV2 response.
+fn parse_list_objects(xml: &str) -> AppResult<ListPage> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| {
+        tracing::error!("Failed to parse S3 listing: {}", e);
+        AppError::BackupFailed("Failed to parse the S3 listing".to_string())
+    })?;
+    let root = document.root_element();
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.tag_name().name() == name)
+            .and_then(|child| child.text())
+            .map(str::to_string)
+    };
+
+    let objects = root
+        .children()
+        .filter(|node| node.tag_name().name() == "Contents")
+        .filter_map(|node| {
+            Some(ListedObject {
+                key: child_text(node, "Key")?,
+                last_modified: child_text(node, "LastModified").unwrap_or_default(),
+                size: child_text(node, "Size")?.parse().ok()?,
+            })
+        })
+        .collect();
+    let truncated = child_text(root, "IsTruncated").as_deref() == Some("true");
+    Ok(ListPage {
+        objects,
+        next_continuation_token: child_text(root, "NextContinuationToken").filter(|_| truncated),
+    })
+}
+
+/// Reads the `UploadId` out of a CreateMultipartUpload response.
+fn parse_upload_id(xml: &str) -> AppResult<String> {
+    roxmltree::Document::parse(xml)
+        .ok()
+        .and_then(|document| {
+            document
+                .descendants()
+                .find(|node| node.tag_name().name() == "UploadId")
+                .and_then(|node| node.text())
+                .map(str::to_string)
+        })
+        .ok_or_else(|| {
+            tracing::error!("Unexpected CreateMultipartUpload response: {}", xml);
+            AppError::BackupFailed("Failed to start the S3 upload".to_string())
+        })
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but the RFC 3986 unreserved characters, the
+/// way SigV4 expects. `/` is kept unless `encode_slash` is set.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime};
+use reqwest::Method;
+
+use super::{
+    backup_name, download_resumable, file_body, newest_first, parse_backup_name,
+    require_backup_name, resolve_version, response_error, with_retries, BackupStore,
+    ProgressReporter,
+};
+use crate::config::WebDavStoreSettings;
+use crate::dto::BackupMetadata;
+use crate::error::{AppError, Result as AppResult};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:getlastmodified/>
+    <d:getcontentlength/>
+  </d:prop>
+</d:propfind>"#;
+
+/// A WebDAV collection, e.g. a Nextcloud folder or a NAS share.
+#[derive(Clone)]
+pub struct WebDavStore {
+    collection: url::Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl fmt::Debug for WebDavStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebDavStore")
+            .field("collection", &self.collection.as_str())
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebDavStore {
+    pub fn new(settings: &WebDavStoreSettings) -> AppResult<Self> {
+        let mut collection = url::Url::parse(settings.url.trim())
+            .map_err(|_| AppError::Validation(format!("Invalid WebDAV URL: {}", settings.url)))?;
+        if !matches!(collection.scheme(), "http" | "https") {
+            return Err(AppError::Validation(format!(
+                "Invalid WebDAV URL: {}",
+                settings.url
+            )));
+        }
+        // Backup names are joined onto the collection, which only keeps its
+        // last segment with a trailing slash.
+        if !collection.path().ends_with('/') {
+            let path = format!("{}/", collection.path());
+            collection.set_path(&path);
+        }
+        Ok(Self {
+            collection,
+            username: settings.username.clone().filter(|u| !u.is_empty()),
+            password: settings.password.clone().filter(|p| !p.is_empty()),
+        })
+    }
+
+    fn request(&self, method: Method, name: &str) -> AppResult<reqwest::RequestBuilder> {
+        let url = self.collection.join(name)?;
+        let request = reqwest::Client::new().request(method, url);
+        Ok(match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        })
+    }
+}
+
+#[async_trait]
+impl BackupStore for WebDavStore {
+    async fn upload(
+        &self,
+        source: &Path,
+        taken_at: NaiveDateTime,
+        progress: &ProgressReporter,
+    ) -> AppResult<String> {
+        let name = backup_name(taken_at);
+        let name_ref = name.as_str();
+        with_retries("WebDAV upload", || async move {
+            let (body, size) = file_body(source, progress).await?;
+            let response = self
+                .request(Method::PUT, name_ref)?
+                .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                .header(reqwest::header::CONTENT_LENGTH, size)
+                .body(body)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(response_error(response, "WebDAV upload").await);
+            }
+            Ok(())
+        })
+        .await?;
+        tracing::info!("Backup uploaded to WebDAV as {}", name);
+        Ok(name)
+    }
+
+    async fn list(&self) -> AppResult<Vec<BackupMetadata>> {
+        let propfind = Method::from_bytes(b"PROPFIND").expect("PROPFIND is a valid method");
+        let response = self
+            .request(propfind, "")?
+            .header("Depth", "1")
+            .header(reqwest::header::CONTENT_TYPE, "application/xml")
+            .body(PROPFIND_BODY)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "WebDAV listing").await);
+        }
+        let body = response.text().await?;
+        Ok(newest_first(parse_propfind(&body)?))
+    }
+
+    async fn download(
+        &self,
+        version_id: Option<&str>,
+        dest: &Path,
+        progress: &ProgressReporter,
+    ) -> AppResult<()> {
+        let name = resolve_version(self, version_id).await?;
+        tracing::info!("Downloading backup {} from WebDAV to: {:?}", name, dest);
+        download_resumable(
+            "WebDAV download",
+            || self.request(Method::GET, &name),
+            dest,
+            progress,
+        )
+        .await
+    }
+
+    async fn delete(&self, version_id: &str) -> AppResult<()> {
+        require_backup_name(version_id)?;
+        let response = self.request(Method::DELETE, version_id)?.send().await?;
+        if !response.status().is_success() {
+            return Err(response_error(response, "WebDAV delete").await);
+        }
+        tracing::info!("Deleted backup {} from WebDAV", version_id);
+        Ok(())
+    }
+}
+
+/// Reads the backups out of a `Depth: 1` PROPFIND multistatus response. The
+/// collection itself and files not named like backups are skipped.
+fn parse_propfind(xml: &str) -> AppResult<Vec<BackupMetadata>> {
+    let document = roxmltree::Document::parse(xml).map_err(|e| {
+        tracing::error!("Failed to parse WebDAV listing: {}", e);
+        AppError::BackupFailed("Failed to parse the WebDAV listing".to_string())
+    })?;
+    let is_dav = |node: &roxmltree::Node, name: &str| {
+        node.tag_name().name() == name && node.tag_name().namespace() == Some("DAV:")
+    };
+    let dav_text = |node: roxmltree::Node, name: &str| {
+        node.descendants()
+            .find(|child| is_dav(child, name))
+            .and_then(|child| child.text())
+            .map(|text| text.trim().to_string())
+    };
+
+    let mut backups = Vec::new();
+    for response in document
+        .root_element()
+        .children()
+        .filter(|node| is_dav(node, "response"))
+    {
+        let Some(href) = dav_text(response, "href") else {
+            continue;
+        };
+        let Some(segment) = href.trim_end_matches('/').rsplit('/').next() else {
+            continue;
+        };
+        // Backup names have nothing in them that would be percent-encoded.
+        let name = segment.to_string();
+        if parse_backup_name(&name).is_none() {
+            continue;
+        }
+        let last_modified = dav_text(response, "getlastmodified")
+            .map(|value| {
+                DateTime::parse_from_rfc2822(&value)
+                    .map(|date| date.to_rfc3339())
+                    .unwrap_or(value)
+            })
+            .unwrap_or_default();
+        backups.push(BackupMetadata {
+            last_modified,
+            size_bytes: dav_text(response, "getcontentlength")
+                .and_then(|length| length.parse().ok())
+                .unwrap_or(0),
+            version_id: name,
+            is_latest: false,
+        });
+    }
+    Ok(backups)
+}
@@ -0,0 +1,465 @@
+use crate::{
+    audit::{self, Actor},
+    clock::GymClock,
+    dto::{EntryLogDisplay, EntryLogQueryParams, PaginatedResponse},
+    error::Result as AppResult,
+    models::EntryLogStatus,
+    AppError,
+};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use sqlx::{Acquire, Row, SqliteConnection, SqlitePool};
+
+fn build_where_clause_and_params(params: &EntryLogQueryParams) -> (String, Vec<String>) {
+    let mut where_conditions = Vec::new();
+    let mut sql_params = Vec::new();
+
+    let search_term = params
+        .search_string
+        .as_ref()
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty());
+
+    if let Some(term) = &search_term {
+        let like_pattern = format!("'%{}%'", term);
+        let search_query = format!(
+            "LOWER(el.member_name) LIKE {val} OR el.card_id LIKE {val}",
+            val = like_pattern
+        );
+        where_conditions.push(search_query);
+    }
+
+    if let Some(filter_fields) = &params.filter_fields {
+        let mut filter_conditions = Vec::new();
+        let filter_query;
+        for field in filter_fields {
+            match field.field.as_str() {
+                "status" => {
+                    // Only known statuses make it into the query.
+                    filter_conditions.push(format!(
+                        "COALESCE(el.status, '') IN ('{}')",
+                        field
+                            .value
+                            .split(',')
+                            .filter_map(|s| EntryLogStatus::parse(s.trim()))
+                            .map(|status| status.as_str())
+                            .collect::<Vec<_>>()
+                            .join("', '")
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        if !filter_conditions.is_empty() {
+            filter_query = filter_conditions.join(" AND ");
+            where_conditions.push(filter_query);
+        }
+    }
+
+    // Date range search
+    if let Some(date_from) = params.date_from {
+        where_conditions.push("el.local_date >= ?".to_string());
+        sql_params.push(date_from.to_string());
+    }
+
+    if let Some(date_to) = params.date_to {
+        where_conditions.push("el.local_date <= ?".to_string());
+        sql_params.push(date_to.to_string());
+    }
+
+    let where_clause = if where_conditions.is_empty() {
+        "1=1".to_string()
+    } else {
+        where_conditions.join(" AND ")
+    };
+
+    (where_clause, sql_params)
+}
+
+fn build_order_clause(params: &EntryLogQueryParams) -> String {
+    let order_by = params.order_by.as_deref().unwrap_or("entry_time");
+    let direction = params.order_direction.as_deref().unwrap_or("desc");
+
+    let valid_order_fields = ["entry_time", "member_name", "status", "card_id"];
+    let order_field = if valid_order_fields.contains(&order_by) {
+        match order_by {
+            "member_name" => "el.member_name",
+            "entry_time" => "el.entry_time",
+            "status" => "el.status",
+            "card_id" => "el.card_id",
+            _ => "el.entry_time",
+        }
+    } else {
+        "el.entry_time"
+    };
+
+    let order_direction = if direction.to_lowercase() == "asc" {
+        "ASC"
+    } else {
+        "DESC"
+    };
+
+    format!("ORDER BY {} {}", order_field, order_direction)
+}
+
+async fn get_total_count(
+    conn: &mut SqliteConnection,
+    where_clause: &str,
+    params: &[String],
+) -> AppResult<i64> {
+    let count_query = format!(
+        r#"
+        SELECT COUNT(*) as total
+        FROM entry_logs el
+        LEFT JOIN memberships ms ON el.membership_id = ms.id
+        LEFT JOIN membership_types mt ON ms.membership_type_id = mt.id
+        WHERE {}
+        "#,
+        where_clause
+    );
+
+    let mut query = sqlx::query_scalar::<_, i64>(&count_query);
+    for param in params {
+        query = query.bind(param);
+    }
+
+    let total = query.fetch_one(conn).await?;
+    Ok(total)
+}
+
+pub async fn get_entry_logs(
+    pool: &SqlitePool,
+    search_params: EntryLogQueryParams,
+) -> AppResult<PaginatedResponse<EntryLogDisplay>> {
+    let mut conn = pool.acquire().await?;
+
+    // Validate and set defaults for pagination
+    let page = search_params.page.unwrap_or(1).max(1);
+    let per_page = search_params.per_page.unwrap_or(50).min(100).max(1);
+    let offset = (page - 1) * per_page;
+
+    let (where_clause, params) = build_where_clause_and_params(&search_params);
+
+    let total_count = get_total_count(&mut conn, &where_clause, &params).await?;
+
+    let total_pages = ((total_count as f64) / (per_page as f64)).ceil() as i32;
+
+    let order_clause = build_order_clause(&search_params);
+
+    let main_query = format!(
+        r#"
+        SELECT
+            el.id,
+            el.member_id,
+            el.membership_id,
+            el.member_name,
+            mt.name as membership_type_name,
+            ms.remaining_visits as visits_left,
+            el.card_id,
+            el.entry_time,
+            el.status,
+            el.notes
+        FROM entry_logs el
+        LEFT JOIN memberships ms ON el.membership_id = ms.id
+        LEFT JOIN membership_types mt ON ms.membership_type_id = mt.id
+        WHERE {}
+        {}
+        LIMIT ? OFFSET ?
+        "#,
+        where_clause, order_clause
+    );
+
+    let mut query = sqlx::query_as::<_, EntryLogDisplay>(&main_query);
+
+    for param in &params {
+        query = query.bind(param);
+    }
+
+    query = query.bind(per_page as i64).bind(offset as i64);
+
+    let entries = query.fetch_all(&mut *conn).await?;
+
+    Ok(PaginatedResponse {
+        data: entries,
+        total: total_count,
+        page,
+        per_page,
+        total_pages: total_pages as i64,
+    })
+}
+
+pub async fn get_recent_entry_logs(
+    pool: &SqlitePool,
+    limit: Option<u32>,
+) -> AppResult<Vec<EntryLogDisplay>> {
+    let limit = limit.unwrap_or(100).min(500).max(1);
+    let mut conn = pool.acquire().await?;
+
+    let entries = sqlx::query_as::<_, EntryLogDisplay>(
+        r#"
+        SELECT
+            el.id,
+            el.member_id,
+            el.membership_id,
+            el.member_name,
+            mt.name as membership_type_name,
+            ms.remaining_visits as visits_left,
+            el.card_id,
+            el.entry_time,
+            el.status,
+            el.notes
+        FROM entry_logs el
+        LEFT JOIN memberships ms ON el.membership_id = ms.id
+        LEFT JOIN membership_types mt ON ms.membership_type_id = mt.id
+        ORDER BY el.entry_time DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(entries)
+}
+
+// Helper function to get entry logs for a specific member
+pub async fn get_member_entry_logs(
+    pool: &SqlitePool,
+    member_id: i64,
+    limit: Option<u32>,
+) -> AppResult<Vec<EntryLogDisplay>> {
+    let limit = limit.unwrap_or(50).min(200).max(1);
+    let mut conn = pool.acquire().await?;
+
+    let entries = sqlx::query_as::<_, EntryLogDisplay>(
+        r#"
+        SELECT
+            el.id,
+            el.member_id,
+            el.membership_id,
+            el.member_name,
+            ms.remaining_visits as visits_left,
+            mt.name as membership_type_name,
+            el.card_id,
+            el.entry_time,
+            el.status,
+            el.notes
+        FROM entry_logs el
+        LEFT JOIN memberships ms ON el.membership_id = ms.id
+        LEFT JOIN membership_types mt ON ms.membership_type_id = mt.id
+        WHERE el.member_id = ?
+        ORDER BY el.entry_time DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(member_id)
+    .bind(limit)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(entries)
+}
+
+// Statistics function for dashboard
+pub async fn get_entry_logs_stats(
+    pool: &SqlitePool,
+    date_from: Option<NaiveDate>,
+    date_to: Option<NaiveDate>,
+) -> AppResult<serde_json::Value> {
+    let mut conn = pool.acquire().await?;
+
+    let mut where_conditions = vec!["1=1"];
+    let mut params = Vec::new();
+
+    if let Some(from_date) = date_from {
+        where_conditions.push("el.local_date >= ?");
+        params.push(from_date.to_string());
+    }
+
+    if let Some(to_date) = date_to {
+        where_conditions.push("el.local_date <= ?");
+        params.push(to_date.to_string());
+    }
+
+    let where_clause = where_conditions.join(" AND ");
+
+    let stats_query = format!(
+        r#"
+        SELECT
+            COUNT(*) as total_entries,
+            SUM(CASE WHEN el.status = 'allowed' THEN 1 ELSE 0 END) as allowed_entries,
+            SUM(CASE WHEN el.status LIKE 'denied%' THEN 1 ELSE 0 END) as denied_entries,
+            COUNT(DISTINCT el.member_id) as unique_members,
+            COUNT(DISTINCT el.local_date) as unique_days
+        FROM entry_logs el
+        WHERE {}
+        "#,
+        where_clause
+    );
+
+    let mut query = sqlx::query(&stats_query);
+    for param in &params {
+        query = query.bind(param);
+    }
+
+    let row = query.fetch_one(&mut *conn).await?;
+
+    let stats = serde_json::json!({
+        "total_entries": row.get::<i64, _>("total_entries"),
+        "allowed_entries": row.get::<i64, _>("allowed_entries"),
+        "denied_entries": row.get::<i64, _>("denied_entries"),
+        "unique_members": row.get::<i64, _>("unique_members"),
+        "unique_days": row.get::<i64, _>("unique_days"),
+        "success_rate": if row.get::<i64, _>("total_entries") > 0 {
+            (row.get::<i64, _>("allowed_entries") as f64 / row.get::<i64, _>("total_entries") as f64) * 100.0
+        } else {
+            0.0
+        }
+    });
+
+    Ok(stats)
+}
+
+/// Keeps the last `period` months of entry logs and deletes the rest; 0
+/// deletes all of them.
+pub async fn delete_entry_logs(
+    pool: &SqlitePool,
+    actor: &Actor,
+    period: Option<i64>,
+) -> AppResult<()> {
+    let mut conn = pool.acquire().await?;
+    if period.is_none() {
+        return Err(AppError::Validation("Period must be specified".to_string()));
+    }
+    let period = period.unwrap();
+
+    // If period is 0  delete all logs
+    if period == 0 {
+        let mut tx = conn.begin().await?;
+        let result = sqlx::query!("DELETE FROM entry_logs")
+            .execute(&mut *tx)
+            .await?;
+        audit::record(
+            &mut *tx,
+            actor,
+            "bulk_delete",
+            "entry_log",
+            None,
+            None,
+            Some(serde_json::json!({
+                "keep_months": period,
+                "deleted": result.rows_affected(),
+            })),
+        )
+        .await?;
+        tx.commit().await?;
+        return Ok(());
+    }
+    if period < 0 || period > 60 {
+        return Err(AppError::Validation(
+            "Period must be between 0 and 60 months".to_string(),
+        ));
+    }
+
+    // Calculate the date threshold
+    let threshold_date = Utc::now()
+        .naive_utc()
+        .checked_sub_signed(chrono::Duration::days(period * 30))
+        .ok_or(AppError::Validation("Invalid period".to_string()))?;
+
+    // Delete logs older than the threshold date
+    let mut tx = conn.begin().await?;
+    let result = sqlx::query!(
+        "DELETE FROM entry_logs WHERE entry_time < ?",
+        threshold_date
+    )
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut *tx,
+        actor,
+        "bulk_delete",
+        "entry_log",
+        None,
+        None,
+        Some(serde_json::json!({
+            "keep_months": period,
+            "older_than": threshold_date,
+            "deleted": result.rows_affected(),
+        })),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn delete_entry_log(
+    pool: &SqlitePool,
+    actor: &Actor,
+    entry_log_id: i64,
+) -> AppResult<()> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+
+    let existing = sqlx::query_as::<_, EntryLogDisplay>(
+        r#"
+        SELECT id, member_id, membership_id, member_name, NULL as visits_left, NULL as membership_type_name,
+               card_id, entry_time, status, notes
+        FROM entry_logs
+        WHERE id = ?
+        "#,
+    )
+    .bind(entry_log_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let result = sqlx::query!("DELETE FROM entry_logs WHERE id = ?", entry_log_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if result.rows_affected() > 0 {
+        audit::record(
+            &mut *tx,
+            actor,
+            "delete",
+            "entry_log",
+            Some(entry_log_id),
+            existing.as_ref().and_then(audit::snapshot),
+            None,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Fills in `local_date` for entries logged before it was recorded, as the
+/// date of their entry time at the gym. Returns the number of entries filled.
+pub async fn backfill_local_dates(pool: &SqlitePool, clock: &GymClock) -> AppResult<u64> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+
+    let entries: Vec<(i64, NaiveDateTime)> =
+        sqlx::query_as("SELECT id, entry_time FROM entry_logs WHERE local_date IS NULL")
+            .fetch_all(&mut *tx)
+            .await?;
+    for (id, entry_time) in &entries {
+        sqlx::query("UPDATE entry_logs SET local_date = ? WHERE id = ?")
+            .bind(clock.to_local(*entry_time).date())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    if !entries.is_empty() {
+        tracing::info!(
+            "Filled in the gym-local date of {} entries in {}.",
+            entries.len(),
+            clock.timezone()
+        );
+    }
+    Ok(entries.len() as u64)
+}
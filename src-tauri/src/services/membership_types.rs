@@ -0,0 +1,431 @@
+use crate::audit::{self, Actor};
+use crate::config::AppSettings;
+use crate::dto::NewMembershipTypePayload;
+use crate::error::{ErrorCodes, TranslatableError};
+use crate::{
+    error::{AppError, Result as AppResult},
+    models::MembershipType,
+};
+use sqlx::{SqliteConnection, SqlitePool};
+
+const MEMBERSHIP_TYPE_SELECT: &str = "SELECT id, name, duration_days, visit_limit, price_minor, enter_by, description, created_at, updated_at, is_deleted, is_active FROM membership_types";
+
+fn in_currency(mut membership_type: MembershipType, settings: &AppSettings) -> MembershipType {
+    membership_type.currency = settings.currency.code.clone();
+    membership_type
+}
+
+async fn fetch_membership_type_for_audit(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    id: i64,
+) -> AppResult<Option<MembershipType>> {
+    let membership_type =
+        sqlx::query_as::<_, MembershipType>(&format!("{} WHERE id = ?", MEMBERSHIP_TYPE_SELECT))
+            .bind(id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    Ok(membership_type)
+}
+
+pub async fn get_membership_type_by_id(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    id: i64,
+) -> AppResult<MembershipType> {
+    tracing::info!("Fetching membership type with id: {}", id);
+
+    let membership_type = sqlx::query_as::<_, MembershipType>(&format!(
+        "{} WHERE id = ? AND is_deleted = FALSE",
+        MEMBERSHIP_TYPE_SELECT
+    ))
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(in_currency(membership_type, settings))
+}
+
+pub async fn update_membership_type(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    actor: &Actor,
+    id: i64,
+    payload: NewMembershipTypePayload,
+) -> AppResult<MembershipType> {
+    tracing::info!("Updating membership type with id: {}", id);
+
+    if payload.name.trim().is_empty() {
+        tracing::warn!("Validation failed: Membership type name cannot be empty.");
+        return Err(AppError::Validation(
+            "Membership type name cannot be empty.".to_string(),
+        ));
+    }
+    if payload.price_minor < 0 {
+        tracing::warn!("Validation failed: Price cannot be negative.");
+        return Err(AppError::Validation(
+            "Price cannot be negative.".to_string(),
+        ));
+    }
+    if let Some(duration) = payload.duration_days {
+        if duration <= 0 {
+            return Err(AppError::Validation(
+                "Duration days must be positive if provided.".to_string(),
+            ));
+        }
+    }
+    if let Some(limit) = payload.visit_limit {
+        if limit < 0 {
+            return Err(AppError::Validation(
+                "Visit limit must be positive or 0 if provided.".to_string(),
+            ));
+        }
+        if limit > payload.duration_days.unwrap_or(0) {
+            return Err(AppError::Validation(
+                "Visit limit cannot exceed duration days.".to_string(),
+            ));
+        }
+    }
+
+    if let Some(enter_by) = payload.enter_by {
+        if enter_by < 0 || enter_by > 23 {
+            return Err(AppError::Validation(
+                "Enter by must valid hours.".to_string(),
+            ));
+        }
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let is_active = payload.is_active.unwrap_or(true);
+
+    let mut tx = pool.begin().await?;
+    let before = fetch_membership_type_for_audit(&mut tx, id).await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE membership_types
+        SET name = ?1, duration_days = ?2, visit_limit = ?3, enter_by = ?4, price_minor = ?5, description = ?6, updated_at = ?7, is_active = ?8
+        WHERE id = ?9 AND is_deleted = FALSE
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(payload.duration_days)
+    .bind(payload.visit_limit)
+    .bind(payload.enter_by)
+    .bind(payload.price_minor)
+    .bind(&payload.description)
+    .bind(now)
+    .bind(is_active)
+    .bind(id)
+    .execute(&mut *tx)
+    .await;
+
+    match result {
+        Ok(query_result) => {
+            if query_result.rows_affected() == 0 {
+                tracing::warn!("No membership type found with id {} to update.", id);
+                return Err(AppError::NotFound(format!(
+                    "Membership type with id {} not found.",
+                    id
+                )));
+            }
+
+            // Fetch the updated membership type to return it
+            let updated_type = sqlx::query_as::<_, MembershipType>(&format!(
+                "{} WHERE id = ?",
+                MEMBERSHIP_TYPE_SELECT
+            ))
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            audit::record(
+                &mut *tx,
+                actor,
+                "update",
+                "membership_type",
+                Some(id),
+                before.as_ref().and_then(audit::snapshot),
+                audit::snapshot(&updated_type),
+            )
+            .await?;
+            tx.commit().await?;
+            tracing::info!(
+                "Successfully updated membership type with id {}: {}.",
+                id,
+                payload.name
+            );
+            return Ok(in_currency(updated_type, settings));
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            tracing::warn!(
+                "Failed to update membership type: Name '{}' already exists. Error: {:?}",
+                payload.name,
+                db_err
+            );
+
+            return Err(AppError::Translatable(TranslatableError::with_params(
+                ErrorCodes::MEMBERSHIP_TYPE_NAME_EXISTS,
+                serde_json::json!({"name": payload.name}),
+                "failed to update membership_type: name already exists!",
+            )));
+        }
+        Err(e) => {
+            tracing::error!("Failed to update membership type in database: {:?}", e);
+            return Err(AppError::Sqlx(e)); // Convert general SQLx errors
+        }
+    }
+}
+
+pub async fn add_membership_type(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    actor: &Actor,
+    payload: NewMembershipTypePayload,
+) -> AppResult<MembershipType> {
+    tracing::info!("Creating new membership type: {}", &payload.name);
+
+    if payload.name.trim().is_empty() {
+        tracing::warn!("Validation failed: Membership type name cannot be empty.");
+        return Err(AppError::Validation(
+            "Membership type name cannot be empty.".to_string(),
+        ));
+    }
+    if payload.price_minor < 0 {
+        tracing::warn!("Validation failed: Price cannot be negative.");
+        return Err(AppError::Validation(
+            "Price cannot be negative.".to_string(),
+        ));
+    }
+    if let Some(duration) = payload.duration_days {
+        if duration <= 0 {
+            return Err(AppError::Validation(
+                "Duration days must be positive if provided.".to_string(),
+            ));
+        }
+    }
+    if let Some(limit) = payload.visit_limit {
+        if limit < 0 {
+            return Err(AppError::Validation(
+                "Visit limit must be 0 or positive if provided.".to_string(),
+            ));
+        }
+
+        if limit > payload.duration_days.unwrap_or(0) {
+            return Err(AppError::Validation(
+                "Visit limit cannot exceed duration days.".to_string(),
+            ));
+        }
+    }
+
+    if let Some(enter_by) = payload.enter_by {
+        if enter_by < 0 || enter_by > 23 {
+            return Err(AppError::Validation(
+                "Enter by must valid hours.".to_string(),
+            ));
+        }
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let is_active = payload.is_active.unwrap_or(true);
+
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+            r#"
+            INSERT INTO membership_types (name, duration_days, visit_limit, enter_by, price_minor, description, created_at, updated_at, is_deleted, is_active)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7, FALSE, ?8)
+            "#,
+        )
+        .bind(&payload.name)
+        .bind(payload.duration_days)
+        .bind(payload.visit_limit)
+        .bind(payload.enter_by)
+        .bind(payload.price_minor)
+        .bind(&payload.description)
+        .bind(now)
+        .bind(is_active)
+        .execute(&mut *tx)
+        .await;
+
+    match result {
+        Ok(query_result) => {
+            let last_insert_id = query_result.last_insert_rowid();
+            tracing::info!(
+                "Successfully inserted new membership type '{}' with id {}.",
+                payload.name,
+                last_insert_id
+            );
+
+            // Fetch the newly created membership type to return it
+            let new_type = sqlx::query_as::<_, MembershipType>(&format!(
+                "{} WHERE id = ?",
+                MEMBERSHIP_TYPE_SELECT
+            ))
+            .bind(last_insert_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            audit::record(
+                &mut *tx,
+                actor,
+                "create",
+                "membership_type",
+                Some(new_type.id),
+                None,
+                audit::snapshot(&new_type),
+            )
+            .await?;
+            tx.commit().await?;
+
+            Ok(in_currency(new_type, settings))
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            tracing::warn!(
+                "Failed to create membership type: Name '{}' already exists. Error: {:?}",
+                payload.name,
+                db_err
+            );
+            return Err(AppError::Translatable(TranslatableError::with_params(
+                ErrorCodes::MEMBERSHIP_TYPE_NAME_EXISTS,
+                serde_json::json!({"name": payload.name}),
+                "failed to update membership_type: name already exists!",
+            )));
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to insert new membership type into database: {:?}",
+                e
+            );
+            Err(AppError::Sqlx(e)) // Convert general SQLx errors
+        }
+    }
+}
+
+pub async fn get_all_membership_types(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+) -> AppResult<Vec<MembershipType>> {
+    tracing::info!("Fetching all membership types.");
+    let types = sqlx::query_as::<_, MembershipType>(&format!(
+        "{} WHERE is_deleted = FALSE ORDER BY name ASC",
+        MEMBERSHIP_TYPE_SELECT
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(types
+        .into_iter()
+        .map(|membership_type| in_currency(membership_type, settings))
+        .collect())
+}
+
+pub async fn delete_membership_type(pool: &SqlitePool, actor: &Actor, id: i64) -> AppResult<()> {
+    tracing::info!(
+        "Attempting to (soft) delete membership type with id: {}",
+        id
+    );
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = pool.begin().await.map_err(AppError::Sqlx)?;
+    let before = fetch_membership_type_for_audit(&mut tx, id).await?;
+    let current_record = sqlx::query!(
+        "SELECT name FROM membership_types WHERE id = ? AND is_deleted = FALSE",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let current_name = match current_record {
+        Some(record) => record.name,
+        None => {
+            tracing::warn!("No membership type found with id {} to delete.", id);
+            return Err(AppError::NotFound(format!(
+                "Membership type with id {} not found.",
+                id
+            )));
+        }
+    };
+
+    // Create unique deleted name using timestamp
+    let deleted_name = format!("{}_deleted_{}", current_name, now.and_utc().timestamp());
+    let result = sqlx::query!(
+        "UPDATE membership_types SET name = ?, is_deleted = TRUE, updated_at = ? WHERE id = ?",
+        deleted_name,
+        now,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        tracing::warn!("No membership type found with id {} to delete.", id);
+        return Err(AppError::NotFound(format!(
+            "Membership type with id {} not found.",
+            id
+        )));
+    }
+
+    // update all memberships with this membership_type_id to inactive
+    let update_result = sqlx::query!(
+        "UPDATE memberships SET status = 'inactive' WHERE membership_type_id = ? AND is_deleted = FALSE",
+        id
+    ).execute(&mut *tx)
+        .await;
+    match update_result {
+        Ok(_) => {
+            tracing::info!(
+                "Successfully updated memberships with membership_type_id: {}",
+                id
+            );
+            audit::record(
+                &mut *tx,
+                actor,
+                "delete",
+                "membership_type",
+                Some(id),
+                before.as_ref().and_then(audit::snapshot),
+                None,
+            )
+            .await?;
+            tx.commit().await.map_err(AppError::Sqlx)?;
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to update memberships with membership_type_id {}: {:?}",
+                id,
+                e
+            );
+            tx.rollback().await.map_err(AppError::Sqlx)?;
+            Err(AppError::Sqlx(e))
+        }
+    }
+}
+
+/// Converts every stored price when the currency's minor unit changes, e.g.
+/// from cents to whole units. Rounds half up when digits are dropped.
+pub async fn rescale_prices(
+    conn: &mut SqliteConnection,
+    from_digits: u32,
+    to_digits: u32,
+) -> AppResult<u64> {
+    let result = if to_digits > from_digits {
+        sqlx::query("UPDATE membership_types SET price_minor = price_minor * ?")
+            .bind(10i64.pow(to_digits - from_digits))
+            .execute(&mut *conn)
+            .await?
+    } else if to_digits < from_digits {
+        sqlx::query("UPDATE membership_types SET price_minor = (price_minor + ?1 / 2) / ?1")
+            .bind(10i64.pow(from_digits - to_digits))
+            .execute(&mut *conn)
+            .await?
+    } else {
+        return Ok(0);
+    };
+    tracing::info!(
+        "Rescaled {} membership type prices from {} to {} minor unit digits.",
+        result.rows_affected(),
+        from_digits,
+        to_digits
+    );
+    Ok(result.rows_affected())
+}
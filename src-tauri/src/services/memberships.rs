@@ -0,0 +1,457 @@
+use crate::audit::{self, Actor};
+use crate::clock::GymClock;
+use crate::dto::{MembershipInfo, MembershipPayload, PaginatedResponse, PaginationPayload};
+use crate::error::{ErrorCodes, TranslatableError};
+use crate::{
+    error::{AppError, Result as AppResult},
+    models::{Membership, MembershipStatus},
+};
+use chrono::{NaiveDate, Utc};
+use sqlx::SqlitePool;
+
+async fn determine_membership_status(
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+    remaining_visits: i64,
+    today: NaiveDate,
+) -> AppResult<MembershipStatus> {
+    let now_date = today;
+    if remaining_visits <= 0 || end_date < &now_date {
+        return Ok(MembershipStatus::Expired);
+    }
+
+    if start_date > &now_date {
+        Ok(MembershipStatus::Pending)
+    } else if end_date > &now_date && remaining_visits > 0 {
+        Ok(MembershipStatus::Active)
+    } else {
+        Ok(MembershipStatus::Inactive)
+    }
+}
+
+async fn fetch_membership_for_audit(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    id: i64,
+) -> AppResult<Option<Membership>> {
+    let membership = sqlx::query_as::<_, Membership>(
+        "SELECT id, member_id, membership_type_id, start_date, end_date, remaining_visits, status, purchase_date, created_at, updated_at, is_deleted FROM memberships WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(membership)
+}
+
+pub async fn get_all_memberships_for_member(
+    pool: &SqlitePool,
+    id: i64,
+    payload: PaginationPayload,
+) -> AppResult<PaginatedResponse<MembershipInfo>> {
+    tracing::info!("Fetching all memberships for member with ID: {}", id);
+
+    let current_page = payload.page.unwrap_or(1).max(1);
+    let page_size = payload.per_page.unwrap_or(10).max(1);
+    let offset = (current_page - 1) * page_size;
+    let page_size_i64 = page_size as i64;
+    let offset_i64 = offset as i64;
+
+    let memberships = sqlx::query_as::<_, MembershipInfo>(
+        r#"
+        SELECT
+            m.id as member_id,
+            m.first_name as member_first_name,
+            m.last_name as member_last_name,
+            mt.id as membership_type_id,
+            mt.name as membership_type_name,
+            mt.enter_by as membership_type_enter_by,
+            ms.id as membership_id,
+            ms.start_date as membership_start_date,
+            ms.end_date as membership_end_date,
+            ms.status as membership_status,
+            ms.remaining_visits as membership_remaining_visits,
+            ms.purchase_date as membership_purchase_date
+        FROM
+            members m
+        JOIN memberships ms ON m.id = ms.member_id AND ms.is_deleted = FALSE
+        LEFT JOIN membership_types mt ON ms.membership_type_id = mt.id AND mt.is_deleted = FALSE
+        WHERE
+            m.id = ?
+        ORDER BY ms.start_date DESC
+        LIMIT ? OFFSET ?
+        "#,
+    )
+    .bind(id)
+    .bind(page_size_i64)
+    .bind(offset_i64)
+    .fetch_all(pool)
+    .await?;
+
+    if memberships.is_empty() {
+        tracing::warn!("No memberships found for member with ID: {}", id);
+    } else {
+        tracing::info!(
+            "Found {} memberships for member with ID: {}",
+            memberships.len(),
+            id
+        );
+    }
+    let total_items = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM memberships WHERE member_id = ? AND is_deleted = FALSE",
+        id
+    )
+    .fetch_one(pool)
+    .await? as i64;
+
+    let total_pages = if page_size > 0 {
+        (total_items as f64 / page_size as f64).ceil() as i64
+    } else {
+        0
+    };
+    Ok(PaginatedResponse {
+        total: total_items,
+        data: memberships,
+        total_pages: total_pages,
+        page: current_page,
+        per_page: page_size,
+    })
+}
+
+pub async fn get_membership_by_id(pool: &SqlitePool, id: i64) -> AppResult<Option<MembershipInfo>> {
+    tracing::info!("Fetching membership by ID: {}", id);
+
+    let membership = sqlx::query_as::<_, MembershipInfo>(
+        r#"
+        SELECT
+            m.id as member_id,
+            m.first_name as member_first_name,
+            m.last_name as member_last_name,
+            mt.id as membership_type_id,
+            mt.name as membership_type_name,
+            mt.enter_by as membership_type_enter_by,
+            ms.id as membership_id,
+            ms.start_date as membership_start_date,
+            ms.end_date as membership_end_date,
+            ms.status as membership_status,
+            ms.remaining_visits as membership_remaining_visits,
+            ms.purchase_date as membership_purchase_date
+        FROM
+            memberships ms
+        JOIN members m ON ms.member_id = m.id
+        LEFT JOIN membership_types mt ON ms.membership_type_id = mt.id AND mt.is_deleted = FALSE
+        WHERE
+            ms.is_deleted = FALSE AND ms.id = ?
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(m) = &membership {
+        tracing::info!("Found membership for member ID: {}", m.member_id);
+    } else {
+        tracing::warn!("No membership found with ID: {}", id);
+    }
+
+    Ok(membership)
+}
+
+/// Creates or updates a membership. New memberships are attributed to
+/// `operator_user_id`, the staff member at the front desk.
+pub async fn save_membership(
+    pool: &SqlitePool,
+    clock: &GymClock,
+    actor: &Actor,
+    operator_user_id: Option<i64>,
+    payload: MembershipPayload,
+) -> AppResult<MembershipInfo> {
+    tracing::info!(
+        "Saving membership for member ID: {}, membership ID: {:?}",
+        payload.member_id,
+        payload.membership_id
+    );
+
+    // Validate member exists
+    let member_exists = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM members WHERE id = ? AND is_deleted = FALSE",
+        payload.member_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if member_exists == 0 {
+        return Err(AppError::NotFound(format!(
+            "Member with ID {} not found.",
+            payload.member_id
+        )));
+    }
+    if payload.membership_start_date.is_none() || payload.membership_start_date.is_none() {
+        return Err(AppError::Validation(
+            "Start date and end date are required for a membership.".to_string(),
+        ));
+    }
+    if payload.membership_end_date <= payload.membership_start_date {
+        return Err(AppError::Validation(
+            "End date cannot be before start date.".to_string(),
+        ));
+    }
+    if payload.membership_remaining_visits.is_none() {
+        return Err(AppError::Validation(
+            "Remaining visits must be specified for a membership.".to_string(),
+        ));
+    }
+    if payload.membership_type_id.is_none() {
+        return Err(AppError::Validation(
+            "Membership_type_id must be provided.".to_string(),
+        ));
+    }
+
+    let final_status;
+    if payload.membership_suspended.is_some() && payload.membership_suspended.unwrap() {
+        final_status = MembershipStatus::Suspended;
+    } else {
+        final_status = determine_membership_status(
+            &payload.membership_start_date.unwrap(),
+            &payload.membership_end_date.unwrap(),
+            payload.membership_remaining_visits.unwrap(),
+            clock.today(),
+        )
+        .await?;
+    }
+    let mut final_membership_id = payload.membership_id;
+    let mut tx = pool.begin().await?;
+    let mut before = None;
+
+    // If membership_id is provided, update existing membership
+    if let Some(membership_id) = payload.membership_id {
+        tracing::info!("Updating existing membership with ID: {}", membership_id);
+        let now = clock.now_utc();
+        before = fetch_membership_for_audit(&mut tx, membership_id).await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE memberships SET
+                start_date = ?, end_date = ?, remaining_visits = ?,
+                status = ?, updated_at = ?, membership_type_id = ?
+            WHERE id = ? AND member_id = ? AND is_deleted = FALSE
+            "#,
+            payload.membership_start_date,
+            payload.membership_end_date,
+            payload.membership_remaining_visits,
+            final_status,
+            now,
+            payload.membership_type_id,
+            membership_id,
+            payload.member_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tracing::info!("Successfully updated membership with ID: {}", membership_id);
+    } else if let Some(membership_type_id) = payload.membership_type_id {
+        // Create new membership from type
+        tracing::info!(
+            "Creating new membership for member ID: {} from type ID: {}",
+            payload.member_id,
+            membership_type_id
+        );
+
+        // Check if member already has membership overlapping with the new one
+        let overlapping_membership = sqlx::query_scalar!(
+            r#"
+          SELECT COUNT(*) FROM memberships
+          WHERE member_id = ? AND is_deleted = FALSE AND status in ('active', 'pending', 'suspended')
+          AND (
+          (start_date <= ? AND end_date >= ?)
+          OR (start_date <= ? AND end_date IS NULL)
+          )
+          "#,
+            payload.member_id,
+            payload.membership_end_date,
+            payload.membership_start_date,
+            payload.membership_start_date,
+        )
+        .fetch_one(&mut *tx)
+        .await;
+        match overlapping_membership {
+            Ok(count) if count > 0 => {
+                tracing::warn!(
+                    "Member with ID {} already has an overlapping membership.",
+                    payload.member_id
+                );
+                return Err(AppError::Translatable(TranslatableError::with_params(
+                    ErrorCodes::OVERLAPPING_MEMBERSHIP,
+                    serde_json::json!({"id": payload.member_id}),
+                    "failed to add membership:Member already has overlapping membership",
+                )));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Failed to check for overlapping memberships: {:?}", e);
+                return Err(AppError::Sqlx(e));
+            }
+        }
+
+        let now = clock.now_utc();
+        let insert_result = sqlx::query(
+            r#"
+            INSERT INTO memberships (member_id, membership_type_id, start_date, end_date, remaining_visits, status, purchase_date, updated_at, created_at, is_deleted, operator_user_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, FALSE, ?)
+            "#,
+        )
+        .bind(payload.member_id)
+        .bind(membership_type_id)
+        .bind(payload.membership_start_date)
+        .bind(payload.membership_end_date)
+        .bind(payload.membership_remaining_visits)
+        .bind(&final_status)
+        .bind(now) // purchase_date
+        .bind(now) // updated_at
+        .bind(now) // created_at
+        .bind(operator_user_id)
+        .execute(&mut *tx)
+        .await;
+
+        match insert_result {
+            Ok(result) => {
+                tracing::info!("Successfully inserted new membership.");
+                final_membership_id = Some(result.last_insert_rowid());
+            }
+            Err(e) => {
+                tracing::error!("Failed to insert new membership: {:?}", e);
+                return Err(AppError::Sqlx(e));
+            }
+        }
+    }
+
+    if let Some(membership_id) = final_membership_id {
+        let after = fetch_membership_for_audit(&mut tx, membership_id).await?;
+        audit::record(
+            &mut *tx,
+            actor,
+            if payload.membership_id.is_some() {
+                "update"
+            } else {
+                "create"
+            },
+            "membership",
+            Some(membership_id),
+            before.as_ref().and_then(audit::snapshot),
+            after.as_ref().and_then(audit::snapshot),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    let membership = get_membership_by_id(pool, final_membership_id.unwrap()).await?;
+    if let Some(m) = membership {
+        tracing::info!(
+            "Successfully retrieved membership details for ID: {}",
+            m.membership_id.unwrap_or(-1)
+        );
+        Ok(m)
+    } else {
+        Err(AppError::NotFound(format!(
+            "Membership with ID {:?} not found after save.",
+            final_membership_id
+        )))
+    }
+}
+
+pub async fn delete_membership(pool: &SqlitePool, actor: &Actor, id: i64) -> AppResult<()> {
+    tracing::info!("Deleting membership with ID: {}", id);
+
+    let now = Utc::now().naive_utc();
+    let mut tx = pool.begin().await?;
+    let before = fetch_membership_for_audit(&mut tx, id).await?;
+    let result = sqlx::query!(
+        "UPDATE memberships SET is_deleted = TRUE, updated_at = ? WHERE id = ? AND is_deleted = FALSE",
+        now,
+        id
+    )
+    .execute(&mut *tx)
+    .await;
+
+    match result {
+        Ok(query_result) => {
+            if query_result.rows_affected() > 0 {
+                audit::record(
+                    &mut *tx,
+                    actor,
+                    "delete",
+                    "membership",
+                    Some(id),
+                    before.as_ref().and_then(audit::snapshot),
+                    None,
+                )
+                .await?;
+            }
+            tx.commit().await?;
+            tracing::info!("Successfully deleted membership with ID: {}", id);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("Failed to delete membership with ID {}: {:?}", id, e);
+            return Err(AppError::Sqlx(e));
+        }
+    }
+}
+
+/// Moves pending memberships that have started to active and active ones
+/// that ended before `today` to expired. Returns the number of changed rows.
+pub async fn update_membership_statuses(pool: &SqlitePool, today: NaiveDate) -> AppResult<i64> {
+    let mut tx = pool.begin().await?;
+
+    // 1. Update pending memberships to active (start_date <= today)
+    let pending_query = r#"
+        UPDATE memberships
+        SET status = ?,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE status = ?
+        AND start_date <= ?
+        AND is_deleted = FALSE
+    "#;
+
+    let pending_result = sqlx::query(pending_query)
+        .bind(MembershipStatus::Active)
+        .bind(MembershipStatus::Pending)
+        .bind(today)
+        .execute(&mut *tx)
+        .await?;
+
+    let pending_to_active_count = pending_result.rows_affected() as i64;
+
+    let expired_query = r#"
+        UPDATE memberships
+        SET status = ?,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE status = ?
+        AND end_date < ?
+        AND is_deleted = FALSE
+    "#;
+
+    let expired_result = sqlx::query(expired_query)
+        .bind(MembershipStatus::Expired)
+        .bind(MembershipStatus::Active)
+        .bind(today)
+        .execute(&mut *tx)
+        .await?;
+
+    let active_to_expired_count = expired_result.rows_affected() as i64;
+    // Commit the transaction
+    tx.commit().await?;
+
+    let total_updated = pending_to_active_count + active_to_expired_count;
+
+    if total_updated > 0 {
+        tracing::info!(
+            "Updated {} membership statuses: {} pending to active, {} active to expired.",
+            total_updated,
+            pending_to_active_count,
+            active_to_expired_count
+        );
+    } else {
+        tracing::debug!("All membership statuses are already current.");
+    }
+
+    Ok(total_updated)
+}
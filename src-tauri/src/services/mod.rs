@@ -0,0 +1,15 @@
+//! Business logic that does not depend on Tauri. Everything here works on a
+//! plain `SqlitePool`, a snapshot of the `AppSettings` and an audit `Actor`,
+//! so it can be driven from tests, a CLI or another frontend. Permission
+//! checks and the session stay with the Tauri commands that wrap it.
+
+pub mod analytics;
+pub mod backup;
+pub mod backup_encryption;
+pub mod backup_manifest;
+pub mod backup_store;
+pub mod entry_logs;
+pub mod members;
+pub mod membership_types;
+pub mod memberships;
+pub mod scanning;
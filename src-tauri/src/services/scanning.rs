@@ -0,0 +1,996 @@
+//! Card scanning at the front desk: decides whether a member may enter and
+//! logs every attempt.
+
+use crate::{
+    audit::{self, Actor},
+    clock::GymClock,
+    config::AppSettings,
+    dto::{
+        CheckInRule, EntryStatus, MembershipInfo, ScanPayload, ScanPayloadSingle,
+        ScanProcessingResult,
+    },
+    error::Result as AppResult,
+    models::{EntryLogStatus, Member, MembershipStatus},
+    schedule, AppError,
+};
+use chrono::{NaiveDate, Timelike};
+use sqlx::{Acquire, Sqlite, SqlitePool, Transaction};
+
+async fn calculate_and_update_membership_status_if_needed(
+    tx: &mut Transaction<'_, Sqlite>,
+    membership_id: i64,
+    current_status: MembershipStatus,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    remaining_visits: i64,
+    clock: &GymClock,
+) -> AppResult<MembershipStatus> {
+    // Don't change suspended memberships
+    if current_status == MembershipStatus::Suspended {
+        return Ok(current_status);
+    }
+
+    let now_date = clock.today();
+    let mut new_status = current_status;
+
+    // Check for expiration conditions first
+    if remaining_visits <= 0 || end_date < now_date {
+        new_status = MembershipStatus::Expired;
+    }
+
+    // Handle pending -> active transition
+    if current_status == MembershipStatus::Pending
+        && start_date <= now_date
+        && new_status != MembershipStatus::Expired
+    {
+        new_status = MembershipStatus::Active;
+    }
+
+    // Update database if status changed
+    if new_status != current_status {
+        let result = sqlx::query!(
+            "UPDATE memberships SET status = ?, updated_at = ? WHERE id = ?",
+            new_status,
+            now_date,
+            membership_id
+        )
+        .execute(&mut **tx)
+        .await;
+        match result {
+            Ok(query_result) => {
+                if query_result.rows_affected() == 0 {
+                    tracing::error!(
+                        "No rows affected when updating membership {} status",
+                        membership_id
+                    );
+                    return Err(AppError::NotFound(
+                        "Failed to update membership status".to_string(),
+                    ));
+                }
+                tracing::info!(
+                    "Updated membership {} status from {} to {}",
+                    membership_id,
+                    current_status.as_str(),
+                    new_status.as_str()
+                );
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Database error updating membership {} status: {}",
+                    membership_id,
+                    e
+                );
+                return Err(AppError::Sqlx(e));
+            }
+        }
+    }
+
+    Ok(new_status)
+}
+
+async fn deny_entry(
+    tx: &mut Transaction<'_, Sqlite>,
+    clock: &GymClock,
+    operator_user_id: Option<i64>,
+    member_id: Option<i64>,
+    membership_id: Option<i64>,
+    card_id: &str,
+    status: EntryStatus,
+    log_status: EntryLogStatus,
+    message: &str,
+    member_name: Option<&String>,
+    membership: Option<&MembershipInfo>,
+) -> AppResult<ScanProcessingResult> {
+    log_entry_attempt(
+        tx,
+        clock,
+        operator_user_id,
+        member_id,
+        member_name.as_deref(),
+        membership_id,
+        card_id,
+        log_status,
+        message,
+    )
+    .await?;
+
+    Ok(ScanProcessingResult {
+        status,
+        message: message.to_string(),
+        member_name: member_name.cloned(),
+        card_id: Some(card_id.to_string()),
+        membership_type_name: membership.and_then(|m| m.membership_type_name.clone()),
+        membership_end_date: membership.and_then(|m| m.membership_end_date),
+        remaining_visits: membership.and_then(|m| m.membership_remaining_visits),
+        denial_rule: None,
+    })
+}
+
+/// Turns the entry away when the gym is closed at this moment. Returns `None`
+/// while it is open.
+async fn deny_if_closed(
+    tx: &mut Transaction<'_, Sqlite>,
+    clock: &GymClock,
+    operator_user_id: Option<i64>,
+    member_id: Option<i64>,
+    card_id: &str,
+    member_name: Option<&String>,
+) -> AppResult<Option<ScanProcessingResult>> {
+    let now_local = clock.now();
+    if schedule::is_open_at(tx, now_local.naive_local()).await? {
+        return Ok(None);
+    }
+    let day = schedule::day_schedule(tx, now_local.date_naive()).await?;
+
+    let message = match (day.is_closed, day.opens_at, day.closes_at) {
+        (true, _, _) => match &day.closure_reason {
+            Some(reason) => format!("gym_closed|{}", reason),
+            None => "gym_closed".to_string(),
+        },
+        (false, opens_at, closes_at) => format!(
+            "outside_opening_hours|{}-{}",
+            opens_at.map_or("00:00".to_string(), |t| t.format("%H:%M").to_string()),
+            closes_at.map_or("24:00".to_string(), |t| t.format("%H:%M").to_string()),
+        ),
+    };
+    let result = deny_entry(
+        tx,
+        clock,
+        operator_user_id,
+        member_id,
+        None,
+        card_id,
+        EntryStatus::DeniedGymClosed,
+        EntryLogStatus::DeniedGymClosed,
+        &message,
+        member_name,
+        None,
+    )
+    .await?;
+    Ok(Some(ScanProcessingResult {
+        denial_rule: Some(CheckInRule::OpeningHours),
+        ..result
+    }))
+}
+
+/// Checks a member in. Entries and denials are logged and attributed to
+/// `operator_user_id`, the staff member at the front desk.
+pub async fn process_scan(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    clock: &GymClock,
+    actor: &Actor,
+    operator_user_id: Option<i64>,
+    payload: ScanPayload,
+) -> AppResult<ScanProcessingResult> {
+    let mut scanned_card_id = payload.card_id.trim();
+
+    if scanned_card_id.is_empty() {
+        return Ok(ScanProcessingResult {
+            status: EntryStatus::Error,
+            message: "card_invalid".to_string(),
+            member_name: None,
+            card_id: None,
+            membership_type_name: None,
+            membership_end_date: None,
+            remaining_visits: None,
+            denial_rule: None,
+        });
+    }
+
+    tracing::info!("Processing scan for card_id: {}", scanned_card_id);
+
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await.map_err(|e| {
+        tracing::error!("Failed to start transaction: {}", e);
+        AppError::Sqlx(e)
+    })?;
+
+    let policy = &settings.check_in_policy;
+    let today_local = clock.today();
+
+    // Find Member by Card ID
+    let member = match sqlx::query_as::<_, Member>(
+        "SELECT * FROM members WHERE (card_id = ?1 OR short_card_id = ?1) AND is_deleted = FALSE",
+    )
+    .bind(scanned_card_id)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(member)) => member,
+        Ok(None) => {
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                None,
+                None,
+                scanned_card_id,
+                EntryStatus::DeniedMemberNotFound,
+                EntryLogStatus::DeniedMemberNotFound,
+                "member_not_found",
+                None,
+                None,
+            )
+            .await;
+            // Commit transaction even for denied entries to save the log
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+        Err(e) => {
+            tracing::error!("Database error finding member: {}", e);
+            let _ = tx.rollback().await;
+            return Err(AppError::Sqlx(e));
+        }
+    };
+
+    let member_full_name = format!("{} {}", member.first_name, member.last_name);
+    if let Some(card_id) = &member.card_id {
+        scanned_card_id = card_id;
+    } else {
+        tracing::warn!("Member {} has no card ID assigned.", member_full_name);
+    }
+
+    match deny_if_closed(
+        &mut tx,
+        clock,
+        operator_user_id,
+        Some(member.id),
+        scanned_card_id,
+        Some(&member_full_name),
+    )
+    .await
+    {
+        Ok(None) => {}
+        Ok(Some(result)) => {
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+            return Ok(result);
+        }
+        Err(e) => {
+            let _ = tx.rollback().await;
+            return Err(e);
+        }
+    }
+    // Get membership information
+    let membership = match sqlx::query_as::<_, MembershipInfo>(
+        r#"
+        SELECT
+            ms.id AS membership_id,
+            ms.member_id,
+            ms.membership_type_id,
+            ms.start_date as membership_start_date,
+            ms.end_date as membership_end_date,
+            ms.remaining_visits as membership_remaining_visits,
+            ms.status as membership_status,
+            ms.purchase_date as membership_purchase_date,
+            mt.name AS membership_type_name,
+            mt.enter_by AS membership_type_enter_by,
+            '' AS member_first_name,
+            '' AS member_last_name
+        FROM
+            memberships ms
+        LEFT JOIN
+            membership_types mt ON ms.membership_type_id = mt.id
+        WHERE
+            ms.member_id = ?
+            AND (ms.is_deleted IS NULL OR ms.is_deleted = FALSE)
+        ORDER BY
+            CASE ms.status WHEN 'active' THEN 0 ELSE 1 END ASC,
+            CASE ms.status WHEN 'pending' THEN 0 ELSE 1 END ASC,
+            CASE WHEN ms.status = 'pending' THEN ms.start_date ELSE NULL END ASC,
+            CASE WHEN ms.status = 'active' THEN ms.start_date ELSE NULL END DESC,
+            ms.start_date DESC
+        LIMIT 1;
+        "#,
+    )
+    .bind(member.id)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(membership)) => membership,
+        Ok(None) => {
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                None,
+                scanned_card_id,
+                EntryStatus::DeniedNoMembership,
+                EntryLogStatus::DeniedNoMembership,
+                "no_membership",
+                Some(&member_full_name),
+                None,
+            )
+            .await;
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+        Err(e) => {
+            tracing::error!("Database error finding membership: {}", e);
+            let _ = tx.rollback().await;
+            return Err(AppError::Sqlx(e));
+        }
+    };
+    // Validate membership data integrity
+    let (membership_id, current_status, start_date, end_date, remaining_visits) = match (
+        membership.membership_id,
+        membership.membership_status,
+        membership.membership_start_date,
+        membership.membership_end_date,
+        membership.membership_remaining_visits,
+    ) {
+        (Some(id), Some(status), Some(s_date), Some(e_date), Some(rem_visits)) => {
+            (id, status, s_date, e_date, rem_visits)
+        }
+        _ => {
+            tracing::error!(
+                "Invalid membership data for member {}: missing required fields",
+                member_full_name
+            );
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                membership.membership_id,
+                scanned_card_id,
+                EntryStatus::DeniedNoMembership,
+                EntryLogStatus::DeniedNoMembership,
+                "invalid_membership",
+                Some(&member_full_name),
+                Some(&membership),
+            )
+            .await;
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+    };
+    // Calculate and update membership status
+    let membership_status = match calculate_and_update_membership_status_if_needed(
+        &mut tx,
+        membership_id,
+        current_status,
+        start_date,
+        end_date,
+        remaining_visits,
+        clock,
+    )
+    .await
+    {
+        Ok(status) => status,
+        Err(e) => {
+            tracing::error!("Failed to update membership status: {}", e);
+            let _ = tx.rollback().await;
+            return Err(e);
+        }
+    };
+
+    // Memberships past their end date still admit during the grace period,
+    // as long as visits are left; they stay expired.
+    let in_grace_period = membership_status == MembershipStatus::Expired
+        && remaining_visits > 0
+        && policy.is_within_grace_period(end_date, today_local);
+
+    // Check membership status and handle non-active cases
+    match membership_status {
+        MembershipStatus::Expired if in_grace_period => {
+            tracing::info!(
+                "Member {} admitted within grace period of membership ended {}.",
+                member_full_name,
+                end_date
+            );
+        }
+        MembershipStatus::Expired => {
+            let (status, log_status, message, rule) =
+                if membership.membership_remaining_visits.unwrap_or(1) <= 0 {
+                    (
+                        EntryStatus::DeniedNoVisitsLeft,
+                        EntryLogStatus::DeniedNoVisitsLeft,
+                        String::from("no_visits_left"),
+                        None,
+                    )
+                } else {
+                    (
+                        EntryStatus::DeniedMembershipExpired,
+                        EntryLogStatus::DeniedMembershipExpired,
+                        format!("expired_on|{:?}", end_date),
+                        (policy.grace_days_after_end > 0).then_some(CheckInRule::GracePeriod),
+                    )
+                };
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                Some(membership_id),
+                scanned_card_id,
+                status,
+                log_status,
+                message.as_str(),
+                Some(&member_full_name),
+                Some(&membership),
+            )
+            .await
+            .map(|result| ScanProcessingResult {
+                denial_rule: rule,
+                ..result
+            });
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+        MembershipStatus::Pending => {
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                Some(membership_id),
+                scanned_card_id,
+                EntryStatus::DeniedMembershipNotActiveYet,
+                EntryLogStatus::DeniedMembershipNotActiveYet,
+                &format!("pending|{:?}.", start_date),
+                Some(&member_full_name),
+                Some(&membership),
+            )
+            .await;
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+        MembershipStatus::Inactive => {
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                Some(membership_id),
+                scanned_card_id,
+                EntryStatus::DeniedNoMembership,
+                EntryLogStatus::DeniedMembershipInactive,
+                "inactive",
+                Some(&member_full_name),
+                Some(&membership),
+            )
+            .await;
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+        MembershipStatus::Suspended => {
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                Some(membership_id),
+                scanned_card_id,
+                EntryStatus::DeniedMembershipSuspended,
+                EntryLogStatus::DeniedMembershipSuspended,
+                "suspended",
+                Some(&member_full_name),
+                Some(&membership),
+            )
+            .await;
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+        MembershipStatus::Active => {
+            tracing::info!("Member {} has an active membership.", member_full_name);
+        }
+    }
+
+    if let Some(enter_by_hours) = membership.membership_type_enter_by {
+        let current_hour = clock.now().time().hour() as i64;
+
+        if current_hour >= enter_by_hours {
+            let result = deny_entry(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                Some(membership_id),
+                scanned_card_id,
+                EntryStatus::DeniedAfterHours,
+                EntryLogStatus::DeniedAfterHours,
+                &format!("after_hours|{}", enter_by_hours),
+                Some(&member_full_name),
+                Some(&membership),
+            )
+            .await
+            .map(|result| ScanProcessingResult {
+                denial_rule: Some(CheckInRule::EnterByHour),
+                ..result
+            });
+
+            if let Err(e) = tx.commit().await {
+                tracing::error!("Failed to commit transaction for denied entry: {}", e);
+            }
+
+            return result;
+        }
+    }
+
+    // Check today's entries against the daily limit and the re-entry window
+    let now = clock.now_utc();
+    let (entries_today, last_entry_time): (i64, Option<chrono::NaiveDateTime>) = match sqlx::query_as(
+        "SELECT COUNT(*), MAX(entry_time) FROM entry_logs WHERE member_id = ? AND local_date = ? AND status = ?",
+    )
+    .bind(member.id)
+    .bind(today_local)
+    .bind(EntryLogStatus::Allowed)
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(row) => row,
+        Err(e) => {
+            tracing::error!("Database error checking existing entries: {}", e);
+            let _ = tx.rollback().await;
+            return Err(AppError::Sqlx(e));
+        }
+    };
+    let is_reentry = policy.is_reentry(last_entry_time, now);
+
+    if !is_reentry && policy.is_daily_limit_reached(entries_today) {
+        let result = deny_entry(
+            &mut tx,
+            clock,
+            operator_user_id,
+            Some(member.id),
+            Some(membership_id),
+            scanned_card_id,
+            EntryStatus::DeniedAlreadyCheckedIn,
+            EntryLogStatus::DeniedAlreadyCheckedIn,
+            "already_checked",
+            Some(&member_full_name),
+            Some(&membership),
+        )
+        .await
+        .map(|result| ScanProcessingResult {
+            denial_rule: Some(CheckInRule::DailyEntryLimit),
+            ..result
+        });
+        if let Err(e) = tx.commit().await {
+            tracing::error!("Failed to commit transaction for denied entry: {}", e);
+        }
+
+        return result;
+    }
+
+    // Handle active membership - update visits and allow entry. A re-entry
+    // only uses up a visit when the policy says so.
+    let consumes_visit = !is_reentry || policy.reentry_consumes_visit;
+    let new_visits = if consumes_visit {
+        remaining_visits - 1
+    } else {
+        remaining_visits
+    };
+    let new_status = if new_visits > 0 && !in_grace_period {
+        MembershipStatus::Active
+    } else {
+        MembershipStatus::Expired
+    };
+
+    if consumes_visit {
+        let update_result = sqlx::query!(
+            "UPDATE memberships SET remaining_visits = ?, updated_at = ?, status = ? WHERE id = ?",
+            new_visits,
+            now,
+            new_status,
+            membership_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        match update_result {
+            Ok(query_result) => {
+                if query_result.rows_affected() == 0 {
+                    tracing::error!(
+                        "No rows affected when updating membership {} visits",
+                        membership_id
+                    );
+                    let _ = tx.rollback().await;
+                    return Err(AppError::Database(
+                        "Failed to update membership visits".to_string(),
+                    ));
+                }
+                tracing::info!(
+                    "Updated membership {} visits to {}",
+                    membership_id,
+                    new_visits
+                );
+            }
+            Err(e) => {
+                tracing::error!("Failed to update membership {}: {}", membership_id, e);
+                let _ = tx.rollback().await;
+                return Err(AppError::Database(format!(
+                    "Failed to update membership: {}",
+                    e
+                )));
+            }
+        }
+        if let Err(e) = audit::record(
+            &mut *tx,
+            actor,
+            "check_in",
+            "membership",
+            Some(membership_id),
+            Some(serde_json::json!({
+                "remaining_visits": remaining_visits,
+                "status": membership_status,
+            })),
+            Some(serde_json::json!({
+                "remaining_visits": new_visits,
+                "status": new_status,
+            })),
+        )
+        .await
+        {
+            tracing::error!(
+                "Failed to audit entry for membership {}: {}",
+                membership_id,
+                e
+            );
+            let _ = tx.rollback().await;
+            return Err(e);
+        }
+    }
+
+    // Log successful entry; re-entries are logged apart so they don't count
+    // towards the daily limit.
+    let (entry_status, log_status) = if is_reentry {
+        (EntryStatus::AllowedReentry, EntryLogStatus::AllowedReentry)
+    } else {
+        (EntryStatus::Allowed, EntryLogStatus::Allowed)
+    };
+    if let Err(e) = log_entry_attempt(
+        &mut tx,
+        clock,
+        operator_user_id,
+        Some(member.id),
+        Some(&member_full_name),
+        Some(membership_id),
+        scanned_card_id,
+        log_status,
+        log_status.as_str(),
+    )
+    .await
+    {
+        tracing::error!("Failed to log successful entry: {}", e);
+        let _ = tx.rollback().await;
+        return Err(e);
+    }
+
+    // Commit transaction
+    match tx.commit().await {
+        Ok(_) => {
+            tracing::info!(
+                "Successfully processed entry for member: {}",
+                member_full_name
+            );
+        }
+        Err(e) => {
+            tracing::error!("Failed to commit transaction: {}", e);
+            return Err(AppError::Database(format!(
+                "Failed to commit entry transaction: {}",
+                e
+            )));
+        }
+    }
+    Ok(ScanProcessingResult {
+        status: entry_status,
+        message: log_status.as_str().to_string(),
+        member_name: Some(member_full_name),
+        card_id: member.card_id.clone(),
+        membership_type_name: membership.membership_type_name.clone(),
+        membership_end_date: membership.membership_end_date,
+        remaining_visits: Some(new_visits),
+        denial_rule: None,
+    })
+}
+
+/// Logs a one-off visit outside any membership, for a card holder or a
+/// walk-in given by name.
+pub async fn process_scan_single(
+    pool: &SqlitePool,
+    clock: &GymClock,
+    actor: &Actor,
+    operator_user_id: Option<i64>,
+    payload: ScanPayloadSingle,
+) -> AppResult<ScanProcessingResult> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await.map_err(|e| {
+        tracing::error!("Failed to start transaction: {}", e);
+        AppError::Sqlx(e)
+    })?;
+
+    match payload.card_id {
+        Some(card_id) if !card_id.is_empty() => {
+            let member = match sqlx::query_as::<_, Member>(
+              "SELECT * FROM members WHERE (card_id = ?1 OR short_card_id = ?1) AND is_deleted = FALSE",
+          )
+          .bind(&card_id)
+          .fetch_optional(&mut *tx)
+          .await?
+          {
+              Some(member) => member,
+              None => {
+                  let result = deny_entry(
+                      &mut tx,
+                      clock,
+                      operator_user_id,
+                      None,
+                      None,
+                      card_id.as_str(),
+                      EntryStatus::DeniedMemberNotFound,
+                      EntryLogStatus::DeniedMemberNotFound,
+                      "member_not_found",
+                      None,
+                      None,
+                  )
+                  .await;
+                  if let Err(e) = tx.commit().await {
+                      tracing::error!("Failed to commit transaction for denied entry: {}", e);
+                  }
+                  return result;
+              }
+          };
+            let card_id = member.card_id.as_deref().unwrap_or(&card_id);
+            let member_full_name = format!("{} {}", member.first_name, member.last_name);
+
+            if let Some(result) = deny_if_closed(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                card_id,
+                Some(&member_full_name),
+            )
+            .await?
+            {
+                if let Err(e) = tx.commit().await {
+                    tracing::error!("Failed to commit transaction for denied entry: {}", e);
+                }
+                return Ok(result);
+            }
+
+            if let Err(e) = log_entry_attempt(
+                &mut tx,
+                clock,
+                operator_user_id,
+                Some(member.id),
+                Some(&member_full_name),
+                None,
+                card_id,
+                EntryLogStatus::AllowedSingle,
+                "allowed_single",
+            )
+            .await
+            {
+                tracing::error!("Failed to log successful entry: {}", e);
+                let _ = tx.rollback().await;
+                return Err(e);
+            }
+            if let Err(e) = audit::record(
+                &mut *tx,
+                actor,
+                "single_entry",
+                "member",
+                Some(member.id),
+                None,
+                Some(serde_json::json!({ "member_name": member_full_name, "card_id": card_id })),
+            )
+            .await
+            {
+                tracing::error!("Failed to audit single entry: {}", e);
+                let _ = tx.rollback().await;
+                return Err(e);
+            }
+
+            // Commit transaction
+            match tx.commit().await {
+                Ok(_) => {
+                    tracing::info!(
+                        "Successfully processed entry for member: {}",
+                        member_full_name
+                    );
+                }
+                Err(e) => {
+                    tracing::error!("Failed to commit transaction: {}", e);
+                    return Err(AppError::Database(format!(
+                        "Failed to commit entry transaction: {}",
+                        e
+                    )));
+                }
+            }
+
+            Ok(ScanProcessingResult {
+                status: EntryStatus::AllowedSingle,
+                message: "allowed_single".to_string(),
+                member_name: Some(member_full_name),
+                card_id: Some(card_id.to_string()),
+                membership_type_name: None,
+                membership_end_date: None,
+                remaining_visits: None,
+                denial_rule: None,
+            })
+        }
+
+        _ => {
+            if payload.first_name.is_none() && payload.last_name.is_none() {
+                return Ok(ScanProcessingResult {
+                    status: EntryStatus::Error,
+                    message: "card_invalid".to_string(),
+                    member_name: None,
+                    card_id: None,
+                    membership_type_name: None,
+                    membership_end_date: None,
+                    remaining_visits: None,
+                    denial_rule: None,
+                });
+            }
+            let member_full_name = format!(
+                "{} {}",
+                payload.first_name.as_deref().unwrap_or(""),
+                payload.last_name.as_deref().unwrap_or("")
+            );
+
+            if let Some(result) = deny_if_closed(
+                &mut tx,
+                clock,
+                operator_user_id,
+                None,
+                "",
+                Some(&member_full_name),
+            )
+            .await?
+            {
+                if let Err(e) = tx.commit().await {
+                    tracing::error!("Failed to commit transaction for denied entry: {}", e);
+                }
+                return Ok(result);
+            }
+
+            if let Err(e) = log_entry_attempt(
+                &mut tx,
+                clock,
+                operator_user_id,
+                None,
+                Some(&member_full_name),
+                None,
+                "",
+                EntryLogStatus::AllowedSingle,
+                "allowed_single",
+            )
+            .await
+            {
+                tracing::error!("Failed to log successful entry: {}", e);
+                let _ = tx.rollback().await;
+                return Err(e);
+            }
+            if let Err(e) = audit::record(
+                &mut *tx,
+                actor,
+                "single_entry",
+                "member",
+                None,
+                None,
+                Some(serde_json::json!({ "member_name": member_full_name })),
+            )
+            .await
+            {
+                tracing::error!("Failed to audit single entry: {}", e);
+                let _ = tx.rollback().await;
+                return Err(e);
+            }
+
+            // Commit transaction
+            match tx.commit().await {
+                Ok(_) => {
+                    tracing::info!(
+                        "Successfully processed entry for member: {}",
+                        member_full_name
+                    );
+                }
+                Err(e) => {
+                    tracing::error!("Failed to commit transaction: {}", e);
+                    return Err(AppError::Database(format!(
+                        "Failed to commit entry transaction: {}",
+                        e
+                    )));
+                }
+            }
+            Ok(ScanProcessingResult {
+                status: EntryStatus::AllowedSingle,
+                message: "allowed_single".to_string(),
+                member_name: Some(member_full_name),
+                card_id: None,
+                membership_type_name: None,
+                membership_end_date: None,
+                remaining_visits: None,
+                denial_rule: None,
+            })
+        }
+    }
+}
+
+// Helper to log entry attempts
+async fn log_entry_attempt(
+    tx: &mut Transaction<'_, Sqlite>,
+    clock: &GymClock,
+    operator_user_id: Option<i64>,
+    member_id: Option<i64>,
+    member_name: Option<&String>,
+    membership_id: Option<i64>,
+    card_id_scanned: &str,
+    status: EntryLogStatus,
+    notes: &str,
+) -> AppResult<()> {
+    let now = clock.now_utc();
+    let local_date = clock.today();
+    sqlx::query(
+        r#"
+        INSERT INTO entry_logs (member_id, membership_id, member_name, card_id, entry_time, status, notes, local_date, operator_user_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(member_id)
+    .bind(membership_id)
+    .bind(member_name)
+    .bind(card_id_scanned)
+    .bind(now)
+    .bind(status)
+    .bind(notes)
+    .bind(local_date)
+    .bind(operator_user_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
@@ -1,22 +1,122 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
 use sqlx::SqlitePool;
 
+use crate::auth::{Operator, PendingTwoFactor, Session};
+use crate::clock::{GymClock, SystemTime, TimeSource};
 use crate::config::AppSettings;
+use crate::db;
+use crate::error::Result as AppResult;
+use crate::profiles::Profile;
 
 #[derive(Debug)]
 pub struct AppState {
-    pub db_pool: SqlitePool,
+    /// Swapped out when another gym profile is opened; see [`AppState::db_pool`].
+    db_pool: RwLock<SqlitePool>,
+    pub profile: tokio::sync::RwLock<Profile>,
+    pub database_path: tokio::sync::RwLock<PathBuf>,
     pub settings: tokio::sync::RwLock<AppSettings>,
     pub last_membership_check: tokio::sync::RwLock<Option<chrono::NaiveDateTime>>,
     pub last_backup: tokio::sync::RwLock<Option<chrono::NaiveDateTime>>,
+    pub session: tokio::sync::RwLock<Option<Session>>,
+    pub pending_two_factor: tokio::sync::RwLock<Option<PendingTwoFactor>>,
+    pub active_operator: tokio::sync::RwLock<Option<Operator>>,
+    pub time_source: Arc<dyn TimeSource>,
 }
 
 impl AppState {
-    pub fn new(db_pool: SqlitePool, settings: AppSettings) -> Self {
+    pub fn new(
+        db_pool: SqlitePool,
+        settings: AppSettings,
+        profile: Profile,
+        database_path: PathBuf,
+    ) -> Self {
         Self {
-            db_pool,
+            db_pool: RwLock::new(db_pool),
+            profile: tokio::sync::RwLock::new(profile),
+            database_path: tokio::sync::RwLock::new(database_path),
             settings: tokio::sync::RwLock::new(settings),
             last_membership_check: tokio::sync::RwLock::new(None),
             last_backup: tokio::sync::RwLock::new(None),
+            session: tokio::sync::RwLock::new(None),
+            pending_two_factor: tokio::sync::RwLock::new(None),
+            active_operator: tokio::sync::RwLock::new(None),
+            time_source: Arc::new(SystemTime),
+        }
+    }
+
+    /// Clock in the gym timezone from the current settings.
+    pub async fn gym_clock(&self) -> AppResult<GymClock> {
+        let tz = self.settings.read().await.gym_timezone()?;
+        Ok(GymClock::new(self.time_source.clone(), tz))
+    }
+
+    /// Pool of the database currently open. Pools are cheap handles, so take a
+    /// fresh one per use instead of holding on to it across a profile switch.
+    pub fn db_pool(&self) -> SqlitePool {
+        self.db_pool
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Points the app at another database. Everything tied to the old one
+    /// (session, settings, cached check times) is dropped. Returns the old
+    /// pool so the caller can close it.
+    pub async fn switch_database(
+        &self,
+        db_pool: SqlitePool,
+        settings: AppSettings,
+        profile: Profile,
+        database_path: PathBuf,
+    ) -> SqlitePool {
+        *self.session.write().await = None;
+        *self.pending_two_factor.write().await = None;
+        *self.active_operator.write().await = None;
+        *self.last_membership_check.write().await = None;
+        *self.last_backup.write().await = None;
+        *self.settings.write().await = settings;
+        *self.profile.write().await = profile;
+
+        self.replace_pool(db_pool, database_path).await
+    }
+
+    /// Opens the database at `database_path`, e.g. just restored, migrating
+    /// it, and swaps it in for the current pool. Should that fail, it is
+    /// swapped in as it is instead, so the app keeps a pool that works on
+    /// whatever is on disk, and the error is returned.
+    pub async fn reopen_database(&self, database_path: PathBuf) -> AppResult<SqlitePool> {
+        let error = match db::init_db(&database_path).await {
+            Ok(pool) => {
+                self.replace_pool(pool.clone(), database_path).await;
+                return Ok(pool);
+            }
+            Err(e) => e,
+        };
+        tracing::error!(
+            "Failed to open {:?}, opening it without migrating: {:?}",
+            database_path,
+            error
+        );
+        match db::connect(&database_path).await {
+            Ok(pool) => {
+                self.replace_pool(pool, database_path).await;
+            }
+            Err(e) => tracing::error!("Failed to reopen {:?}: {:?}", database_path, e),
         }
+        Err(error)
+    }
+
+    /// Swaps in a pool on the same data at a new location, e.g. after the
+    /// database was moved. The session and settings stay as they are.
+    pub async fn replace_pool(&self, db_pool: SqlitePool, database_path: PathBuf) -> SqlitePool {
+        *self.database_path.write().await = database_path;
+
+        let mut current = self
+            .db_pool
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        std::mem::replace(&mut *current, db_pool)
     }
 }
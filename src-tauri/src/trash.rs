@@ -0,0 +1,110 @@
+//! Member trash: deleting a member only flags it, so it can be restored until
+//! it is purged, by hand or once the retention period from the settings has
+//! passed.
+
+use std::time::Duration;
+
+use sqlx::{SqliteConnection, SqlitePool};
+use tauri::Manager;
+use tokio::time::interval;
+
+use crate::{
+    audit::{self, Actor},
+    error::Result as AppResult,
+    models::Member,
+    state::AppState,
+};
+
+const TRASH_PURGE_INTERVAL_MINUTES: u64 = 60;
+
+pub async fn fetch_deleted_member(
+    conn: &mut SqliteConnection,
+    id: i64,
+) -> AppResult<Option<Member>> {
+    let member = sqlx::query_as::<_, Member>(
+        "SELECT id, card_id, short_card_id, first_name, last_name, email, phone, date_of_birth, created_at, updated_at, is_deleted FROM members WHERE id = ? AND is_deleted = TRUE",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(member)
+}
+
+/// Permanently deletes a member that is in the trash, together with its
+/// memberships. Entry logs are kept: they lose the link to the member but
+/// keep the name, so the visit history stays readable.
+pub async fn purge_member(conn: &mut SqliteConnection, member: &Member) -> AppResult<()> {
+    sqlx::query(
+        "UPDATE entry_logs SET member_name = COALESCE(member_name, ?), member_id = NULL WHERE member_id = ?",
+    )
+    .bind(format!("{} {}", member.first_name, member.last_name))
+    .bind(member.id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM members WHERE id = ? AND is_deleted = TRUE")
+        .bind(member.id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Purges members that have been in the trash longer than `retention_days`.
+/// Returns the number of purged members.
+pub async fn purge_expired_members(pool: &SqlitePool, retention_days: u32) -> AppResult<u64> {
+    if retention_days == 0 {
+        return Ok(0);
+    }
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(retention_days as i64);
+
+    let expired: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM members WHERE is_deleted = TRUE AND deleted_at IS NOT NULL AND deleted_at <= ?",
+    )
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await?;
+
+    let mut purged = 0;
+    for id in expired {
+        let mut tx = pool.begin().await?;
+        let Some(member) = fetch_deleted_member(&mut tx, id).await? else {
+            continue;
+        };
+        purge_member(&mut tx, &member).await?;
+        audit::record(
+            &mut *tx,
+            &Actor::system(),
+            "purge",
+            "member",
+            Some(id),
+            audit::snapshot(&member),
+            None,
+        )
+        .await?;
+        tx.commit().await?;
+        purged += 1;
+    }
+    Ok(purged)
+}
+
+pub fn spawn_trash_purge_task(app_handle: tauri::AppHandle) {
+    tracing::info!(
+        "Spawning periodic member trash purge task (Interval: {} minutes)",
+        TRASH_PURGE_INTERVAL_MINUTES,
+    );
+    tokio::spawn(async move {
+        let mut purge_timer = interval(Duration::from_secs(TRASH_PURGE_INTERVAL_MINUTES * 60));
+        purge_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            purge_timer.tick().await;
+
+            let app_state = app_handle.state::<AppState>();
+            let retention_days = app_state.settings.read().await.member_trash_retention_days;
+            match purge_expired_members(&app_state.db_pool(), retention_days).await {
+                Ok(0) => tracing::debug!("No deleted members due for purging."),
+                Ok(count) => tracing::info!("Purged {} member(s) from the trash.", count),
+                Err(e) => tracing::error!("Error purging the member trash: {:?}", e),
+            }
+        }
+    });
+}
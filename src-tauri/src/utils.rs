@@ -1,8 +1,10 @@
 use std::time::Duration;
 
 use crate::{
-    error::{AppError, Result as AppResult},
+    config::PasswordPolicySettings,
+    error::{AppError, ErrorCodes, Result as AppResult, TranslatableError},
     models::CronCheck,
+    services::{entry_logs, memberships},
     AppState,
 };
 
@@ -12,7 +14,6 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
-use chrono::Local;
 use tauri::Manager;
 use tokio::time::interval;
 
@@ -29,7 +30,46 @@ pub fn hash_password(password: &str) -> AppResult<String> {
     Ok(password_hash)
 }
 
-#[allow(dead_code)]
+/// Checks a new password against the configured complexity rules.
+pub fn validate_password(password: &str, policy: &PasswordPolicySettings) -> AppResult<()> {
+    let violation = |rule: &str, fallback: &str| {
+        AppError::Translatable(TranslatableError::with_params(
+            ErrorCodes::PASSWORD_POLICY_VIOLATION,
+            serde_json::json!({ "rule": rule, "min_length": policy.min_length }),
+            fallback,
+        ))
+    };
+
+    if password.chars().count() < policy.min_length {
+        return Err(violation(
+            "min_length",
+            &format!(
+                "Password must be at least {} characters long",
+                policy.min_length
+            ),
+        ));
+    }
+    if policy.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+        return Err(violation(
+            "uppercase",
+            "Password must contain an uppercase letter",
+        ));
+    }
+    if policy.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+        return Err(violation(
+            "lowercase",
+            "Password must contain a lowercase letter",
+        ));
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(violation("digit", "Password must contain a digit"));
+    }
+    if policy.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+        return Err(violation("symbol", "Password must contain a symbol"));
+    }
+    Ok(())
+}
+
 pub fn verify_password(password: &str, hash: &str) -> AppResult<bool> {
     let parsed_hash = PasswordHash::new(hash)
         .map_err(|e| AppError::Config(format!("Invalid password hash format: {}", e)))?;
@@ -39,15 +79,29 @@ pub fn verify_password(password: &str, hash: &str) -> AppResult<bool> {
         .is_ok())
 }
 
-async fn load_last_checked_membership_date(
-    app_state: &tauri::State<'_, AppState>,
+/// Fills in the gym-local date of entries logged before it was recorded, in
+/// the database just opened. Failing only keeps those entries out of date
+/// filters, so it is logged instead of stopping the caller.
+pub async fn backfill_entry_local_dates(app_state: &AppState) {
+    let result = async {
+        let clock = app_state.gym_clock().await?;
+        entry_logs::backfill_local_dates(&app_state.db_pool(), &clock).await
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to fill in the local date of entry logs: {:?}", e);
+    }
+}
+
+pub(crate) async fn load_last_checked_membership_date(
+    app_state: &AppState,
 ) -> AppResult<chrono::NaiveDateTime> {
     println!("✅ ✅✅✅✅loading from DB");
     let check = sqlx::query_as!(
           CronCheck,
           "SELECT id as `id!`, last_check_time, check_type, status, created_at, updated_at FROM cron_checks WHERE check_type = 'membership' ORDER BY last_check_time DESC LIMIT 1",
       )
-      .fetch_optional(&app_state.db_pool)
+      .fetch_optional(&app_state.db_pool())
       .await?;
 
     match check {
@@ -56,62 +110,8 @@ async fn load_last_checked_membership_date(
     }
 }
 
-async fn update_membership_statuses(app_state: &tauri::State<'_, AppState>) -> AppResult<i64> {
-    let today = Local::now().date_naive();
-    let mut tx = app_state.db_pool.begin().await?;
-
-    // 1. Update pending memberships to active (start_date <= today)
-    let pending_query = r#"
-        UPDATE memberships
-        SET status = 'active',
-            updated_at = CURRENT_TIMESTAMP
-        WHERE status = 'pending'
-        AND start_date <= ?
-        AND is_deleted = FALSE
-    "#;
-
-    let pending_result = sqlx::query(pending_query)
-        .bind(today)
-        .execute(&mut *tx)
-        .await?;
-
-    let pending_to_active_count = pending_result.rows_affected() as i64;
-
-    let expired_query = r#"
-        UPDATE memberships
-        SET status = 'expired',
-            updated_at = CURRENT_TIMESTAMP
-        WHERE status = 'active'
-        AND end_date < ?
-        AND is_deleted = FALSE
-    "#;
-
-    let expired_result = sqlx::query(expired_query)
-        .bind(today)
-        .execute(&mut *tx)
-        .await?;
-
-    let active_to_expired_count = expired_result.rows_affected() as i64;
-    // Commit the transaction
-    tx.commit().await?;
-
-    let total_updated = pending_to_active_count + active_to_expired_count;
-
-    // Log the results
-    if total_updated > 0 {
-        println!("📊 Membership Status Update Summary:");
-        println!("   • Pending → Active: {}", pending_to_active_count);
-        println!("   • Active → Expired: {}", active_to_expired_count);
-        println!("   • Total Updated: {}", total_updated);
-    } else {
-        println!("✅ All membership statuses are already current");
-    }
-
-    Ok(total_updated)
-}
-
 async fn save_last_checked_membership_date(
-    app_state: &tauri::State<'_, AppState>,
+    app_state: &AppState,
     date: chrono::NaiveDateTime,
     status: &str,
 ) -> AppResult<()> {
@@ -122,7 +122,7 @@ async fn save_last_checked_membership_date(
         date,
         status
     )
-    .execute(&app_state.db_pool)
+    .execute(&app_state.db_pool())
     .await?;
 
     // Try to get the lock without waiting
@@ -136,8 +136,8 @@ async fn save_last_checked_membership_date(
     Ok(())
 }
 
-pub async fn check_membership_statuses(app_state: &tauri::State<'_, AppState>) -> AppResult<()> {
-    let today = Local::now().naive_local();
+pub async fn check_membership_statuses(app_state: &AppState) -> AppResult<()> {
+    let today = app_state.gym_clock().await?.now_local();
     let last_check_in_memory = *app_state.last_membership_check.read().await;
 
     let last_checked: chrono::NaiveDateTime;
@@ -151,7 +151,8 @@ pub async fn check_membership_statuses(app_state: &tauri::State<'_, AppState>) -
     }
 
     if last_checked.date() < today.date() {
-        let updated_count = update_membership_statuses(&app_state).await;
+        let updated_count =
+            memberships::update_membership_statuses(&app_state.db_pool(), today.date()).await;
         match updated_count {
             Ok(count) => {
                 if count > 0 {
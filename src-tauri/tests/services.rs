@@ -0,0 +1,2257 @@
+//! Runs the service layer against a fresh in-memory database.
+
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use gym_manager_lib::audit::Actor;
+use gym_manager_lib::clock::{FixedTime, GymClock};
+use gym_manager_lib::config::{
+    self, parse_backup_url, AppSettings, BackupStoreSettings, CheckInPolicy, CurrencySettings,
+    CurrencySymbolPosition, LocalBackupSettings, LoginLockoutSettings, PasswordPolicySettings,
+    S3StoreSettings, WebDavStoreSettings,
+};
+use gym_manager_lib::db;
+use gym_manager_lib::dto::{
+    AppSettingsView, BackupManifest, CheckInRule, DaySchedule, EntryLogQueryParams, EntryStatus,
+    FilterField, GetDeletedMembersPayload, GetMemberByIdPayload, GetMembersPaginatedPayload,
+    MemberPayload, MembershipPayload, NewMembershipTypePayload, ScanPayload, ScanPayloadSingle,
+    ScanProcessingResult, TransferDirection,
+};
+use gym_manager_lib::error::{AppError, ErrorCodes};
+use gym_manager_lib::models::{EntryLogStatus, Member, MembershipStatus, MembershipType};
+use gym_manager_lib::profiles::Profile;
+use gym_manager_lib::services::{
+    analytics, backup, backup_encryption, backup_manifest, backup_store, entry_logs, members,
+    membership_types, memberships, scanning,
+};
+use gym_manager_lib::{auth, schedule, totp, utils, AppState};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+
+async fn test_pool() -> SqlitePool {
+    // A single connection that never expires, as every connection to
+    // `sqlite::memory:` opens its own empty database.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("open in-memory database");
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    pool
+}
+
+/// Mid-morning at a gym in Belgrade, pinned so tests don't depend on the
+/// machine's clock or timezone.
+fn test_clock() -> GymClock {
+    GymClock::new(
+        Arc::new(FixedTime::new(
+            Utc.with_ymd_and_hms(2025, 3, 12, 9, 0, 0).unwrap(),
+        )),
+        chrono_tz::Europe::Belgrade,
+    )
+}
+
+/// [`test_clock`] with a handle to move its time.
+fn movable_test_clock() -> (GymClock, Arc<FixedTime>) {
+    let time = Arc::new(FixedTime::new(
+        Utc.with_ymd_and_hms(2025, 3, 12, 9, 0, 0).unwrap(),
+    ));
+    (
+        GymClock::new(time.clone(), chrono_tz::Europe::Belgrade),
+        time,
+    )
+}
+
+async fn add_membership_type(pool: &SqlitePool, name: &str, visits: i64) -> MembershipType {
+    membership_types::add_membership_type(
+        pool,
+        &AppSettings::default(),
+        &Actor::system(),
+        NewMembershipTypePayload {
+            name: name.to_string(),
+            duration_days: Some(30),
+            visit_limit: Some(visits),
+            enter_by: None,
+            price_minor: 4_000,
+            description: None,
+            is_active: Some(true),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn add_member(pool: &SqlitePool, card_id: &str, first_name: &str) -> Member {
+    members::add_member(
+        pool,
+        &Actor::system(),
+        MemberPayload {
+            id: None,
+            card_id: card_id.to_string(),
+            first_name: first_name.to_string(),
+            last_name: "Tester".to_string(),
+            email: None,
+            phone: None,
+            date_of_birth: None,
+        },
+    )
+    .await
+    .unwrap()
+}
+
+async fn add_active_membership(
+    pool: &SqlitePool,
+    clock: &GymClock,
+    member_id: i64,
+    membership_type_id: i64,
+    visits: i64,
+) {
+    let end_date = clock.today() + Duration::days(29);
+    add_membership_ending(pool, clock, member_id, membership_type_id, end_date, visits).await;
+}
+
+/// Adds a 30 day membership that ends on `end_date`.
+async fn add_membership_ending(
+    pool: &SqlitePool,
+    clock: &GymClock,
+    member_id: i64,
+    membership_type_id: i64,
+    end_date: NaiveDate,
+    visits: i64,
+) {
+    memberships::save_membership(
+        pool,
+        clock,
+        &Actor::system(),
+        None,
+        MembershipPayload {
+            member_id,
+            membership_id: None,
+            membership_type_id: Some(membership_type_id),
+            membership_start_date: Some(end_date - Duration::days(30)),
+            membership_end_date: Some(end_date),
+            membership_remaining_visits: Some(visits),
+            membership_suspended: None,
+        },
+    )
+    .await
+    .unwrap();
+}
+
+async fn scan(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    clock: &GymClock,
+    card_id: &str,
+) -> EntryStatus {
+    scan_result(pool, settings, clock, card_id).await.status
+}
+
+async fn scan_result(
+    pool: &SqlitePool,
+    settings: &AppSettings,
+    clock: &GymClock,
+    card_id: &str,
+) -> ScanProcessingResult {
+    scanning::process_scan(
+        pool,
+        settings,
+        clock,
+        &Actor::system(),
+        None,
+        ScanPayload {
+            card_id: card_id.to_string(),
+        },
+    )
+    .await
+    .unwrap()
+}
+
+fn with_check_in_policy(policy: CheckInPolicy) -> AppSettings {
+    AppSettings {
+        check_in_policy: policy,
+        ..AppSettings::default()
+    }
+}
+
+async fn entry_log_statuses(pool: &SqlitePool, member_id: i64) -> Vec<EntryLogStatus> {
+    sqlx::query_scalar("SELECT status FROM entry_logs WHERE member_id = ? ORDER BY id")
+        .bind(member_id)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+fn error_code(err: AppError) -> String {
+    match err {
+        AppError::Translatable(e) => e.error_code,
+        other => panic!("expected a translatable error, got {:?}", other),
+    }
+}
+
+/// App state over `pool`, with nobody logged in.
+fn test_state(pool: SqlitePool) -> AppState {
+    let profile = Profile {
+        id: "default".to_string(),
+        name: "Gym".to_string(),
+        database_file: "gym.sqlite".to_string(),
+        database_dir: None,
+        created_at: Utc::now().naive_utc(),
+    };
+    AppState::new(pool, AppSettings::default(), profile, "gym.sqlite".into())
+}
+
+/// Logs `username` in with the permissions of `role`.
+async fn log_in(state: &AppState, user_id: i64, username: &str, role: &str) {
+    let permissions = auth::load_role_permissions(&state.db_pool(), role)
+        .await
+        .unwrap()
+        .expect("role exists");
+    auth::start_session(state, user_id, username, role, permissions, false).await;
+}
+
+async fn add_user(pool: &SqlitePool, username: &str, role: &str, password: &str) -> i64 {
+    sqlx::query_scalar(
+        "INSERT INTO users (username, role, password_hash) VALUES (?, ?, ?) RETURNING id",
+    )
+    .bind(username)
+    .bind(role)
+    .bind(utils::hash_password(password).unwrap())
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn membership_types_can_be_added_updated_and_deleted() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    assert_eq!(monthly.price_minor, 4_000);
+    assert_eq!(monthly.currency, settings.currency.code);
+
+    let updated = membership_types::update_membership_type(
+        &pool,
+        &settings,
+        &Actor::system(),
+        monthly.id,
+        NewMembershipTypePayload {
+            name: "Monthly 16".to_string(),
+            duration_days: Some(30),
+            visit_limit: Some(16),
+            enter_by: None,
+            price_minor: 5_050,
+            description: None,
+            is_active: Some(true),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated.name, "Monthly 16");
+    assert_eq!(updated.visit_limit, Some(16));
+    assert_eq!(updated.price_minor, 5_050);
+
+    membership_types::delete_membership_type(&pool, &Actor::system(), monthly.id)
+        .await
+        .unwrap();
+    assert!(membership_types::get_all_membership_types(&pool, &settings)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn deleted_members_go_to_the_trash_and_can_be_restored_or_purged() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let member = add_member(&pool, "CARD-1", "Ana").await;
+
+    members::delete_member(&pool, &Actor::system(), member.id)
+        .await
+        .unwrap();
+    let listed = members::get_members_with_memberships_paginated(
+        &pool,
+        GetMembersPaginatedPayload {
+            page: None,
+            per_page: None,
+            order_by: None,
+            order_direction: None,
+            search_string: None,
+            filter_fields: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(listed.total, 0);
+
+    let trash = members::get_deleted_members(
+        &pool,
+        &settings,
+        GetDeletedMembersPayload {
+            page: None,
+            per_page: None,
+            search_string: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(trash.total, 1);
+    assert!(trash.data[0].purge_after.is_some());
+
+    // The card stays reserved while the member is in the trash.
+    let err = members::add_member(
+        &pool,
+        &Actor::system(),
+        MemberPayload {
+            id: None,
+            card_id: "CARD-1".to_string(),
+            first_name: "Bojan".to_string(),
+            last_name: "Tester".to_string(),
+            email: None,
+            phone: None,
+            date_of_birth: None,
+        },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::CARD_BELONGS_TO_DELETED_MEMBER);
+
+    let restored = members::restore_member(&pool, &Actor::system(), member.id)
+        .await
+        .unwrap();
+    assert!(!restored.is_deleted);
+
+    members::delete_member(&pool, &Actor::system(), member.id)
+        .await
+        .unwrap();
+    members::purge_deleted_member(&pool, &Actor::system(), member.id)
+        .await
+        .unwrap();
+    assert!(
+        members::get_member_by_id(&pool, GetMemberByIdPayload { id: member.id })
+            .await
+            .unwrap()
+            .is_none()
+    );
+    add_member(&pool, "CARD-1", "Bojan").await;
+}
+
+#[tokio::test]
+async fn scanning_admits_once_per_day_and_uses_a_visit() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-2", "Ivana").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "CARD-2").await,
+        EntryStatus::Allowed
+    ));
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "CARD-2").await,
+        EntryStatus::DeniedAlreadyCheckedIn
+    ));
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "UNKNOWN").await,
+        EntryStatus::DeniedMemberNotFound
+    ));
+
+    let with_membership = members::get_member_by_id_with_membership(
+        &pool,
+        &settings,
+        GetMemberByIdPayload { id: member.id },
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(with_membership.membership_remaining_visits, Some(11));
+
+    let member_logs = entry_logs::get_member_entry_logs(&pool, member.id, None)
+        .await
+        .unwrap();
+    assert_eq!(member_logs.len(), 2);
+
+    let all_logs = entry_logs::get_entry_logs(
+        &pool,
+        EntryLogQueryParams {
+            date_from: None,
+            date_to: None,
+            page: None,
+            per_page: None,
+            order_by: None,
+            order_direction: None,
+            search_string: None,
+            filter_fields: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(all_logs.total, 3);
+
+    entry_logs::delete_entry_log(&pool, &Actor::system(), member_logs[0].id)
+        .await
+        .unwrap();
+    assert_eq!(
+        entry_logs::get_recent_entry_logs(&pool, None)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn entries_belong_to_the_gym_local_day() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    // 00:30 on Sunday 30 March in Belgrade, the night clocks go forward.
+    let time = Arc::new(FixedTime::new(
+        Utc.with_ymd_and_hms(2025, 3, 29, 23, 30, 0).unwrap(),
+    ));
+    let clock = GymClock::new(time.clone(), chrono_tz::Europe::Belgrade);
+    let sunday = NaiveDate::from_ymd_opt(2025, 3, 30).unwrap();
+    assert_eq!(clock.today(), sunday);
+
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-5", "Luka").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "CARD-5").await,
+        EntryStatus::Allowed
+    ));
+
+    let logs_on = |date: NaiveDate| EntryLogQueryParams {
+        date_from: Some(date),
+        date_to: Some(date),
+        page: None,
+        per_page: None,
+        order_by: None,
+        order_direction: None,
+        search_string: None,
+        filter_fields: None,
+    };
+    let saturday = sunday - Duration::days(1);
+    assert_eq!(
+        entry_logs::get_entry_logs(&pool, logs_on(sunday))
+            .await
+            .unwrap()
+            .total,
+        1
+    );
+    assert_eq!(
+        entry_logs::get_entry_logs(&pool, logs_on(saturday))
+            .await
+            .unwrap()
+            .total,
+        0
+    );
+
+    let visits = analytics::get_daily_hourly_visit_count(&pool, &clock, sunday, sunday)
+        .await
+        .unwrap();
+    assert_eq!(visits.len(), 1);
+    assert_eq!(
+        (
+            visits[0].day_of_week,
+            visits[0].hour_of_day,
+            visits[0].visit_count
+        ),
+        (0, 0, 1)
+    );
+
+    // 21:30 the same evening, after the switch to summer time.
+    time.advance(Duration::hours(20));
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "CARD-5").await,
+        EntryStatus::DeniedAlreadyCheckedIn
+    ));
+    // 00:30 on Monday.
+    time.advance(Duration::hours(3));
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "CARD-5").await,
+        EntryStatus::Allowed
+    ));
+}
+
+#[tokio::test]
+async fn entries_without_a_local_date_get_the_gym_date() {
+    let pool = test_pool().await;
+    let clock = test_clock();
+    // 23:30 UTC on 11 March is already 12 March in Belgrade.
+    for entry_time in ["2025-03-11 23:30:00", "2025-03-11 12:00:00"] {
+        sqlx::query(
+            "INSERT INTO entry_logs (card_id, entry_time, status) VALUES ('OLD', ?, 'allowed')",
+        )
+        .bind(entry_time)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(
+        entry_logs::backfill_local_dates(&pool, &clock)
+            .await
+            .unwrap(),
+        2
+    );
+    let dates: Vec<NaiveDate> = sqlx::query_scalar("SELECT local_date FROM entry_logs ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        dates,
+        vec![
+            NaiveDate::from_ymd_opt(2025, 3, 12).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 3, 11).unwrap(),
+        ]
+    );
+    assert_eq!(
+        entry_logs::backfill_local_dates(&pool, &clock)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn check_in_policy_limits_entries_per_day() {
+    let pool = test_pool().await;
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-10", "Mila").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+
+    let twice_a_day = with_check_in_policy(CheckInPolicy {
+        max_entries_per_day: 2,
+        ..CheckInPolicy::default()
+    });
+    for remaining_visits in [11, 10] {
+        let result = scan_result(&pool, &twice_a_day, &clock, "CARD-10").await;
+        assert!(matches!(result.status, EntryStatus::Allowed));
+        assert_eq!(result.remaining_visits, Some(remaining_visits));
+    }
+    let denied = scan_result(&pool, &twice_a_day, &clock, "CARD-10").await;
+    assert!(matches!(denied.status, EntryStatus::DeniedAlreadyCheckedIn));
+    assert_eq!(denied.denial_rule, Some(CheckInRule::DailyEntryLimit));
+    assert_eq!(denied.remaining_visits, Some(10));
+
+    let unlimited = with_check_in_policy(CheckInPolicy {
+        max_entries_per_day: 0,
+        ..CheckInPolicy::default()
+    });
+    assert!(matches!(
+        scan(&pool, &unlimited, &clock, "CARD-10").await,
+        EntryStatus::Allowed
+    ));
+}
+
+#[tokio::test]
+async fn reentries_within_the_window_are_logged_apart() {
+    let pool = test_pool().await;
+    let (clock, time) = movable_test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-11", "Petar").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    let settings = with_check_in_policy(CheckInPolicy {
+        reentry_window_minutes: 30,
+        ..CheckInPolicy::default()
+    });
+
+    assert_eq!(
+        scan_result(&pool, &settings, &clock, "CARD-11")
+            .await
+            .remaining_visits,
+        Some(11)
+    );
+    time.advance(Duration::minutes(20));
+    let reentry = scan_result(&pool, &settings, &clock, "CARD-11").await;
+    assert!(matches!(reentry.status, EntryStatus::AllowedReentry));
+    assert_eq!(reentry.remaining_visits, Some(11));
+    // The window runs from the regular entry, not from the re-entry.
+    time.advance(Duration::minutes(20));
+    let denied = scan_result(&pool, &settings, &clock, "CARD-11").await;
+    assert!(matches!(denied.status, EntryStatus::DeniedAlreadyCheckedIn));
+    assert_eq!(denied.denial_rule, Some(CheckInRule::DailyEntryLimit));
+
+    assert_eq!(
+        entry_log_statuses(&pool, member.id).await,
+        vec![
+            EntryLogStatus::Allowed,
+            EntryLogStatus::AllowedReentry,
+            EntryLogStatus::DeniedAlreadyCheckedIn,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn reentries_can_be_made_to_use_a_visit() {
+    let pool = test_pool().await;
+    let (clock, time) = movable_test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-12", "Sara").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    let settings = with_check_in_policy(CheckInPolicy {
+        reentry_window_minutes: 30,
+        reentry_consumes_visit: true,
+        ..CheckInPolicy::default()
+    });
+
+    scan(&pool, &settings, &clock, "CARD-12").await;
+    time.advance(Duration::minutes(10));
+    let reentry = scan_result(&pool, &settings, &clock, "CARD-12").await;
+    assert!(matches!(reentry.status, EntryStatus::AllowedReentry));
+    assert_eq!(reentry.remaining_visits, Some(10));
+    let remaining: i64 =
+        sqlx::query_scalar("SELECT remaining_visits FROM memberships WHERE member_id = ?")
+            .bind(member.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 10);
+}
+
+#[tokio::test]
+async fn expired_memberships_admit_during_the_grace_days() {
+    let pool = test_pool().await;
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let ended = clock.today() - Duration::days(2);
+    for card_id in ["CARD-13", "CARD-14", "CARD-15"] {
+        let member = add_member(&pool, card_id, card_id).await;
+        add_membership_ending(&pool, &clock, member.id, monthly.id, ended, 5).await;
+    }
+    let grace_days = |days| {
+        with_check_in_policy(CheckInPolicy {
+            grace_days_after_end: days,
+            ..CheckInPolicy::default()
+        })
+    };
+
+    let admitted = scan_result(&pool, &grace_days(2), &clock, "CARD-13").await;
+    assert!(matches!(admitted.status, EntryStatus::Allowed));
+    assert_eq!(admitted.remaining_visits, Some(4));
+    let status: MembershipStatus = sqlx::query_scalar(
+        "SELECT ms.status FROM memberships ms JOIN members m ON m.id = ms.member_id WHERE m.card_id = ?",
+    )
+    .bind("CARD-13")
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(status, MembershipStatus::Expired);
+
+    let too_late = scan_result(&pool, &grace_days(1), &clock, "CARD-14").await;
+    assert!(matches!(
+        too_late.status,
+        EntryStatus::DeniedMembershipExpired
+    ));
+    assert_eq!(too_late.denial_rule, Some(CheckInRule::GracePeriod));
+
+    let no_grace = scan_result(&pool, &grace_days(0), &clock, "CARD-15").await;
+    assert!(matches!(
+        no_grace.status,
+        EntryStatus::DeniedMembershipExpired
+    ));
+    assert_eq!(no_grace.denial_rule, None);
+}
+
+#[tokio::test]
+async fn entries_after_the_enter_by_hour_report_the_rule() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let clock = test_clock();
+    let morning = add_membership_type(&pool, "Morning", 12).await;
+    // The test clock reads 10:00 in Belgrade.
+    sqlx::query("UPDATE membership_types SET enter_by = 10 WHERE id = ?")
+        .bind(morning.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let member = add_member(&pool, "CARD-16", "Vesna").await;
+    add_active_membership(&pool, &clock, member.id, morning.id, 12).await;
+
+    let denied = scan_result(&pool, &settings, &clock, "CARD-16").await;
+    assert!(matches!(denied.status, EntryStatus::DeniedAfterHours));
+    assert_eq!(denied.denial_rule, Some(CheckInRule::EnterByHour));
+}
+
+fn hm(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+async fn set_opening_hours(
+    pool: &SqlitePool,
+    weekday: i64,
+    is_closed: bool,
+    opens_at: Option<NaiveTime>,
+    closes_at: Option<NaiveTime>,
+) {
+    sqlx::query(
+        "INSERT INTO opening_hours (weekday, is_closed, opens_at, closes_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(weekday)
+    .bind(is_closed)
+    .bind(opens_at)
+    .bind(closes_at)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn add_closure(
+    pool: &SqlitePool,
+    date: NaiveDate,
+    hours: Option<(NaiveTime, NaiveTime)>,
+    reason: &str,
+) {
+    sqlx::query("INSERT INTO closures (date, opens_at, closes_at, reason) VALUES (?, ?, ?, ?)")
+        .bind(date)
+        .bind(hours.map(|(opens_at, _)| opens_at))
+        .bind(hours.map(|(_, closes_at)| closes_at))
+        .bind(reason)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+fn day_schedule(opens_at: Option<NaiveTime>, closes_at: Option<NaiveTime>) -> DaySchedule {
+    DaySchedule {
+        date: NaiveDate::from_ymd_opt(2025, 3, 12).unwrap(),
+        weekday: 2,
+        is_closed: false,
+        opens_at,
+        closes_at,
+        closure_reason: None,
+        is_special_day: false,
+    }
+}
+
+#[test]
+fn day_schedules_tell_when_the_gym_is_open() {
+    let regular = day_schedule(Some(hm(6, 0)), Some(hm(22, 0)));
+    assert!(!regular.closes_after_midnight());
+    assert!(regular.is_open_at(hm(6, 0)));
+    assert!(regular.is_open_at(hm(21, 59)));
+    assert!(!regular.is_open_at(hm(5, 59)));
+    assert!(!regular.is_open_at(hm(22, 0)));
+    assert!(!regular.is_open_past_midnight_at(hm(1, 0)));
+
+    let all_day = day_schedule(None, None);
+    assert!(all_day.is_open_at(hm(0, 0)));
+    assert!(all_day.is_open_at(hm(23, 59)));
+
+    let late_night = day_schedule(Some(hm(6, 0)), Some(hm(2, 0)));
+    assert!(late_night.closes_after_midnight());
+    assert!(late_night.is_open_at(hm(23, 30)));
+    assert!(!late_night.is_open_at(hm(1, 0)));
+    assert!(!late_night.is_open_at(hm(5, 0)));
+    assert!(late_night.is_open_past_midnight_at(hm(1, 59)));
+    assert!(!late_night.is_open_past_midnight_at(hm(2, 0)));
+
+    let closed = DaySchedule {
+        is_closed: true,
+        ..late_night
+    };
+    assert!(!closed.is_open_at(hm(12, 0)));
+    assert!(!closed.is_open_past_midnight_at(hm(1, 0)));
+
+    assert!(schedule::validate_times(Some(hm(6, 0)), Some(hm(2, 0))).is_ok());
+    assert!(matches!(
+        schedule::validate_times(Some(hm(6, 0)), Some(hm(6, 0))),
+        Err(AppError::Validation(_))
+    ));
+}
+
+#[tokio::test]
+async fn closures_override_the_weekly_hours() {
+    let pool = test_pool().await;
+    let wednesday = NaiveDate::from_ymd_opt(2025, 3, 12).unwrap();
+    set_opening_hours(&pool, 2, false, Some(hm(6, 0)), Some(hm(22, 0))).await;
+    add_closure(&pool, wednesday + Duration::days(7), None, "Holiday").await;
+    add_closure(
+        &pool,
+        wednesday + Duration::days(14),
+        Some((hm(8, 0), hm(12, 0))),
+        "Short day",
+    )
+    .await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let regular = schedule::day_schedule(&mut conn, wednesday).await.unwrap();
+    assert_eq!((regular.weekday, regular.is_special_day), (2, false));
+    assert_eq!(
+        (regular.opens_at, regular.closes_at),
+        (Some(hm(6, 0)), Some(hm(22, 0)))
+    );
+
+    let holiday = schedule::day_schedule(&mut conn, wednesday + Duration::days(7))
+        .await
+        .unwrap();
+    assert!(holiday.is_closed && holiday.is_special_day);
+    assert_eq!(holiday.closure_reason.as_deref(), Some("Holiday"));
+
+    let short_day = schedule::day_schedule(&mut conn, wednesday + Duration::days(14))
+        .await
+        .unwrap();
+    assert!(!short_day.is_closed && short_day.is_special_day);
+    assert!(short_day.is_open_at(hm(11, 0)));
+    assert!(!short_day.is_open_at(hm(13, 0)));
+
+    // Weekdays without hours are open all day.
+    let thursday = schedule::day_schedule(&mut conn, wednesday + Duration::days(1))
+        .await
+        .unwrap();
+    assert!(!thursday.is_closed && thursday.opens_at.is_none());
+}
+
+#[tokio::test]
+async fn todays_schedule_follows_the_gym_date() {
+    let pool = test_pool().await;
+    // 23:30 UTC on Wednesday is already Thursday in Belgrade.
+    let clock = GymClock::new(
+        Arc::new(FixedTime::new(
+            Utc.with_ymd_and_hms(2025, 3, 12, 23, 30, 0).unwrap(),
+        )),
+        chrono_tz::Europe::Belgrade,
+    );
+    let thursday = NaiveDate::from_ymd_opt(2025, 3, 13).unwrap();
+    add_closure(&pool, thursday, None, "Inventory").await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let today = schedule::today_schedule(&mut conn, &clock).await.unwrap();
+    assert_eq!(today.date, thursday);
+    assert_eq!(today.weekday, 3);
+    assert!(today.is_closed);
+    assert_eq!(today.closure_reason.as_deref(), Some("Inventory"));
+}
+
+#[tokio::test]
+async fn scans_are_refused_while_the_gym_is_closed() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-20", "Jovan").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    add_closure(&pool, clock.today(), None, "Holiday").await;
+
+    let denied = scan_result(&pool, &settings, &clock, "CARD-20").await;
+    assert!(matches!(denied.status, EntryStatus::DeniedGymClosed));
+    assert_eq!(denied.denial_rule, Some(CheckInRule::OpeningHours));
+    assert_eq!(denied.message, "gym_closed|Holiday");
+
+    let single = |card_id: Option<&str>, first_name: Option<&str>| ScanPayloadSingle {
+        card_id: card_id.map(str::to_string),
+        first_name: first_name.map(str::to_string),
+        last_name: None,
+    };
+    for payload in [single(Some("CARD-20"), None), single(None, Some("Guest"))] {
+        let denied = scanning::process_scan_single(&pool, &clock, &Actor::system(), None, payload)
+            .await
+            .unwrap();
+        assert!(matches!(denied.status, EntryStatus::DeniedGymClosed));
+        assert_eq!(denied.denial_rule, Some(CheckInRule::OpeningHours));
+    }
+
+    // Denials are logged and cost no visit.
+    assert_eq!(
+        entry_log_statuses(&pool, member.id).await,
+        vec![EntryLogStatus::DeniedGymClosed; 2]
+    );
+    let logged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entry_logs WHERE status = ?")
+        .bind(EntryLogStatus::DeniedGymClosed)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(logged, 3);
+    let remaining: i64 =
+        sqlx::query_scalar("SELECT remaining_visits FROM memberships WHERE member_id = ?")
+            .bind(member.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 12);
+}
+
+#[tokio::test]
+async fn late_opening_hours_admit_past_midnight() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let (clock, time) = movable_test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-21", "Dragan").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    // Tuesday runs until 02:00 on Wednesday, which is closed.
+    set_opening_hours(&pool, 1, false, Some(hm(6, 0)), Some(hm(2, 0))).await;
+    set_opening_hours(&pool, 2, true, None, None).await;
+
+    // 00:30 on Wednesday in Belgrade.
+    time.set(Utc.with_ymd_and_hms(2025, 3, 11, 23, 30, 0).unwrap());
+    assert!(matches!(
+        scan(&pool, &settings, &clock, "CARD-21").await,
+        EntryStatus::Allowed
+    ));
+    // 02:30 on Wednesday.
+    time.advance(Duration::hours(2));
+    let denied = scan_result(&pool, &settings, &clock, "CARD-21").await;
+    assert!(matches!(denied.status, EntryStatus::DeniedGymClosed));
+    assert_eq!(denied.message, "gym_closed");
+}
+
+#[tokio::test]
+async fn analytics_count_active_memberships_by_type() {
+    let pool = test_pool().await;
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let weekly = add_membership_type(&pool, "Weekly", 4).await;
+    for (card_id, type_id) in [("A", monthly.id), ("B", monthly.id), ("C", weekly.id)] {
+        let member = add_member(&pool, card_id, card_id).await;
+        add_active_membership(&pool, &clock, member.id, type_id, 4).await;
+    }
+
+    let distribution = analytics::get_membership_type_distribution(&pool)
+        .await
+        .unwrap();
+    let counts: Vec<(String, i64)> = distribution
+        .into_iter()
+        .map(|item| (item.membership_type_name, item.active_member_count))
+        .collect();
+    assert_eq!(
+        counts,
+        vec![("Monthly".to_string(), 2), ("Weekly".to_string(), 1)]
+    );
+}
+
+#[tokio::test]
+async fn expired_memberships_are_marked_by_the_status_update() {
+    let pool = test_pool().await;
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-3", "Marko").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+
+    let in_two_months = clock.today() + Duration::days(60);
+    let updated = memberships::update_membership_statuses(&pool, in_two_months)
+        .await
+        .unwrap();
+    assert_eq!(updated, 1);
+    let status: String = sqlx::query_scalar("SELECT status FROM memberships WHERE member_id = ?")
+        .bind(member.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "expired");
+}
+
+#[tokio::test]
+async fn statuses_are_typed_and_checked() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    let member = add_member(&pool, "CARD-6", "Nikola").await;
+    add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    scan(&pool, &settings, &clock, "CARD-6").await;
+
+    let listed = members::get_members_with_memberships_paginated(
+        &pool,
+        GetMembersPaginatedPayload {
+            page: None,
+            per_page: None,
+            order_by: None,
+            order_direction: None,
+            search_string: None,
+            filter_fields: Some(vec![FilterField {
+                field: "membership_status".to_string(),
+                value: "active, pending".to_string(),
+            }]),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(listed.total, 1);
+    assert_eq!(
+        listed.data[0].membership_status,
+        Some(MembershipStatus::Active)
+    );
+
+    let injected = members::get_members_with_memberships_paginated(
+        &pool,
+        GetMembersPaginatedPayload {
+            page: None,
+            per_page: None,
+            order_by: None,
+            order_direction: None,
+            search_string: None,
+            filter_fields: Some(vec![FilterField {
+                field: "membership_status".to_string(),
+                value: "active') OR ('1' = '1".to_string(),
+            }]),
+        },
+    )
+    .await;
+    assert!(matches!(injected, Err(AppError::Validation(_))));
+
+    let logs = entry_logs::get_member_entry_logs(&pool, member.id, None)
+        .await
+        .unwrap();
+    assert_eq!(logs[0].status, Some(EntryLogStatus::Allowed));
+
+    let unknown = sqlx::query("UPDATE entry_logs SET status = 'let_in' WHERE member_id = ?")
+        .bind(member.id)
+        .execute(&pool)
+        .await;
+    assert!(unknown.is_err());
+}
+
+#[tokio::test]
+async fn snapshots_are_sound_databases() {
+    let pool = test_pool().await;
+    add_member(&pool, "CARD-4", "Jelena").await;
+
+    let dir = std::env::temp_dir().join(format!("gym-manager-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let snapshot_path = dir.join("snapshot.sqlite");
+    backup::snapshot(&pool, &snapshot_path).await.unwrap();
+    backup::check_db_integrity(&snapshot_path).await.unwrap();
+    let snapshot = std::fs::read(&snapshot_path).unwrap();
+
+    let manifest = backup_manifest::build(&snapshot_path, &test_clock())
+        .await
+        .unwrap();
+    assert_eq!(manifest.size_bytes, snapshot.len() as u64);
+    assert_eq!(manifest.row_counts["members"], 1);
+    assert_eq!(manifest.row_counts["memberships"], 0);
+    assert!(manifest.migration_version >= 20250703100000);
+    assert_eq!(manifest.app_version, env!("CARGO_PKG_VERSION"));
+    // 10:00 in Belgrade, at the test clock's 09:00 UTC.
+    assert_eq!(
+        manifest.created_at.to_rfc3339(),
+        "2025-03-12T10:00:00+01:00"
+    );
+    assert_eq!(manifest.timezone, "Europe/Belgrade");
+
+    // Packed backups are compressed, and unpack to the snapshot again.
+    let packed_path = dir.join("snapshot.pack");
+    let size = backup::pack(&snapshot_path, &packed_path, &manifest, None)
+        .await
+        .unwrap();
+    assert!(size < snapshot.len() as u64);
+    let unpacked_path = dir.join("unpacked.sqlite");
+    let unpacked = backup::unpack(&packed_path, &unpacked_path, None)
+        .await
+        .unwrap();
+    assert_eq!(unpacked, Some(manifest));
+    assert_eq!(std::fs::read(&unpacked_path).unwrap(), snapshot);
+
+    // Plain copies made before backups were packed still restore.
+    let unpacked = backup::unpack(&snapshot_path, &unpacked_path, None)
+        .await
+        .unwrap();
+    assert_eq!(unpacked, None);
+    assert_eq!(std::fs::read(&unpacked_path).unwrap(), snapshot);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn backups_that_differ_from_their_manifest_are_refused() {
+    let pool = test_pool().await;
+    add_member(&pool, "CARD-5", "Milica").await;
+    let dir = std::env::temp_dir().join(format!("gym-manager-manifest-{}", std::process::id()));
+    let store = backup_store::DirectoryStore::new(dir.join("store"));
+    std::fs::create_dir_all(dir.join("store")).unwrap();
+    let progress = backup_store::ProgressReporter::none();
+    let taken_at = NaiveDate::from_ymd_opt(2025, 3, 12)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+
+    let snapshot_path = dir.join("snapshot.sqlite");
+    let packed_path = dir.join("backup.pack");
+    let manifest = backup::prepare_backup(&pool, &snapshot_path, &packed_path, None, &test_clock())
+        .await
+        .unwrap();
+    assert!(!snapshot_path.exists());
+    let version_id = backup::upload_verified(&store, &packed_path, taken_at, &progress)
+        .await
+        .unwrap();
+    assert_eq!(version_id, "gym-backup-20250312-100000.sqlite");
+    let restored = dir.join("restored.sqlite");
+    let fetched = backup::fetch_backup(&store, None, &restored, None, &progress)
+        .await
+        .unwrap();
+    assert_eq!(fetched, Some(manifest.clone()));
+
+    // A store that doesn't hold what was uploaded fails the verification.
+    std::fs::write(dir.join("store").join(&version_id), "something else").unwrap();
+    let err = backup_manifest::verify_upload(&store, &version_id, &packed_path, &progress)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::BACKUP_VERIFICATION_FAILED);
+
+    // Packed with manifests that don't describe the database.
+    let wrong_checksum = BackupManifest {
+        sha256: "0".repeat(64),
+        ..manifest.clone()
+    };
+    let mut wrong_counts = manifest.clone();
+    *wrong_counts.row_counts.get_mut("members").unwrap() += 1;
+    let newer = BackupManifest {
+        migration_version: manifest.migration_version + 1,
+        ..manifest.clone()
+    };
+    for (wrong, expected) in [
+        (wrong_checksum, ErrorCodes::BACKUP_MANIFEST_MISMATCH),
+        (wrong_counts, ErrorCodes::BACKUP_MANIFEST_MISMATCH),
+        (newer, ErrorCodes::BACKUP_FROM_NEWER_VERSION),
+    ] {
+        backup::pack(&restored, &packed_path, &wrong, None)
+            .await
+            .unwrap();
+        std::fs::copy(&packed_path, dir.join("store").join(&version_id)).unwrap();
+        let dest = dir.join("refused.sqlite");
+        let err = backup::fetch_backup(&store, None, &dest, None, &progress)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), expected);
+        assert!(!dest.exists());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn restored_databases_reopen_in_place() {
+    let dir = std::env::temp_dir().join(format!("gym-manager-restore-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("store")).unwrap();
+    let store = backup_store::DirectoryStore::new(dir.join("store"));
+    let progress = backup_store::ProgressReporter::none();
+    let db_path = dir.join("gym.sqlite");
+    let pool = db::init_db(&db_path).await.unwrap();
+    add_member(&pool, "CARD-6", "Nikola").await;
+
+    let packed_path = dir.join("backup.pack");
+    let manifest = backup::prepare_backup(
+        &pool,
+        &dir.join("snapshot.sqlite"),
+        &packed_path,
+        None,
+        &test_clock(),
+    )
+    .await
+    .unwrap();
+    backup::upload_verified(
+        &store,
+        &packed_path,
+        manifest.created_at.naive_local(),
+        &progress,
+    )
+    .await
+    .unwrap();
+    add_member(&pool, "CARD-7", "Ana").await;
+
+    let restored = backup::download_path(&db_path);
+    let fetched = backup::fetch_backup(&store, None, &restored, None, &progress)
+        .await
+        .unwrap();
+    pool.close().await;
+    backup::replace_database(
+        &restored,
+        &db_path,
+        &AppSettings::default(),
+        None,
+        &Actor::system(),
+        serde_json::json!({ "manifest": fetched }),
+    )
+    .await
+    .unwrap();
+
+    let pool = db::init_db(&db_path).await.unwrap();
+    let members: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM members")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(members, 1);
+    // The audit trail of the replaced database is kept, the restore on top.
+    let actions: Vec<String> = sqlx::query_scalar(
+        "SELECT action FROM audit_log WHERE entity_type IN ('member', 'database') ORDER BY id",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(actions, vec!["create", "create", "restore"]);
+    pool.close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_failed_reopen_leaves_a_working_pool() {
+    let dir = std::env::temp_dir().join(format!("gym-manager-reopen-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db_path = dir.join("gym.sqlite");
+    let pool = db::init_db(&db_path).await.unwrap();
+    add_member(&pool, "CARD-8", "Milan").await;
+    // A migration this version doesn't know, as in a backup from a newer one,
+    // makes migrating fail.
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (99990101000000, 'from a newer version', TRUE, x'00', 0)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let profile = Profile {
+        id: "default".to_string(),
+        name: "Gym".to_string(),
+        database_file: "gym.sqlite".to_string(),
+        database_dir: Some(dir.clone()),
+        created_at: Utc::now().naive_utc(),
+    };
+    let state = AppState::new(pool, AppSettings::default(), profile, db_path.clone());
+    state.db_pool().close().await;
+
+    assert!(matches!(
+        state.reopen_database(db_path.clone()).await,
+        Err(AppError::Migrate(_))
+    ));
+    let members: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM members")
+        .fetch_one(&state.db_pool())
+        .await
+        .unwrap();
+    assert_eq!(members, 1);
+    state.db_pool().close().await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn local_backups_keep_the_latest_daily_and_weekly_copies() {
+    let dir = std::env::temp_dir().join(format!("gym-manager-local-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a backup").unwrap();
+    let settings = AppSettings {
+        local_backup: LocalBackupSettings {
+            directory: Some(dir.to_string_lossy().into_owned()),
+            keep_daily: 3,
+            keep_weekly: 2,
+        },
+        ..AppSettings::default()
+    };
+    let local = backup_store::DirectoryStore::local_backup(&settings).unwrap();
+    let source =
+        std::env::temp_dir().join(format!("gym-manager-local-{}.pack", std::process::id()));
+    std::fs::write(&source, "snapshot").unwrap();
+    let progress = backup_store::ProgressReporter::none();
+
+    // A copy every night from Saturday 1 March to Thursday 20 March, and one
+    // more on the afternoon of the last day.
+    let first_night = NaiveDate::from_ymd_opt(2025, 3, 1)
+        .unwrap()
+        .and_hms_opt(2, 0, 0)
+        .unwrap();
+    for day in 0..20 {
+        local
+            .store(&source, first_night + Duration::days(day), &progress)
+            .await
+            .unwrap();
+    }
+    local
+        .store(
+            &source,
+            first_night + Duration::days(19) + Duration::hours(12),
+            &progress,
+        )
+        .await
+        .unwrap();
+
+    let status = local.status().await.unwrap();
+    assert!(status.free_bytes > 0);
+    let kept: Vec<String> = status.copies.into_iter().map(|c| c.file_name).collect();
+    assert_eq!(
+        kept,
+        vec![
+            "gym-backup-20250320-140000.sqlite",
+            "gym-backup-20250319-020000.sqlite",
+            "gym-backup-20250318-020000.sqlite",
+            // Newest copy of the week before.
+            "gym-backup-20250316-020000.sqlite",
+        ]
+    );
+    assert!(dir.join("notes.txt").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let err = local
+        .store(&source, first_night, &progress)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::LOCAL_BACKUP_DIR_UNAVAILABLE);
+    std::fs::remove_file(&source).unwrap();
+}
+
+/// Uploads two snapshots to `store`, which must not hold other backups,
+/// and reads, restores and deletes them again.
+async fn exercise_backup_store(store: &dyn backup_store::BackupStore) {
+    let pool = test_pool().await;
+    let work_dir = std::env::temp_dir().join(format!(
+        "gym-manager-store-work-{}-{}",
+        std::process::id(),
+        Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    ));
+    std::fs::create_dir_all(&work_dir).unwrap();
+    let snapshot_path = work_dir.join("snapshot.sqlite");
+    backup::snapshot(&pool, &snapshot_path).await.unwrap();
+    let snapshot = std::fs::read(&snapshot_path).unwrap();
+    let older_path = work_dir.join("older.pack");
+    std::fs::write(&older_path, "older").unwrap();
+    let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let progress = {
+        let reports = reports.clone();
+        backup_store::ProgressReporter::new(move |report| reports.lock().unwrap().push(report))
+    };
+    let last_report = || reports.lock().unwrap().last().cloned().unwrap();
+
+    let taken_at = NaiveDate::from_ymd_opt(2025, 3, 12)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    let older_id = store
+        .upload(&older_path, taken_at, &progress)
+        .await
+        .unwrap();
+    let latest_id = store
+        .upload(&snapshot_path, taken_at + Duration::hours(1), &progress)
+        .await
+        .unwrap();
+    assert_eq!(older_id, "gym-backup-20250312-100000.sqlite");
+    assert_eq!(latest_id, "gym-backup-20250312-110000.sqlite");
+    let report = last_report();
+    assert_eq!(report.direction, TransferDirection::Upload);
+    assert_eq!(report.transferred_bytes, snapshot.len() as u64);
+    assert_eq!(report.total_bytes, Some(snapshot.len() as u64));
+
+    let backups = store.list().await.unwrap();
+    let ids: Vec<&str> = backups.iter().map(|b| b.version_id.as_str()).collect();
+    assert_eq!(
+        ids,
+        vec![
+            "gym-backup-20250312-110000.sqlite",
+            "gym-backup-20250312-100000.sqlite",
+        ]
+    );
+    assert!(backups[0].is_latest && !backups[1].is_latest);
+    assert_eq!(backups[0].size_bytes, snapshot.len() as u64);
+
+    let latest = work_dir.join("latest.sqlite");
+    store.download(None, &latest, &progress).await.unwrap();
+    assert_eq!(std::fs::read(&latest).unwrap(), snapshot);
+    let report = last_report();
+    assert_eq!(report.direction, TransferDirection::Download);
+    assert_eq!(report.transferred_bytes, snapshot.len() as u64);
+    backup::check_db_integrity(&latest).await.unwrap();
+    let older = work_dir.join("older.sqlite");
+    store
+        .download(
+            Some("gym-backup-20250312-100000.sqlite"),
+            &older,
+            &backup_store::ProgressReporter::none(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&older).unwrap(), b"older");
+
+    // Only names of backups are accepted, so nothing outside the store can
+    // be reached.
+    assert!(store.delete("../gym.sqlite").await.is_err());
+    for backup in backups {
+        store.delete(&backup.version_id).await.unwrap();
+    }
+    assert!(store.list().await.unwrap().is_empty());
+    std::fs::remove_dir_all(&work_dir).unwrap();
+}
+
+#[test]
+fn backup_store_secrets_are_never_shown() {
+    let s3 = S3StoreSettings {
+        endpoint: "http://localhost:9000".to_string(),
+        region: "us-east-1".to_string(),
+        bucket: "gym".to_string(),
+        prefix: String::new(),
+        access_key_id: "AKIA".to_string(),
+        secret_access_key: "s3-secret".to_string(),
+        virtual_hosted_style: false,
+    };
+    let settings = AppSettings {
+        backup_store: BackupStoreSettings::S3(s3.clone()),
+        ..AppSettings::default()
+    };
+    let view = serde_json::to_string(&AppSettingsView::from(&settings)).unwrap();
+    assert!(!view.contains("s3-secret"));
+    assert!(view.contains(r#""backup_store_has_secret":true"#));
+    assert!(!format!("{:?}", settings).contains("s3-secret"));
+
+    // Sent back blank, the stored secret is kept; a new one replaces it.
+    let mut update = BackupStoreSettings::S3(S3StoreSettings {
+        secret_access_key: String::new(),
+        ..s3.clone()
+    });
+    update.keep_secrets_from(&settings.backup_store);
+    assert!(matches!(&update, BackupStoreSettings::S3(s) if s.secret_access_key == "s3-secret"));
+    let mut update = BackupStoreSettings::S3(S3StoreSettings {
+        secret_access_key: "rotated".to_string(),
+        ..s3
+    });
+    update.keep_secrets_from(&settings.backup_store);
+    assert!(matches!(&update, BackupStoreSettings::S3(s) if s.secret_access_key == "rotated"));
+
+    let webdav = BackupStoreSettings::WebDav(WebDavStoreSettings {
+        url: "https://cloud.example.com/dav/".to_string(),
+        username: Some("gym".to_string()),
+        password: Some("dav-secret".to_string()),
+    });
+    assert!(!format!("{:?}", webdav).contains("dav-secret"));
+    let mut update = webdav.without_secrets();
+    assert!(!update.has_secret());
+    update.keep_secrets_from(&webdav);
+    assert!(update.has_secret());
+
+    let gateway = AppSettings {
+        backup_store: BackupStoreSettings::Http {
+            url: "https://backup.example.com/api?region=eu&token=gw-secret".to_string(),
+        },
+        ..AppSettings::default()
+    };
+    assert!(gateway.backup_store.has_secret());
+    assert!(!format!("{:?}", gateway).contains("gw-secret"));
+    let view = serde_json::to_string(&AppSettingsView::from(&gateway)).unwrap();
+    assert!(!view.contains("gw-secret"));
+    assert!(view.contains("region=eu"));
+    let mut update = gateway.backup_store.without_secrets();
+    assert!(!update.has_secret());
+    update.keep_secrets_from(&gateway.backup_store);
+    let BackupStoreSettings::Http { url } = &update else {
+        panic!("expected the gateway settings, got {:?}", update);
+    };
+    assert_eq!(
+        parse_backup_url(url).unwrap(),
+        (
+            "https://backup.example.com/api".to_string(),
+            "gw-secret".to_string()
+        )
+    );
+}
+
+#[tokio::test]
+async fn directory_store_round_trips_backups() {
+    let dir = std::env::temp_dir().join(format!("gym-manager-store-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let settings = AppSettings {
+        backup_store: BackupStoreSettings::Directory {
+            path: dir.to_string_lossy().into_owned(),
+        },
+        ..AppSettings::default()
+    };
+    let store = backup_store::configured(&settings).unwrap();
+    exercise_backup_store(store.as_ref()).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let relative = BackupStoreSettings::Directory {
+        path: "backups".to_string(),
+    };
+    assert!(backup_store::open(&relative, "GYM").is_err());
+    let err = backup_store::configured(&AppSettings::default()).unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::BACKUP_NOT_CONFIGURED);
+}
+
+/// Needs an S3 compatible server, e.g.
+/// `docker run -p 9000:9000 minio/minio server /data` with a bucket created,
+/// and `GYM_TEST_S3_ENDPOINT`, `GYM_TEST_S3_BUCKET`, `GYM_TEST_S3_ACCESS_KEY`
+/// and `GYM_TEST_S3_SECRET_KEY` set.
+#[tokio::test]
+#[ignore]
+async fn s3_store_round_trips_backups() {
+    let env = |name: &str| std::env::var(name).unwrap_or_else(|_| panic!("{} is not set", name));
+    let settings = S3StoreSettings {
+        endpoint: env("GYM_TEST_S3_ENDPOINT"),
+        region: std::env::var("GYM_TEST_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+        bucket: env("GYM_TEST_S3_BUCKET"),
+        prefix: format!("test-{}/", std::process::id()),
+        access_key_id: env("GYM_TEST_S3_ACCESS_KEY"),
+        secret_access_key: env("GYM_TEST_S3_SECRET_KEY"),
+        virtual_hosted_style: false,
+    };
+    let store = backup_store::S3Store::new(&settings).unwrap();
+    exercise_backup_store(&store).await;
+}
+
+#[tokio::test]
+async fn encrypted_backups_need_the_passphrase_or_recovery_key() {
+    use backup_encryption::{BackupKey, BackupSecret};
+
+    let pool = test_pool().await;
+    assert!(BackupKey::generate("too short").is_err());
+    let key = BackupKey::generate("correct horse battery").unwrap();
+    backup_encryption::save_key(&pool, &key).await.unwrap();
+    let stored = backup_encryption::load_key(&pool).await.unwrap().unwrap();
+    assert_eq!(stored.key_id(), key.key_id());
+    assert!(backup_encryption::status(&pool).await.unwrap().enabled);
+
+    let dir = std::env::temp_dir().join(format!("gym-manager-encrypted-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let snapshot_path = dir.join("snapshot.sqlite");
+    let packed_path = dir.join("snapshot.pack");
+    backup::snapshot(&pool, &snapshot_path).await.unwrap();
+    let snapshot = std::fs::read(&snapshot_path).unwrap();
+    let manifest = backup_manifest::build(&snapshot_path, &test_clock())
+        .await
+        .unwrap();
+    backup::pack(&snapshot_path, &packed_path, &manifest, Some(&key))
+        .await
+        .unwrap();
+    let encrypted = std::fs::read(&packed_path).unwrap();
+    assert!(backup_encryption::is_encrypted(&encrypted));
+
+    let store = backup_store::DirectoryStore::new(dir.join("store"));
+    std::fs::create_dir_all(dir.join("store")).unwrap();
+    let taken_at = NaiveDate::from_ymd_opt(2025, 3, 12)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    let progress = backup_store::ProgressReporter::none();
+    backup_store::BackupStore::upload(&store, &packed_path, taken_at, &progress)
+        .await
+        .unwrap();
+
+    let restored = dir.join("restored.sqlite");
+    let err = backup::fetch_backup(&store, None, &restored, None, &progress)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::BACKUP_PASSPHRASE_REQUIRED);
+    assert!(!restored.exists());
+    let err = backup::fetch_backup(
+        &store,
+        None,
+        &restored,
+        Some(&BackupSecret::Passphrase("wrong horse battery".to_string())),
+        &progress,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::WRONG_BACKUP_PASSPHRASE);
+    let other = BackupKey::generate("another passphrase")
+        .unwrap()
+        .recovery_key();
+    let err = backup::fetch_backup(
+        &store,
+        None,
+        &restored,
+        Some(&BackupSecret::RecoveryKey(other)),
+        &progress,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::INVALID_RECOVERY_KEY);
+
+    backup::fetch_backup(
+        &store,
+        None,
+        &restored,
+        Some(&BackupSecret::Passphrase(
+            "correct horse battery".to_string(),
+        )),
+        &progress,
+    )
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(&restored).unwrap(), snapshot);
+    // Typed in by hand, in any case and with spaces instead of dashes.
+    let typed = key.recovery_key().to_lowercase().replace('-', " ");
+    backup::fetch_backup(
+        &store,
+        None,
+        &restored,
+        Some(&BackupSecret::RecoveryKey(typed.clone())),
+        &progress,
+    )
+    .await
+    .unwrap();
+    assert_eq!(std::fs::read(&restored).unwrap(), snapshot);
+
+    // A new passphrase keeps the key, and older backups keep their old one.
+    let rewrapped = key.rewrap("a new passphrase here").unwrap();
+    assert_eq!(rewrapped.key_id(), key.key_id());
+    assert_eq!(rewrapped.recovery_key(), key.recovery_key());
+    assert!(rewrapped
+        .verify_passphrase("correct horse battery")
+        .is_err());
+    backup_encryption::decrypt(
+        &encrypted,
+        &BackupSecret::Passphrase("correct horse battery".to_string()),
+    )
+    .unwrap();
+
+    // Chunk boundaries and tampering.
+    let large: Vec<u8> = (0..128 * 1024).map(|i| (i % 251) as u8).collect();
+    let secret = BackupSecret::RecoveryKey(typed);
+    let sealed = key.encrypt(&large).unwrap();
+    assert_eq!(backup_encryption::decrypt(&sealed, &secret).unwrap(), large);
+    let mut tampered = sealed.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(matches!(
+        backup_encryption::decrypt(&tampered, &secret),
+        Err(AppError::RestoreFailed(_))
+    ));
+    assert!(backup_encryption::decrypt(&sealed[..sealed.len() - 100], &secret).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn revenue_is_summed_in_minor_units() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let clock = test_clock();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+    for card_id in ["D", "E", "F"] {
+        let member = add_member(&pool, card_id, card_id).await;
+        add_active_membership(&pool, &clock, member.id, monthly.id, 12).await;
+    }
+
+    let today = clock.today();
+    let revenue = analytics::get_revenue_by_membership_type(
+        &pool,
+        &settings,
+        &clock,
+        today - Duration::days(1),
+        today + Duration::days(1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(revenue.len(), 1);
+    assert_eq!(revenue[0].total_revenue_minor, 12_000);
+    assert_eq!(revenue[0].count, 3);
+    assert_eq!(revenue[0].currency, "EUR");
+    assert_eq!(settings.currency.format(12_000), "€120.00");
+}
+
+#[tokio::test]
+async fn prices_follow_a_change_of_minor_unit() {
+    let pool = test_pool().await;
+    let settings = AppSettings::default();
+    let monthly = add_membership_type(&pool, "Monthly", 12).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    membership_types::rescale_prices(&mut conn, 2, 0)
+        .await
+        .unwrap();
+    drop(conn);
+    let rescaled = membership_types::get_membership_type_by_id(&pool, &settings, monthly.id)
+        .await
+        .unwrap();
+    assert_eq!(rescaled.price_minor, 40);
+}
+
+#[test]
+fn amounts_are_formatted_with_the_currency_settings() {
+    let mut currency = CurrencySettings::default();
+    assert_eq!(currency.format(123_456_789), "€1,234,567.89");
+    assert_eq!(currency.format(-5), "-€0.05");
+
+    currency.symbol = "RSD".to_string();
+    currency.symbol_position = CurrencySymbolPosition::After;
+    currency.minor_unit_digits = 0;
+    currency.thousands_separator = ".".to_string();
+    assert_eq!(currency.format(2_500), "2.500 RSD");
+}
+
+/// The RFC 6238 test secret `12345678901234567890`, base32 encoded.
+const RFC_6238_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+#[test]
+fn totp_codes_match_the_rfc_6238_vectors() {
+    // The RFC lists 8 digit codes; these are their last 6 digits.
+    for (unix_time, code) in [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ] {
+        assert_eq!(totp::code_at(RFC_6238_SECRET, unix_time).unwrap(), code);
+        assert_eq!(
+            totp::verify(RFC_6238_SECRET, code, unix_time).unwrap(),
+            Some(totp::step_at(unix_time))
+        );
+    }
+    assert_eq!(
+        totp::verify(RFC_6238_SECRET, " 287 082 ", 59).unwrap(),
+        Some(1)
+    );
+    assert_eq!(totp::verify(RFC_6238_SECRET, "28708", 59).unwrap(), None);
+    assert!(totp::code_at("not base32!", 59).is_err());
+}
+
+#[test]
+fn totp_accepts_one_step_of_drift_and_reports_the_step() {
+    // Step 1 (seconds 30 to 59) shows 287082, step 2 shows 359152.
+    assert_eq!(
+        totp::verify(RFC_6238_SECRET, "287082", 60).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        totp::verify(RFC_6238_SECRET, "287082", 89).unwrap(),
+        Some(1)
+    );
+    assert_eq!(totp::verify(RFC_6238_SECRET, "287082", 90).unwrap(), None);
+    assert_eq!(
+        totp::verify(RFC_6238_SECRET, "359152", 59).unwrap(),
+        Some(2)
+    );
+    assert_eq!(totp::verify(RFC_6238_SECRET, "359152", 29).unwrap(), None);
+
+    // A late code reports its own step, not the current one, so a replay of
+    // it in the next step is still recognised as used.
+    assert_eq!(
+        totp::verify(RFC_6238_SECRET, "081804", 1111111111).unwrap(),
+        Some(1111111109 / totp::STEP_SECONDS)
+    );
+}
+
+#[tokio::test]
+async fn repeated_lockouts_double_up_to_the_maximum() {
+    let pool = test_pool().await;
+    let policy = LoginLockoutSettings {
+        max_failed_attempts: 3,
+        base_lockout_minutes: 5,
+        max_lockout_minutes: 15,
+    };
+
+    for minutes in [5, 10, 15, 15] {
+        for _ in 0..2 {
+            assert_eq!(
+                auth::register_failed_login(&pool, "petar", &policy)
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        let before = Utc::now().naive_utc();
+        let locked_until = auth::register_failed_login(&pool, "petar", &policy)
+            .await
+            .unwrap()
+            .expect("the third failure locks the account");
+        let after = Utc::now().naive_utc();
+        assert!(locked_until >= before + Duration::minutes(minutes));
+        assert!(locked_until <= after + Duration::minutes(minutes));
+        assert_eq!(
+            auth::get_active_lockout(&pool, "petar").await.unwrap(),
+            Some(locked_until)
+        );
+    }
+    assert_eq!(auth::get_active_lockout(&pool, "ana").await.unwrap(), None);
+
+    // A lockout that ran out no longer blocks.
+    sqlx::query("UPDATE login_lockouts SET locked_until = ? WHERE username = 'petar'")
+        .bind(Utc::now().naive_utc() - Duration::minutes(1))
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        auth::get_active_lockout(&pool, "petar").await.unwrap(),
+        None
+    );
+
+    // A successful login starts the backoff over.
+    assert!(auth::clear_failed_logins(&pool, "petar").await.unwrap());
+    assert!(!auth::clear_failed_logins(&pool, "petar").await.unwrap());
+    for _ in 0..2 {
+        auth::register_failed_login(&pool, "petar", &policy)
+            .await
+            .unwrap();
+    }
+    let before = Utc::now().naive_utc();
+    let locked_until = auth::register_failed_login(&pool, "petar", &policy)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(locked_until < before + Duration::minutes(6));
+}
+
+#[test]
+fn passwords_must_follow_the_policy() {
+    let policy = PasswordPolicySettings {
+        min_length: 10,
+        require_uppercase: true,
+        require_lowercase: true,
+        require_digit: true,
+        require_symbol: true,
+        history_size: 3,
+    };
+    let broken_rule = |password: &str| match utils::validate_password(password, &policy) {
+        Ok(()) => None,
+        Err(AppError::Translatable(e)) => {
+            assert_eq!(e.error_code, ErrorCodes::PASSWORD_POLICY_VIOLATION);
+            Some(e.params.unwrap()["rule"].as_str().unwrap().to_string())
+        }
+        Err(other) => panic!("expected a policy violation, got {:?}", other),
+    };
+
+    assert_eq!(broken_rule("Sh0rt!pw").as_deref(), Some("min_length"));
+    // Length counts characters, not bytes.
+    assert_eq!(broken_rule("Šš1!čć").as_deref(), Some("min_length"));
+    assert_eq!(
+        broken_rule("lowercase-only-1").as_deref(),
+        Some("uppercase")
+    );
+    assert_eq!(
+        broken_rule("UPPERCASE-ONLY-1").as_deref(),
+        Some("lowercase")
+    );
+    assert_eq!(broken_rule("No-Digits-Here").as_deref(), Some("digit"));
+    assert_eq!(broken_rule("NoSymbols123").as_deref(), Some("symbol"));
+    assert_eq!(broken_rule("Correct-Horse-1"), None);
+
+    let default_policy = PasswordPolicySettings::default();
+    assert!(utils::validate_password("password1", &default_policy).is_ok());
+    assert!(utils::validate_password("password", &default_policy).is_err());
+}
+
+#[tokio::test]
+async fn recent_passwords_cannot_be_reused() {
+    let pool = test_pool().await;
+    let user_id = add_user(&pool, "jelena", "user", "first-pass-1").await;
+    let first_hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    auth::record_password_history(&pool, user_id, &first_hash)
+        .await
+        .unwrap();
+
+    // Each change goes through the same steps as `change_password`.
+    for password in ["second-pass-2", "third-pass-3", "fourth-pass-4"] {
+        auth::ensure_password_not_reused(&pool, user_id, password, 3)
+            .await
+            .unwrap();
+        let hash = utils::hash_password(password).unwrap();
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(&hash)
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        auth::record_password_history(&pool, user_id, &hash)
+            .await
+            .unwrap();
+    }
+
+    for password in ["fourth-pass-4", "third-pass-3", "second-pass-2"] {
+        let err = auth::ensure_password_not_reused(&pool, user_id, password, 3)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), ErrorCodes::PASSWORD_REUSED);
+    }
+    // The first password has dropped out of the last three.
+    auth::ensure_password_not_reused(&pool, user_id, "first-pass-1", 3)
+        .await
+        .unwrap();
+    assert!(
+        auth::ensure_password_not_reused(&pool, user_id, "first-pass-1", 4)
+            .await
+            .is_err()
+    );
+    // A history size of 0 turns the check off, even for the current password.
+    auth::ensure_password_not_reused(&pool, user_id, "fourth-pass-4", 0)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_current_password_counts_without_any_history() {
+    let pool = test_pool().await;
+    let user_id = add_user(&pool, "stefan", "user", "legacy-pass-1").await;
+
+    let err = auth::ensure_password_not_reused(&pool, user_id, "legacy-pass-1", 3)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::PASSWORD_REUSED);
+    auth::ensure_password_not_reused(&pool, user_id, "fresh-pass-2", 3)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn permissions_follow_the_role_of_the_session() {
+    let state = test_state(test_pool().await);
+    let denied_permission = |err: AppError| match err {
+        AppError::Translatable(e) => {
+            assert_eq!(e.error_code, ErrorCodes::PERMISSION_DENIED);
+            e.params.unwrap()["permission"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        }
+        other => panic!("expected a permission error, got {:?}", other),
+    };
+
+    let err = auth::require_permission(&state, auth::Permission::MembersView)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::NOT_AUTHENTICATED);
+    assert!(auth::require_session(&state).await.is_err());
+
+    log_in(&state, 1, "admin", "admin").await;
+    for permission in auth::Permission::ALL {
+        auth::require_permission(&state, permission).await.unwrap();
+    }
+
+    log_in(&state, 2, "desk", "user").await;
+    for permission in [
+        auth::Permission::MembersView,
+        auth::Permission::MembersDelete,
+        auth::Permission::MembershipsManage,
+        auth::Permission::MembershipTypesView,
+        auth::Permission::EntryLogsScan,
+    ] {
+        auth::require_permission(&state, permission).await.unwrap();
+    }
+    for permission in [
+        auth::Permission::MembershipTypesManage,
+        auth::Permission::EntryLogsView,
+        auth::Permission::EntryLogsDelete,
+        auth::Permission::AnalyticsView,
+        auth::Permission::BackupManage,
+        auth::Permission::BackupRestore,
+        auth::Permission::SettingsManage,
+        auth::Permission::UsersManage,
+        auth::Permission::AuditView,
+    ] {
+        let err = auth::require_permission(&state, permission)
+            .await
+            .unwrap_err();
+        assert_eq!(denied_permission(err), permission.as_str());
+    }
+    // Denials leave the session in place.
+    assert_eq!(
+        auth::require_session(&state).await.unwrap().username,
+        "desk"
+    );
+
+    auth::end_session(&state).await;
+    let err = auth::require_session(&state).await.unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::NOT_AUTHENTICATED);
+}
+
+#[tokio::test]
+async fn custom_roles_grant_what_their_permissions_imply() {
+    let pool = test_pool().await;
+    let state = test_state(pool.clone());
+    let role_id: i64 =
+        sqlx::query_scalar("INSERT INTO roles (name) VALUES ('cleaner') RETURNING id")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    sqlx::query(
+        "INSERT INTO role_permissions (role_id, permission) VALUES (?, 'entry_logs.delete')",
+    )
+    .bind(role_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        auth::load_role_permissions(&pool, "janitor").await.unwrap(),
+        None
+    );
+
+    log_in(&state, 3, "mop", "cleaner").await;
+    auth::require_permission(&state, auth::Permission::EntryLogsDelete)
+        .await
+        .unwrap();
+    auth::require_permission(&state, auth::Permission::EntryLogsView)
+        .await
+        .unwrap();
+    assert!(
+        auth::require_permission(&state, auth::Permission::EntryLogsScan)
+            .await
+            .is_err()
+    );
+
+    // Edited role permissions apply to the live session.
+    auth::refresh_session_permissions(
+        &state,
+        "cleaner",
+        &[auth::Permission::EntryLogsScan].into_iter().collect(),
+    )
+    .await;
+    auth::require_permission(&state, auth::Permission::EntryLogsScan)
+        .await
+        .unwrap();
+    assert!(
+        auth::require_permission(&state, auth::Permission::EntryLogsDelete)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn a_pending_password_change_blocks_everything_else() {
+    let pool = test_pool().await;
+    let state = test_state(pool.clone());
+    let permissions = auth::load_role_permissions(&pool, "admin")
+        .await
+        .unwrap()
+        .unwrap();
+    auth::start_session(&state, 1, "admin", "admin", permissions, true).await;
+
+    let err = auth::require_permission(&state, auth::Permission::MembersView)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::PASSWORD_CHANGE_REQUIRED);
+    let err = auth::require_session(&state).await.unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::PASSWORD_CHANGE_REQUIRED);
+    auth::require_session_for_password_change(&state)
+        .await
+        .unwrap();
+
+    // Another user's password change does not count.
+    auth::mark_password_changed(&state, 2).await;
+    assert!(auth::require_session(&state).await.is_err());
+    auth::mark_password_changed(&state, 1).await;
+    auth::require_permission(&state, auth::Permission::MembersView)
+        .await
+        .unwrap();
+}
+
+#[test]
+fn operator_pins_are_four_to_eight_digits() {
+    for pin in ["1234", "12345678", "0000"] {
+        auth::validate_operator_pin(pin).unwrap();
+    }
+    for pin in ["123", "123456789", "12a4", "12 34", ""] {
+        let err = auth::validate_operator_pin(pin).unwrap_err();
+        assert_eq!(error_code(err), ErrorCodes::INVALID_OPERATOR_PIN_FORMAT);
+    }
+}
+
+#[tokio::test]
+async fn operators_switch_in_with_their_pin() {
+    let pool = test_pool().await;
+    let policy = LoginLockoutSettings {
+        max_failed_attempts: 3,
+        ..LoginLockoutSettings::default()
+    };
+    let marko = add_user(&pool, "marko", "user", "marko-pass-1").await;
+    sqlx::query("UPDATE users SET pin_hash = ? WHERE id = ?")
+        .bind(utils::hash_password("2468").unwrap())
+        .bind(marko)
+        .execute(&pool)
+        .await
+        .unwrap();
+    add_user(&pool, "nopin", "user", "nopin-pass-1").await;
+
+    let operator = auth::verify_operator_pin(&pool, "marko", " 2468 ", &policy)
+        .await
+        .unwrap();
+    assert_eq!(operator.user_id, marko);
+    assert_eq!(operator.username, "marko");
+
+    for (username, pin) in [("marko", "1357"), ("nopin", "2468"), ("nobody", "2468")] {
+        let err = auth::verify_operator_pin(&pool, username, pin, &policy)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), ErrorCodes::INVALID_OPERATOR_PIN);
+    }
+
+    // A right PIN clears earlier misses.
+    auth::verify_operator_pin(&pool, "marko", "2468", &policy)
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        assert!(auth::verify_operator_pin(&pool, "marko", "0000", &policy)
+            .await
+            .is_err());
+    }
+    assert_eq!(
+        auth::get_active_lockout(&pool, "marko").await.unwrap(),
+        None
+    );
+
+    // Wrong PINs lock the account like failed logins, after which even the
+    // right PIN is refused.
+    assert!(auth::verify_operator_pin(&pool, "marko", "0000", &policy)
+        .await
+        .is_err());
+    assert!(auth::get_active_lockout(&pool, "marko")
+        .await
+        .unwrap()
+        .is_some());
+    let err = auth::verify_operator_pin(&pool, "marko", "2468", &policy)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(err), ErrorCodes::ACCOUNT_LOCKED);
+
+    let reasons: Vec<String> = sqlx::query_scalar(
+        "SELECT reason FROM login_attempts WHERE username = 'marko' ORDER BY id",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        reasons,
+        [
+            "operator_switch",
+            "invalid_pin",
+            "operator_switch",
+            "invalid_pin",
+            "invalid_pin",
+            "invalid_pin",
+            "locked_out",
+        ]
+    );
+}
+
+/// Stores raw settings rows, each value being JSON as in the `settings` table.
+async fn store_settings(pool: &SqlitePool, rows: &[(&str, &str)]) {
+    for (key, value) in rows {
+        sqlx::query("INSERT INTO settings (key, value) VALUES (?, ?)")
+            .bind(key)
+            .bind(value)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
+
+async fn stored_setting(pool: &SqlitePool, key: &str) -> String {
+    sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn settings_from_before_versioning_are_upgraded() {
+    let pool = test_pool().await;
+    assert!(config::load_stored_settings(&pool).await.unwrap().is_none());
+    store_settings(
+        &pool,
+        &[
+            (
+                "backup_url",
+                r#""https://backup.example.com/upload?token=abc""#,
+            ),
+            ("gym_name", r#""Iron Temple""#),
+            ("gym_code", r#""IRON000001""#),
+            ("language", r#""sr""#),
+        ],
+    )
+    .await;
+
+    let settings = config::load_stored_settings(&pool).await.unwrap().unwrap();
+    assert_eq!(settings.settings_version, config::CURRENT_SETTINGS_VERSION);
+    assert!(matches!(
+        &settings.backup_store,
+        BackupStoreSettings::Http { url } if url == "https://backup.example.com/upload?token=abc"
+    ));
+    assert_eq!(settings.gym_name, "Iron Temple");
+    assert_eq!(settings.gym_code, "IRON000001");
+    assert_eq!(settings.language, "sr");
+    // Sections added since then get their defaults.
+    assert_eq!(settings.login_lockout.max_failed_attempts, 5);
+    assert_eq!(settings.password_policy.history_size, 3);
+
+    // The upgrade is written back, so it runs once.
+    assert_eq!(
+        stored_setting(&pool, "settings_version").await,
+        config::CURRENT_SETTINGS_VERSION.to_string()
+    );
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&stored_setting(&pool, "backup_store").await)
+            .unwrap(),
+        serde_json::to_value(&settings.backup_store).unwrap()
+    );
+    let reloaded = config::load_stored_settings(&pool).await.unwrap().unwrap();
+    assert_eq!(reloaded.gym_code, "IRON000001");
+}
+
+#[tokio::test]
+async fn an_empty_backup_url_upgrades_to_no_backup_store() {
+    let pool = test_pool().await;
+    store_settings(
+        &pool,
+        &[
+            ("settings_version", "2"),
+            ("backup_url", r#""""#),
+            ("gym_code", r#""IRON000002""#),
+        ],
+    )
+    .await;
+
+    let settings = config::load_stored_settings(&pool).await.unwrap().unwrap();
+    assert!(matches!(
+        settings.backup_store,
+        BackupStoreSettings::Disabled
+    ));
+    assert_eq!(settings.settings_version, config::CURRENT_SETTINGS_VERSION);
+}
+
+#[tokio::test]
+async fn readable_settings_are_kept_when_others_are_broken() {
+    let pool = test_pool().await;
+    store_settings(
+        &pool,
+        &[
+            ("settings_version", "3"),
+            ("gym_name", r#""Iron Temple""#),
+            ("gym_code", r#""IRON000003""#),
+            ("language", "5"),
+            ("login_lockout", r#""lock forever""#),
+            ("password_policy", r#"{"min_length": 12}"#),
+            ("theme", "not json"),
+        ],
+    )
+    .await;
+
+    let settings = config::load_stored_settings(&pool).await.unwrap().unwrap();
+    assert_eq!(settings.gym_name, "Iron Temple");
+    assert_eq!(settings.gym_code, "IRON000003");
+    assert_eq!(settings.password_policy.min_length, 12);
+    assert_eq!(settings.language, "en");
+    assert_eq!(settings.theme, "light");
+    assert_eq!(settings.login_lockout.max_failed_attempts, 5);
+
+    // The salvaged settings replace the broken values.
+    assert_eq!(stored_setting(&pool, "language").await, r#""en""#);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&stored_setting(&pool, "login_lockout").await)
+            .unwrap(),
+        serde_json::to_value(&settings.login_lockout).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn an_invalid_settings_version_is_salvaged() {
+    let pool = test_pool().await;
+    store_settings(
+        &pool,
+        &[
+            ("settings_version", r#""three""#),
+            ("gym_code", r#""IRON000004""#),
+        ],
+    )
+    .await;
+
+    let settings = config::load_stored_settings(&pool).await.unwrap().unwrap();
+    assert_eq!(settings.settings_version, config::CURRENT_SETTINGS_VERSION);
+    assert_eq!(settings.gym_code, "IRON000004");
+    assert_eq!(
+        stored_setting(&pool, "settings_version").await,
+        config::CURRENT_SETTINGS_VERSION.to_string()
+    );
+}
@@ -47,7 +47,13 @@ function createAuthStore() {
 				return false;
 			}
 		},
-		logout: () => {
+		logout: async () => {
+			const { invoke } = await import('@tauri-apps/api/core');
+			try {
+				await invoke('logout');
+			} catch (e) {
+				console.error('Logout error:', e);
+			}
 			set({ isAuthenticated: false, role: null, username: null, error: null });
 		},
 		clearError: () => {